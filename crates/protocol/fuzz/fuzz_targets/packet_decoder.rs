@@ -0,0 +1,32 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use protocol::{packets, PacketDecoder};
+
+// The first byte picks the compression threshold and how the rest of the input
+// is split into reads, the rest is what the server sent.
+fuzz_target!(|data: &[u8]| {
+    let Some((&mode, data)) = data.split_first() else {
+        return;
+    };
+
+    let mut decoder = PacketDecoder::new();
+    if mode & 0x80 != 0 {
+        decoder.set_compression((mode & 0x7f) as i32);
+    }
+
+    let chunk_len = (mode & 0x0f) as usize + 1;
+    for chunk in data.chunks(chunk_len) {
+        decoder.queue_slice(chunk);
+
+        loop {
+            match decoder.try_next_packet() {
+                Ok(Some(frame)) => {
+                    packets::decode_all(&frame);
+                }
+                Ok(None) => break,
+                Err(_) => return,
+            }
+        }
+    }
+});
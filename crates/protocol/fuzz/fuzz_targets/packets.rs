@@ -0,0 +1,18 @@
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use protocol::{packets, Decode, PacketFrame, VarInt};
+
+// Skips the framing and hands the input straight to the packet decoders.
+fuzz_target!(|data: &[u8]| {
+    let mut rdr = data;
+    let Ok(VarInt(id)) = VarInt::decode(&mut rdr) else {
+        return;
+    };
+
+    packets::decode_all(&PacketFrame {
+        id,
+        body: BytesMut::from(rdr),
+    });
+});
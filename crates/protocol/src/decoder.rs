@@ -1,12 +1,16 @@
 #![allow(dead_code)]
 use std::fmt;
+use std::io::Read;
 
 use anyhow::{ensure, Context};
 use bevy::prelude::*;
 use bevy_inspector_egui::prelude::*;
 use bytes::{Buf, BytesMut};
+use cfb8::cipher::generic_array::GenericArray;
+use cfb8::cipher::{BlockDecryptMut, BlockSizeUser, KeyIvInit};
+use flate2::bufread::ZlibDecoder;
 
-use crate::{Decode, Packet, VarInt, MAX_PACKET_SIZE};
+use crate::{Decode, Packet, VarInt, MAX_DATA_LEN, MAX_PACKET_SIZE};
 
 type Cipher = cfb8::Decryptor<aes::Aes128>;
 
@@ -44,7 +48,7 @@ impl PacketDecoder {
             Err(_) => return Ok(None),
         };
         ensure!(
-            pkt_len <= MAX_PACKET_SIZE,
+            (0..=MAX_PACKET_SIZE).contains(&pkt_len),
             "packet length of {pkt_len} is out of bounds"
         );
 
@@ -54,10 +58,44 @@ impl PacketDecoder {
 
         let pkt_len_size = VarInt(pkt_len).size();
 
-        // TODO: Add decompression
+        let mut data;
+        if self.threshold >= 0 {
+            rdr = &rdr[..pkt_len as usize];
 
-        self.buf.advance(pkt_len_size);
-        let mut data = self.buf.split_to(pkt_len as usize);
+            let data_len = VarInt::decode(&mut rdr)
+                .context("failed to decode data length")?
+                .0;
+            ensure!(
+                (0..=MAX_DATA_LEN as i32).contains(&data_len),
+                "decompressed packet length of {data_len} is out of bounds"
+            );
+
+            if data_len > 0 {
+                self.decompress_buf.clear();
+                self.decompress_buf.resize(data_len as usize, 0);
+
+                let mut z = ZlibDecoder::new(rdr);
+                z.read_exact(&mut self.decompress_buf)
+                    .context("failed to decompress packet")?;
+
+                ensure!(
+                    z.total_in() as usize == rdr.len(),
+                    "decompressed packet length is shorter than expected"
+                );
+
+                self.buf.advance(pkt_len_size + pkt_len as usize);
+                data = BytesMut::from(&self.decompress_buf[..]);
+            } else {
+                let remaining_len = rdr.len();
+                let data_len_size = pkt_len as usize - remaining_len;
+
+                self.buf.advance(pkt_len_size + data_len_size);
+                data = self.buf.split_to(remaining_len);
+            }
+        } else {
+            self.buf.advance(pkt_len_size);
+            data = self.buf.split_to(pkt_len as usize);
+        }
 
         rdr = &data[..];
         let pkt_id = VarInt::decode(&mut rdr)
@@ -72,12 +110,52 @@ impl PacketDecoder {
         }))
     }
 
-    pub fn queue_bytes(&mut self, bytes: BytesMut) {
+    /// Enables decompression of packets framed with a data length prefix. A
+    /// negative threshold disables compression.
+    pub fn set_compression(&mut self, threshold: i32) {
+        self.threshold = threshold;
+    }
+
+    pub fn compression(&self) -> i32 {
+        self.threshold
+    }
+
+    /// Enables AES/CFB8 decryption with the shared secret as both key and IV.
+    /// Bytes that were queued before this call are decrypted as well, since
+    /// they may already contain data sent after our `EncryptionResponse`.
+    pub fn enable_encryption(&mut self, key: &[u8; 16]) {
+        assert!(self.cipher.is_none(), "encryption is already enabled");
+
+        let mut cipher = Cipher::new_from_slices(key, key).expect("invalid key");
+        Self::decrypt_bytes(&mut cipher, &mut self.buf);
+        self.cipher = Some(cipher);
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    fn decrypt_bytes(cipher: &mut Cipher, bytes: &mut [u8]) {
+        for chunk in bytes.chunks_mut(Cipher::block_size()) {
+            cipher.decrypt_block_mut(GenericArray::from_mut_slice(chunk));
+        }
+    }
+
+    pub fn queue_bytes(&mut self, mut bytes: BytesMut) {
+        if let Some(cipher) = &mut self.cipher {
+            Self::decrypt_bytes(cipher, &mut bytes);
+        }
+
         self.buf.unsplit(bytes);
     }
 
     pub fn queue_slice(&mut self, bytes: &[u8]) {
-        self.buf.extend_from_slice(bytes)
+        let start_len = self.buf.len();
+        self.buf.extend_from_slice(bytes);
+
+        if let Some(cipher) = &mut self.cipher {
+            Self::decrypt_bytes(cipher, &mut self.buf[start_len..]);
+        }
     }
 }
 
@@ -122,3 +200,107 @@ impl fmt::Debug for PacketFrame {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::{ClientKeepAlivePlay, ClientPluginMessageConfiguration};
+    use crate::{Bounded, Encode, PacketEncoder, RawBytes};
+
+    fn encode_decode(threshold: i32) {
+        let payload = vec![7; 1024];
+        let mut encoder = PacketEncoder::new();
+        let mut decoder = PacketDecoder::new();
+        encoder.set_compression(threshold);
+        decoder.set_compression(threshold);
+
+        encoder
+            .append_packet(&ClientKeepAlivePlay { id: 0x1122334455 })
+            .unwrap();
+        encoder
+            .append_packet(&ClientPluginMessageConfiguration {
+                channel: "minecraft:brand",
+                data: Bounded(RawBytes(&payload)),
+            })
+            .unwrap();
+
+        let bytes = encoder.take();
+        let (first, second) = bytes.split_at(3);
+        decoder.queue_slice(first);
+        assert!(decoder.try_next_packet().unwrap().is_none());
+        decoder.queue_slice(second);
+
+        let frame = decoder.try_next_packet().unwrap().unwrap();
+        let pkt = frame.decode::<ClientKeepAlivePlay>().unwrap();
+        assert_eq!(0x1122334455, pkt.id);
+
+        let frame = decoder.try_next_packet().unwrap().unwrap();
+        let pkt = frame.decode::<ClientPluginMessageConfiguration>().unwrap();
+        assert_eq!("minecraft:brand", pkt.channel);
+        assert_eq!(&payload[..], pkt.data.0 .0);
+
+        assert!(decoder.try_next_packet().unwrap().is_none());
+    }
+
+    #[test]
+    fn uncompressed() {
+        encode_decode(-1);
+    }
+
+    #[test]
+    fn compressed() {
+        encode_decode(256);
+    }
+
+    #[test]
+    fn encrypted() {
+        const KEY: [u8; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+        // AES-128/CFB8 of `09 24 00 00 00 11 22 33 44 55` with KEY as key and IV.
+        const EXPECTED: [u8; 10] = [0x03, 0x98, 0xf3, 0x15, 0x0d, 0x48, 0x05, 0x87, 0xce, 0xd0];
+
+        let mut encoder = PacketEncoder::new();
+        encoder
+            .append_packet(&ClientKeepAlivePlay { id: 0x7f })
+            .unwrap();
+        encoder.enable_encryption(&KEY);
+        encoder
+            .append_packet(&ClientKeepAlivePlay { id: 0x1122334455 })
+            .unwrap();
+
+        let bytes = encoder.take();
+        let (plain, encrypted) = bytes.split_at(10);
+        assert_eq!(&EXPECTED, encrypted);
+
+        let mut decoder = PacketDecoder::new();
+        decoder.queue_slice(plain);
+        decoder.queue_slice(&encrypted[..4]);
+
+        let frame = decoder.try_next_packet().unwrap().unwrap();
+        assert_eq!(0x7f, frame.decode::<ClientKeepAlivePlay>().unwrap().id);
+
+        decoder.enable_encryption(&KEY);
+        assert!(decoder.try_next_packet().unwrap().is_none());
+        decoder.queue_slice(&encrypted[4..]);
+
+        let frame = decoder.try_next_packet().unwrap().unwrap();
+        assert_eq!(
+            0x1122334455,
+            frame.decode::<ClientKeepAlivePlay>().unwrap().id
+        );
+    }
+
+    #[test]
+    fn compressed_too_large() {
+        let mut decoder = PacketDecoder::new();
+        decoder.set_compression(256);
+
+        let mut buf = BytesMut::new();
+        let data_len = VarInt(MAX_DATA_LEN as i32 + 1);
+        VarInt(data_len.size() as i32 + 1).encode(&mut buf).unwrap();
+        data_len.encode(&mut buf).unwrap();
+        buf.extend_from_slice(&[0]);
+        decoder.queue_bytes(buf);
+
+        assert!(decoder.try_next_packet().is_err());
+    }
+}
@@ -1,8 +1,14 @@
 #![allow(dead_code)]
+use std::io::Read;
+
 use anyhow::ensure;
 use bevy::prelude::*;
 use bevy_inspector_egui::prelude::*;
 use bytes::{BufMut, BytesMut};
+use cfb8::cipher::generic_array::GenericArray;
+use cfb8::cipher::{BlockEncryptMut, BlockSizeUser, KeyIvInit};
+use flate2::bufread::ZlibEncoder;
+use flate2::Compression;
 
 use crate::{Encode, Packet, VarInt, MAX_PACKET_SIZE};
 
@@ -43,14 +49,75 @@ impl PacketEncoder {
     }
 
     pub fn append_packet<P>(&mut self, pkt: &P) -> anyhow::Result<()>
+    where
+        P: Packet + Encode,
+    {
+        let start_len = self.buf.len();
+        self.append_frame(pkt)?;
+
+        if let Some(cipher) = &mut self.cipher {
+            Self::encrypt_bytes(cipher, &mut self.buf[start_len..]);
+        }
+
+        Ok(())
+    }
+
+    fn append_frame<P>(&mut self, pkt: &P) -> anyhow::Result<()>
     where
         P: Packet + Encode,
     {
         let start_len = self.buf.len();
         pkt.encode_with_id(&mut self.buf)?;
-        let pkt_len = self.buf.len() - start_len;
+        let data_len = self.buf.len() - start_len;
+
+        if self.threshold >= 0 {
+            if data_len > self.threshold as usize {
+                let mut z = ZlibEncoder::new(&self.buf[start_len..], Compression::default());
+
+                self.compress_buf.clear();
+
+                let data_len_size = VarInt(data_len as i32).size();
+                let pkt_len = data_len_size + z.read_to_end(&mut self.compress_buf)?;
+
+                ensure!(
+                    pkt_len <= MAX_PACKET_SIZE as usize,
+                    "packet exceeds maximum length"
+                );
+
+                drop(z);
+
+                self.buf.truncate(start_len);
+
+                VarInt(pkt_len as i32).encode(&mut self.buf)?;
+                VarInt(data_len as i32).encode(&mut self.buf)?;
+                self.buf.extend_from_slice(&self.compress_buf);
+            } else {
+                // A data length of zero marks the packet as uncompressed.
+                let data_len_size = 1;
+                let pkt_len = data_len_size + data_len;
 
-        // TODO: Add compression
+                ensure!(
+                    pkt_len <= MAX_PACKET_SIZE as usize,
+                    "packet exceeds maximum length"
+                );
+
+                let pkt_len_size = VarInt(pkt_len as i32).size();
+                let prefix_len = pkt_len_size + data_len_size;
+
+                self.buf.put_bytes(0, prefix_len);
+                self.buf
+                    .copy_within(start_len..start_len + data_len, start_len + prefix_len);
+
+                unsafe { self.buf.set_len(start_len) };
+                VarInt(pkt_len as i32).encode(&mut self.buf)?;
+                VarInt(0).encode(&mut self.buf)?;
+                unsafe { self.buf.set_len(start_len + prefix_len + data_len) }
+            }
+
+            return Ok(());
+        }
+
+        let pkt_len = data_len;
 
         ensure!(
             pkt_len <= MAX_PACKET_SIZE as usize,
@@ -71,6 +138,36 @@ impl PacketEncoder {
         Ok(())
     }
 
+    /// Enables compression for every packet appended after this call. Packets
+    /// whose data is larger than `threshold` bytes are zlib compressed, a
+    /// negative threshold disables compression.
+    pub fn set_compression(&mut self, threshold: i32) {
+        self.threshold = threshold;
+    }
+
+    pub fn compression(&self) -> i32 {
+        self.threshold
+    }
+
+    /// Enables AES/CFB8 encryption with the shared secret as both key and IV.
+    /// Bytes that are already queued stay unencrypted, since the server only
+    /// expects encrypted data after our `EncryptionResponse`.
+    pub fn enable_encryption(&mut self, key: &[u8; 16]) {
+        assert!(self.cipher.is_none(), "encryption is already enabled");
+
+        self.cipher = Some(Cipher::new_from_slices(key, key).expect("invalid key"));
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    fn encrypt_bytes(cipher: &mut Cipher, bytes: &mut [u8]) {
+        for chunk in bytes.chunks_mut(Cipher::block_size()) {
+            cipher.encrypt_block_mut(GenericArray::from_mut_slice(chunk));
+        }
+    }
+
     pub fn take(&mut self) -> BytesMut {
         self.buf.split()
     }
@@ -63,7 +63,9 @@ impl<'a, T: Decode<'a>> Decode<'a> for Vec<T> {
         ensure!(len >= 0, "attempt to decode Vec with negative length");
         let len = len as usize;
 
-        let mut vec = Vec::<T>::with_capacity(len);
+        // Every element takes at least a byte, except for zero sized ones, so
+        // don't trust the length prefix with the allocation.
+        let mut vec = Vec::<T>::with_capacity(len.min(rdr.len()));
 
         for _ in 0..len {
             vec.push(T::decode(rdr)?);
@@ -73,6 +75,7 @@ impl<'a, T: Decode<'a>> Decode<'a> for Vec<T> {
     }
 }
 
+#[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deref, DerefMut, AsRef, From)]
 pub struct LenPrefixed<T>(pub Vec<T>);
 
@@ -90,9 +93,14 @@ impl<T: Encode> Encode for LenPrefixed<T> {
 
 impl<'a, T: Decode<'a>> Decode<'a> for LenPrefixed<T> {
     fn decode(rdr: &mut &'a [u8]) -> anyhow::Result<Self> {
-        let len = VarInt::decode(rdr)?.0 as usize;
+        let len = VarInt::decode(rdr)?.0;
+        ensure!(
+            len >= 0,
+            "attempt to decode LenPrefixed with negative length"
+        );
+        let len = len as usize;
 
-        let mut vec = Vec::<T>::with_capacity(len);
+        let mut vec = Vec::<T>::with_capacity(len.min(rdr.len()));
         for _ in 0..len {
             vec.push(Decode::decode(rdr)?)
         }
@@ -103,8 +111,26 @@ impl<'a, T: Decode<'a>> Decode<'a> for LenPrefixed<T> {
 
 #[cfg(test)]
 mod tests {
+    use proptest::prelude::*;
+
     use super::*;
 
+    proptest! {
+        #[test]
+        fn container_round_trip(
+            a: Option<i64>,
+            b: Vec<String>,
+            c: [u16; 4],
+            d: Vec<Option<Vec<u8>>>,
+        ) {
+            assert_round_trip!(Option<i64>, a);
+            assert_round_trip!(Vec<String>, b.clone());
+            assert_round_trip!([u16; 4], c);
+            assert_round_trip!(Vec<Option<Vec<u8>>>, d);
+            assert_round_trip!(LenPrefixed<String>, LenPrefixed(b));
+        }
+    }
+
     #[test]
     fn container_encode_decode() {
         let a = Some("aaa".to_string());
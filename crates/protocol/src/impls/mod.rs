@@ -20,3 +20,15 @@ pub use string::*;
 #[allow(unused_imports)]
 pub use tuple::*;
 pub use varnum::*;
+
+/// Fails with a descriptive error instead of letting a `bytes::Buf` getter
+/// panic when fewer than `len` bytes are left to decode `what`.
+pub(crate) fn ensure_remaining(rdr: &[u8], len: usize, what: &str) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        rdr.len() >= len,
+        "unexpected end of input while decoding {what} (expected {len} bytes, got {})",
+        rdr.len()
+    );
+
+    Ok(())
+}
@@ -27,3 +27,17 @@ impl<'a, T> Decode<'a> for PhantomData<&'a T> {
         Ok(PhantomData {})
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn uuid_round_trip(a: u128) {
+            assert_round_trip!(Uuid, Uuid::from_u128(a));
+        }
+    }
+}
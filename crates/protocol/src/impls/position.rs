@@ -1,6 +1,7 @@
 use bytes::{Buf, BufMut, BytesMut};
 use derive_more::From;
 
+use super::ensure_remaining;
 use crate::{Decode, Encode};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, From)]
@@ -23,7 +24,9 @@ impl Encode for Position {
 
 impl<'a> Decode<'a> for Position {
     fn decode(rdr: &mut &'a [u8]) -> anyhow::Result<Self> {
-        let val = rdr.get_u64();
+        ensure_remaining(rdr, 8, "Position")?;
+        // Signed shifts, so negative coordinates are sign extended.
+        let val = rdr.get_i64();
         Ok(Self {
             x: (val >> 38) as i32,
             y: (val << 52 >> 52) as i32,
@@ -31,3 +34,32 @@ impl<'a> Decode<'a> for Position {
         })
     }
 }
+
+#[cfg(any(test, feature = "arbitrary"))]
+impl<'a> arbitrary::Arbitrary<'a> for Position {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Self {
+            x: u.int_in_range(-(1 << 25)..=(1 << 25) - 1)?,
+            y: u.int_in_range(-(1 << 11)..=(1 << 11) - 1)?,
+            z: u.int_in_range(-(1 << 25)..=(1 << 25) - 1)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn position_round_trip(
+            x in -(1 << 25)..(1 << 25),
+            y in -(1 << 11)..(1 << 11),
+            z in -(1 << 25)..(1 << 25),
+        ) {
+            assert_round_trip!(Position, Position { x, y, z });
+        }
+    }
+}
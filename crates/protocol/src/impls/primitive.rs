@@ -1,5 +1,8 @@
+use std::mem;
+
 use bytes::{Buf, BufMut, BytesMut};
 
+use super::ensure_remaining;
 use crate::{Decode, Encode};
 
 impl Encode for bool {
@@ -11,6 +14,7 @@ impl Encode for bool {
 
 impl Decode<'_> for bool {
     fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        ensure_remaining(buf, 1, "bool")?;
         Ok(buf.get_u8() != 0)
     }
 }
@@ -26,6 +30,7 @@ macro_rules! define_primitive {
 
         impl Decode<'_> for $type {
             fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
+                ensure_remaining(buf, mem::size_of::<$type>(), stringify!($type))?;
                 Ok(buf.$read())
             }
         }
@@ -47,8 +52,35 @@ define_primitive!(f64, get_f64, put_f64);
 
 #[cfg(test)]
 mod tests {
+    use proptest::prelude::*;
+
     use super::*;
 
+    proptest! {
+        #[test]
+        fn integer_round_trip(
+            a: u8, b: i8, c: u16, d: i16, e: u32, f: i32, g: u64, h: i64, i: u128, j: i128,
+        ) {
+            assert_round_trip!(u8, a);
+            assert_round_trip!(i8, b);
+            assert_round_trip!(u16, c);
+            assert_round_trip!(i16, d);
+            assert_round_trip!(u32, e);
+            assert_round_trip!(i32, f);
+            assert_round_trip!(u64, g);
+            assert_round_trip!(i64, h);
+            assert_round_trip!(u128, i);
+            assert_round_trip!(i128, j);
+        }
+
+        #[test]
+        fn bool_float_round_trip(a: bool, b: f32, c: f64) {
+            assert_round_trip!(bool, a);
+            assert_round_trip!(f32, b);
+            assert_round_trip!(f64, c);
+        }
+    }
+
     #[test]
     fn primitive_encode_decode() {
         let a = true;
@@ -66,4 +98,12 @@ mod tests {
         assert_eq!(0x1122, u16::decode(&mut buf).unwrap());
         assert_eq!(0x33441122, u32::decode(&mut buf).unwrap());
     }
+
+    #[test]
+    fn primitive_truncated() {
+        assert!(bool::decode(&mut &[][..]).is_err());
+        assert!(i32::decode(&mut &[0, 0, 0][..]).is_err());
+        assert!(f64::decode(&mut &[0; 7][..]).is_err());
+        assert!(u128::decode(&mut &[0; 15][..]).is_err());
+    }
 }
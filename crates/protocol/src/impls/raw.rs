@@ -6,6 +6,7 @@ use derive_more::{AsRef, Deref, DerefMut, From};
 
 use crate::{Bounded, Decode, Encode};
 
+#[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deref, DerefMut, AsRef, From)]
 pub struct RawBytes<'a>(pub &'a [u8]);
 
@@ -44,3 +45,35 @@ impl<'a, const MAX_BYTES: usize> Decode<'a> for Bounded<RawBytes<'a>, MAX_BYTES>
         Ok(Bounded(RawBytes::decode(r)?))
     }
 }
+
+#[cfg(any(test, feature = "arbitrary"))]
+impl<'a, const MAX_BYTES: usize> arbitrary::Arbitrary<'a> for Bounded<RawBytes<'a>, MAX_BYTES> {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let bytes = <&[u8]>::arbitrary(u)?;
+        Ok(Bounded(RawBytes(&bytes[..bytes.len().min(MAX_BYTES)])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn raw_round_trip(a: Vec<u8>) {
+            assert_round_trip!(RawBytes, RawBytes(&a));
+            assert_round_trip!(Bounded<RawBytes, 1024>, Bounded::<_, 1024>(RawBytes(&a)));
+        }
+    }
+
+    #[test]
+    fn bounded_raw_too_long() {
+        let mut buf = BytesMut::new();
+        assert!(Bounded::<_, 2>(RawBytes(&[1, 2, 3]))
+            .encode(&mut buf)
+            .is_err());
+        assert!(Bounded::<RawBytes, 2>::decode(&mut &[1, 2, 3][..]).is_err());
+    }
+}
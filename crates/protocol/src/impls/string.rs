@@ -22,14 +22,15 @@ impl<'a> Decode<'a> for &'a str {
 
 impl<const MAX: usize> Encode for Bounded<&'_ str, MAX> {
     fn encode(&self, wtr: &mut BytesMut) -> anyhow::Result<()> {
-        let len = self.encode_utf16().count();
+        let char_count = self.encode_utf16().count();
 
         ensure!(
-            len <= MAX,
-            "string len exceeds maximum (expected <= {MAX}, got {len})"
+            char_count <= MAX,
+            "char count of string exceeds maximum (expected <= {MAX}, got {char_count})"
         );
 
-        VarInt(len as i32).encode(wtr)?;
+        // The length prefix counts bytes, the maximum counts UTF-16 chars.
+        VarInt(self.len() as i32).encode(wtr)?;
         wtr.write_str(self)?;
 
         Ok(())
@@ -86,10 +87,48 @@ impl<const MAX: usize> Decode<'_> for Bounded<String, MAX> {
     }
 }
 
+#[cfg(any(test, feature = "arbitrary"))]
+impl<'a, const MAX: usize> arbitrary::Arbitrary<'a> for Bounded<&'a str, MAX> {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let s = <&str>::arbitrary(u)?;
+
+        // Cut before the first char that doesn't fit, counting like the encoder.
+        let mut len = 0;
+        let end = s
+            .char_indices()
+            .find_map(|(i, c)| {
+                len += c.len_utf16();
+                (len > MAX).then_some(i)
+            })
+            .unwrap_or(s.len());
+
+        Ok(Bounded(&s[..end]))
+    }
+}
+
+#[cfg(any(test, feature = "arbitrary"))]
+impl<'a, const MAX: usize> arbitrary::Arbitrary<'a> for Bounded<String, MAX> {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Bounded::<&str, MAX>::arbitrary(u)?.map_into())
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use proptest::prelude::*;
+
     use super::*;
 
+    proptest! {
+        #[test]
+        fn string_round_trip(a: String, b in "\\PC{0,8}") {
+            assert_round_trip!(String, a.clone());
+            assert_round_trip!(&str, a.as_str());
+            assert_round_trip!(Bounded<&str, 16>, Bounded::<_, 16>(b.as_str()));
+            assert_round_trip!(Bounded<String, 16>, Bounded::<_, 16>(b));
+        }
+    }
+
     #[test]
     fn string() {
         let a = "str";
@@ -36,3 +36,17 @@ impl_tuple!(A B C D E F G H I);
 impl_tuple!(A B C D E F G H I J);
 impl_tuple!(A B C D E F G H I J K);
 impl_tuple!(A B C D E F G H I J K L);
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn tuple_round_trip(a: (u8, i32), b: (bool, String, Option<u16>)) {
+            assert_round_trip!((), ());
+            assert_round_trip!((u8, i32), a);
+            assert_round_trip!((bool, String, Option<u16>), b);
+        }
+    }
+}
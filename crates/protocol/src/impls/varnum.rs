@@ -1,5 +1,4 @@
-use anyhow::anyhow;
-use byteorder::ReadBytesExt;
+use anyhow::{anyhow, bail};
 use bytes::{BufMut, BytesMut};
 use derive_more::{Deref, DerefMut, From, Into};
 use mem_macros::size_of;
@@ -8,6 +7,7 @@ use crate::{Decode, Encode};
 
 macro_rules! define_varnum {
     ($name:ident, $type:ty, $container_type:ty, $max_size:literal) => {
+        #[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
         #[derive(
             Debug,
             Default,
@@ -55,7 +55,14 @@ macro_rules! define_varnum {
             fn decode(buf: &mut &[u8]) -> anyhow::Result<Self> {
                 let mut val = 0 as $container_type;
                 for i in 0..$max_size {
-                    let byte = buf.read_u8()?;
+                    let Some((&byte, rest)) = buf.split_first() else {
+                        bail!(
+                            "unexpected end of input while decoding {}",
+                            stringify!($name)
+                        );
+                    };
+                    *buf = rest;
+
                     val |= (byte as $container_type & 0x7F) << (i * 7);
 
                     if byte & 0x80 == 0 {
@@ -74,8 +81,23 @@ define_varnum!(VarLong, i64, u64, 10);
 
 #[cfg(test)]
 mod tests {
+    use proptest::prelude::*;
+
     use super::*;
 
+    proptest! {
+        #[test]
+        fn varnum_round_trip(a: i32, b: i64) {
+            assert_round_trip!(VarInt, VarInt(a));
+            assert_round_trip!(VarLong, VarLong(b));
+            assert_eq!(VarInt(a).size(), {
+                let mut buf = BytesMut::new();
+                VarInt(a).encode(&mut buf).unwrap();
+                buf.len()
+            });
+        }
+    }
+
     #[test]
     fn primitive_encode_decode() {
         let a: i32 = 0x11223344;
@@ -9,10 +9,14 @@ pub mod __private {
     pub use crate::{Decode, Encode, Packet, PacketSide, PacketState, VarInt};
 }
 
+#[cfg(test)]
+#[macro_use]
+mod testing;
+
 mod decoder;
 mod encoder;
 mod impls;
-mod nbt;
+pub mod nbt;
 pub mod packets;
 mod plugin;
 mod registry;
@@ -20,7 +24,7 @@ mod registry;
 pub use decoder::*;
 pub use encoder::*;
 pub use impls::*;
-pub use nbt::*;
+pub use nbt::{Compound, List, NBT};
 pub use plugin::*;
 use protocol_derive::{define_protocol, Decode, Encode, Packet};
 pub use registry::*;
@@ -46,8 +50,9 @@ impl PacketSide {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum PacketState {
+    #[default]
     Handshaking,
     Status,
     Login,
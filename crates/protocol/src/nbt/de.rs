@@ -0,0 +1,414 @@
+use serde::de::{
+    self,
+    value::{BorrowedStrDeserializer, SeqDeserializer},
+    DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess, Visitor,
+};
+use serde::{forward_to_deserialize_any, Deserialize};
+
+use super::{Compound, Error, List, NBT};
+
+/// Deserializes a `T` from `nbt`. Compounds map to structs and maps, lists and
+/// arrays to sequences, and bytes equal to 0 or 1 to `bool`s. Strings can be
+/// borrowed from `nbt`.
+pub fn from_nbt<'de, T: Deserialize<'de>>(nbt: &'de NBT) -> Result<T, Error> {
+    T::deserialize(Deserializer(nbt))
+}
+
+struct Deserializer<'de>(&'de NBT);
+
+impl<'de> de::Deserializer<'de> for Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            NBT::End() => visitor.visit_unit(),
+            NBT::Byte(val) => visitor.visit_i8(*val),
+            NBT::Short(val) => visitor.visit_i16(*val),
+            NBT::Int(val) => visitor.visit_i32(*val),
+            NBT::Long(val) => visitor.visit_i64(*val),
+            NBT::Float(val) => visitor.visit_f32(*val),
+            NBT::Double(val) => visitor.visit_f64(*val),
+            NBT::ByteArray(val) => visitor.visit_borrowed_bytes(val),
+            NBT::String(val) => visitor.visit_borrowed_str(val),
+            NBT::List(val) => visitor.visit_seq(ListAccess::new(val)),
+            NBT::Compound(val) => visitor.visit_map(CompoundAccess::new(val)),
+            NBT::IntArray(val) => visitor.visit_seq(SeqDeserializer::new(val.iter().copied())),
+            NBT::LongArray(val) => visitor.visit_seq(SeqDeserializer::new(val.iter().copied())),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            NBT::Byte(0) => visitor.visit_bool(false),
+            NBT::Byte(1) => visitor.visit_bool(true),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    // `to_nbt` stores unsigned integers as the signed tag of the same size, so
+    // read them back the same way instead of rejecting negative values.
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            NBT::Byte(val) => visitor.visit_u8(*val as u8),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            NBT::Short(val) => visitor.visit_u16(*val as u16),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            NBT::Int(val) => visitor.visit_u32(*val as u32),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            NBT::Long(val) => visitor.visit_u64(*val as u64),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            NBT::End() => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.0 {
+            NBT::String(variant) => visitor.visit_enum(variant.as_str().into_deserializer()),
+            NBT::Compound(val) if val.len() == 1 => {
+                let (variant, value) = val.first().unwrap();
+                visitor
+                    .visit_enum(Enum { variant, value })
+                    .map_err(|e| e.in_key(variant))
+            }
+            _ => Err(de::Error::invalid_type(unexpected(self.0), &visitor)),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+fn unexpected(nbt: &NBT) -> de::Unexpected<'_> {
+    match nbt {
+        NBT::End() => de::Unexpected::Unit,
+        NBT::Byte(val) => de::Unexpected::Signed(*val as i64),
+        NBT::Short(val) => de::Unexpected::Signed(*val as i64),
+        NBT::Int(val) => de::Unexpected::Signed(*val as i64),
+        NBT::Long(val) => de::Unexpected::Signed(*val),
+        NBT::Float(val) => de::Unexpected::Float(*val as f64),
+        NBT::Double(val) => de::Unexpected::Float(*val),
+        NBT::ByteArray(val) => de::Unexpected::Bytes(val),
+        NBT::String(val) => de::Unexpected::Str(val),
+        NBT::List(_) | NBT::IntArray(_) | NBT::LongArray(_) => de::Unexpected::Seq,
+        NBT::Compound(_) => de::Unexpected::Map,
+    }
+}
+
+struct ListAccess<'de> {
+    iter: std::iter::Enumerate<std::slice::Iter<'de, NBT>>,
+    len: usize,
+}
+
+impl<'de> ListAccess<'de> {
+    fn new(list: &'de List) -> Self {
+        Self {
+            iter: list.iter().enumerate(),
+            len: list.len(),
+        }
+    }
+}
+
+impl<'de> SeqAccess<'de> for ListAccess<'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        match self.iter.next() {
+            Some((i, tag)) => seed
+                .deserialize(Deserializer(tag))
+                .map(Some)
+                .map_err(|e| e.in_index(i)),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len)
+    }
+}
+
+struct CompoundAccess<'de> {
+    iter: indexmap::map::Iter<'de, String, NBT>,
+    value: Option<(&'de str, &'de NBT)>,
+}
+
+impl<'de> CompoundAccess<'de> {
+    fn new(compound: &'de Compound) -> Self {
+        Self {
+            iter: compound.iter(),
+            value: None,
+        }
+    }
+}
+
+impl<'de> MapAccess<'de> for CompoundAccess<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.value = Some((key, value));
+                seed.deserialize(BorrowedStrDeserializer::new(key))
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let (key, value) = self
+            .value
+            .take()
+            .ok_or_else(|| Error::new("value requested before key"))?;
+
+        seed.deserialize(Deserializer(value))
+            .map_err(|e| e.in_key(key))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct Enum<'de> {
+    variant: &'de str,
+    value: &'de NBT,
+}
+
+impl<'de> EnumAccess<'de> for Enum<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Enum<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(Deserializer(self.value))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_seq(Deserializer(self.value), visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_map(Deserializer(self.value), visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Read;
+
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Food {
+        name: String,
+        value: f32,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Entry<'a> {
+        name: &'a str,
+        #[serde(rename = "created-on")]
+        created_on: i64,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Level<'a> {
+        #[serde(rename = "nested compound test")]
+        nested: HashMap<String, Food>,
+        #[serde(rename = "listTest (long)")]
+        longs: Vec<i64>,
+        #[serde(rename = "listTest (compound)", borrow)]
+        entries: Vec<Entry<'a>>,
+        #[serde(rename = "byteTest")]
+        byte: i8,
+        #[serde(rename = "shortTest")]
+        short: i16,
+        #[serde(rename = "intTest")]
+        int: i32,
+        #[serde(rename = "longTest")]
+        long: i64,
+        #[serde(rename = "doubleTest")]
+        double: f64,
+        #[serde(rename = "stringTest")]
+        string: &'a str,
+        #[serde(
+            rename = "byteArrayTest (the first 1000 values of (n*n*255+n*7)%100, starting with n=0 (0, 62, 34, 16, 8, ...))",
+            with = "serde_bytes"
+        )]
+        bytes: &'a [u8],
+    }
+
+    #[test]
+    fn bigtest() {
+        let mut data = vec![];
+        flate2::read::GzDecoder::new(include_bytes!("../testdata/bigtest.nbt").as_slice())
+            .read_to_end(&mut data)
+            .unwrap();
+        let (_, tag) = NBT::read_named(&mut data.as_slice()).unwrap();
+
+        let level: Level = from_nbt(&tag).unwrap();
+
+        assert_eq!(
+            level.nested["egg"],
+            Food {
+                name: "Eggbert".to_owned(),
+                value: 0.5,
+            }
+        );
+        assert_eq!(level.longs, [11, 12, 13, 14, 15]);
+        assert_eq!(
+            level.entries[0],
+            Entry {
+                name: "Compound tag #0",
+                created_on: 1264099775885,
+            }
+        );
+        assert_eq!(level.byte, 127);
+        assert_eq!(level.short, i16::MAX);
+        assert_eq!(level.int, i32::MAX);
+        assert_eq!(level.long, i64::MAX);
+        assert_eq!(level.double, 0.4931287132182315);
+        assert_eq!(
+            level.string,
+            "HELLO WORLD THIS IS A TEST STRING \u{c5}\u{c4}\u{d6}!"
+        );
+        assert_eq!(level.bytes.len(), 1000);
+    }
+
+    #[test]
+    fn arrays() {
+        #[derive(Deserialize)]
+        struct Heightmaps {
+            #[serde(rename = "MOTION_BLOCKING")]
+            motion_blocking: Vec<i64>,
+            #[serde(default)]
+            sections: Vec<i32>,
+        }
+
+        let mut compound = Compound::new();
+        compound.insert("MOTION_BLOCKING".to_owned(), NBT::LongArray(vec![1, -2]));
+        compound.insert("unknown".to_owned(), NBT::IntArray(vec![3]));
+
+        let heightmaps: Heightmaps = from_nbt(&NBT::Compound(compound)).unwrap();
+        assert_eq!(heightmaps.motion_blocking, [1, -2]);
+        assert!(heightmaps.sections.is_empty());
+    }
+
+    #[test]
+    fn error_path() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Point {
+            x: i32,
+        }
+
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Root {
+            nested: Vec<Point>,
+        }
+
+        let mut point = Compound::new();
+        point.insert("x".to_owned(), NBT::String("one".to_owned()));
+        let mut nested = List::new();
+        nested.push(NBT::Compound(point));
+        let mut root = Compound::new();
+        root.insert("nested".to_owned(), NBT::List(nested));
+
+        let err = from_nbt::<Root>(&NBT::Compound(root)).unwrap_err();
+        assert_eq!(err.path(), "nested[0].x");
+        assert!(err.to_string().ends_with("at `nested[0].x`"), "{err}");
+    }
+
+    #[test]
+    fn enums_and_bools() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        enum Shape {
+            Empty,
+            Circle(f32),
+            Rect { w: i32, h: i32 },
+        }
+
+        let empty = NBT::String("Empty".to_owned());
+        assert_eq!(from_nbt::<Shape>(&empty).unwrap(), Shape::Empty);
+
+        let mut circle = Compound::new();
+        circle.insert("Circle".to_owned(), NBT::Float(2.0));
+        assert_eq!(
+            from_nbt::<Shape>(&NBT::Compound(circle)).unwrap(),
+            Shape::Circle(2.0)
+        );
+
+        let mut fields = Compound::new();
+        fields.insert("w".to_owned(), NBT::Int(1));
+        let mut rect = Compound::new();
+        rect.insert("Rect".to_owned(), NBT::Compound(fields));
+        let err = from_nbt::<Shape>(&NBT::Compound(rect)).unwrap_err();
+        assert_eq!(err.path(), "Rect");
+
+        assert!(from_nbt::<bool>(&NBT::Byte(1)).unwrap());
+        assert!(from_nbt::<bool>(&NBT::Byte(2)).is_err());
+    }
+}
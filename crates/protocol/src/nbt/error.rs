@@ -0,0 +1,67 @@
+use std::fmt::{self, Display};
+
+/// Error from converting between `NBT` and Rust values, with the path to the
+/// tag it happened at, like `Level.listTest[2].name`.
+#[derive(Debug)]
+pub struct Error {
+    path: String,
+    message: String,
+}
+
+impl Error {
+    pub(super) fn new(message: impl Display) -> Self {
+        Self {
+            path: String::new(),
+            message: message.to_string(),
+        }
+    }
+
+    /// Path of the tag the error happened at, empty for the root tag.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub(super) fn in_key(self, key: &str) -> Self {
+        self.prepend(key)
+    }
+
+    pub(super) fn in_index(self, index: usize) -> Self {
+        self.prepend(&format!("[{index}]"))
+    }
+
+    fn prepend(mut self, segment: &str) -> Self {
+        if !self.path.is_empty() && !self.path.starts_with('[') {
+            self.path.insert(0, '.');
+        }
+        self.path.insert_str(0, segment);
+        self
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{} at `{}`", self.message, self.path)
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl serde::de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::new(msg)
+    }
+}
+
+impl serde::ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::new(msg)
+    }
+}
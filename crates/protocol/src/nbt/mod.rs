@@ -0,0 +1,613 @@
+use anyhow::ensure;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use derive_more::{Deref, DerefMut, From, TryInto};
+use indexmap::IndexMap;
+
+use crate::{impls::ensure_remaining, Decode, Encode};
+
+mod de;
+mod error;
+mod ser;
+mod snbt;
+
+pub use de::from_nbt;
+pub use error::Error;
+pub use ser::{int_array, long_array, to_nbt};
+
+/// Maximum nesting of lists and compounds, the same limit the vanilla client
+/// uses.
+const MAX_DEPTH: usize = 512;
+
+#[derive(Debug, Clone, PartialEq, From, TryInto)]
+#[try_into(owned, ref, ref_mut)]
+#[repr(u8)]
+pub enum NBT {
+    End(),
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Bytes),
+    String(String),
+    List(List),
+    Compound(Compound),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl NBT {
+    fn id(&self) -> u8 {
+        match self {
+            NBT::End() => 0,
+            NBT::Byte(_) => 1,
+            NBT::Short(_) => 2,
+            NBT::Int(_) => 3,
+            NBT::Long(_) => 4,
+            NBT::Float(_) => 5,
+            NBT::Double(_) => 6,
+            NBT::ByteArray(_) => 7,
+            NBT::String(_) => 8,
+            NBT::List(_) => 9,
+            NBT::Compound(_) => 10,
+            NBT::IntArray(_) => 11,
+            NBT::LongArray(_) => 12,
+        }
+    }
+
+    /// Reads a root tag in the named format used by files like `level.dat`,
+    /// its ID followed by its name and payload. A root end tag has no name.
+    pub fn read_named(rdr: &mut &[u8]) -> anyhow::Result<(String, NBT)> {
+        let id = u8::decode(rdr)?;
+        if id == 0 {
+            return Ok((String::new(), NBT::End()));
+        }
+
+        let name = decode_string(rdr)?;
+        let tag = NBT::decode_payload(rdr, id, 0)?;
+        Ok((name, tag))
+    }
+
+    pub fn write_named(&self, name: &str, wtr: &mut BytesMut) -> anyhow::Result<()> {
+        wtr.put_u8(self.id());
+        if let NBT::End() = self {
+            return Ok(());
+        }
+
+        encode_string(name, wtr)?;
+        self.encode_payload(wtr)
+    }
+
+    /// Reads a root tag in the nameless format used by packets since 1.20.2,
+    /// its ID followed by its payload.
+    pub fn read_network(rdr: &mut &[u8]) -> anyhow::Result<NBT> {
+        let id = u8::decode(rdr)?;
+        NBT::decode_payload(rdr, id, 0)
+    }
+
+    pub fn write_network(&self, wtr: &mut BytesMut) -> anyhow::Result<()> {
+        wtr.put_u8(self.id());
+        self.encode_payload(wtr)
+    }
+
+    fn encode_payload(&self, wtr: &mut BytesMut) -> anyhow::Result<()> {
+        match self {
+            NBT::End() => {}
+            NBT::Byte(val) => wtr.put_i8(*val),
+            NBT::Short(val) => wtr.put_i16(*val),
+            NBT::Int(val) => wtr.put_i32(*val),
+            NBT::Long(val) => wtr.put_i64(*val),
+            NBT::Float(val) => wtr.put_f32(*val),
+            NBT::Double(val) => wtr.put_f64(*val),
+            NBT::ByteArray(val) => {
+                wtr.put_i32(encode_len(val.len(), "byte array")?);
+                wtr.put_slice(val);
+            }
+            NBT::String(val) => encode_string(val, wtr)?,
+            NBT::List(val) => {
+                let id = val.first().map_or(0, NBT::id);
+                ensure!(
+                    val.iter().all(|tag| tag.id() == id),
+                    "NBT list elements must all have the same type"
+                );
+                ensure!(val.is_empty() || id != 0, "NBT list can't contain end tags");
+
+                wtr.put_u8(id);
+                wtr.put_i32(encode_len(val.len(), "list")?);
+                for tag in &val.0 {
+                    tag.encode_payload(wtr)?;
+                }
+            }
+            NBT::Compound(val) => {
+                for (key, tag) in &val.0 {
+                    ensure!(
+                        !matches!(tag, NBT::End()),
+                        "NBT compound can't contain an end tag (key {key})"
+                    );
+
+                    wtr.put_u8(tag.id());
+                    encode_string(key, wtr)?;
+                    tag.encode_payload(wtr)?;
+                }
+                wtr.put_u8(0);
+            }
+            NBT::IntArray(val) => {
+                wtr.put_i32(encode_len(val.len(), "int array")?);
+                for val in val {
+                    wtr.put_i32(*val);
+                }
+            }
+            NBT::LongArray(val) => {
+                wtr.put_i32(encode_len(val.len(), "long array")?);
+                for val in val {
+                    wtr.put_i64(*val);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn decode_payload(rdr: &mut &[u8], id: u8, depth: usize) -> anyhow::Result<Self> {
+        ensure!(
+            depth <= MAX_DEPTH,
+            "NBT exceeds the maximum depth of {MAX_DEPTH}"
+        );
+
+        match id {
+            0 => Ok(NBT::End()),
+            1 => Ok(NBT::Byte(i8::decode(rdr)?)),
+            2 => Ok(NBT::Short(i16::decode(rdr)?)),
+            3 => Ok(NBT::Int(i32::decode(rdr)?)),
+            4 => Ok(NBT::Long(i64::decode(rdr)?)),
+            5 => Ok(NBT::Float(f32::decode(rdr)?)),
+            6 => Ok(NBT::Double(f64::decode(rdr)?)),
+            7 => {
+                let len = i32::decode(rdr)?;
+                let len = checked_len(rdr, len, 1, "byte array")?;
+                let (val, rest) = rdr.split_at(len);
+                *rdr = rest;
+                Ok(NBT::ByteArray(Bytes::copy_from_slice(val)))
+            }
+            8 => Ok(NBT::String(decode_string(rdr)?)),
+            9 => {
+                let id = u8::decode(rdr)?;
+                let len = i32::decode(rdr)?;
+
+                if len <= 0 {
+                    return Ok(NBT::List(List::new()));
+                }
+                ensure!(id != 0, "NBT list of end tags has a length of {len}");
+
+                let len = checked_len(rdr, len, min_payload_len(id), "list")?;
+                let mut list = List::with_capacity(len);
+                for _ in 0..len {
+                    list.push(NBT::decode_payload(rdr, id, depth + 1)?);
+                }
+                Ok(NBT::List(list))
+            }
+            10 => {
+                let mut map = Compound::new();
+                loop {
+                    let id = u8::decode(rdr)?;
+                    if id == 0 {
+                        break;
+                    }
+
+                    let name = decode_string(rdr)?;
+                    let tag = NBT::decode_payload(rdr, id, depth + 1)?;
+                    map.insert(name, tag);
+                }
+                Ok(NBT::Compound(map))
+            }
+            11 => {
+                let len = i32::decode(rdr)?;
+                let len = checked_len(rdr, len, 4, "int array")?;
+                let mut vec = Vec::with_capacity(len);
+                for _ in 0..len {
+                    vec.push(rdr.get_i32());
+                }
+                Ok(NBT::IntArray(vec))
+            }
+            12 => {
+                let len = i32::decode(rdr)?;
+                let len = checked_len(rdr, len, 8, "long array")?;
+                let mut vec = Vec::with_capacity(len);
+                for _ in 0..len {
+                    vec.push(rdr.get_i64());
+                }
+                Ok(NBT::LongArray(vec))
+            }
+            _ => Err(anyhow::anyhow!("Unknown tag ID: {}", id)),
+        }
+    }
+}
+
+/// Checks a length prefix against the remaining input, given the smallest
+/// encoded size of an element, so a bogus length can't make us allocate more
+/// than the packet could possibly hold.
+fn checked_len(rdr: &[u8], len: i32, elem_len: usize, what: &str) -> anyhow::Result<usize> {
+    ensure!(len >= 0, "NBT {what} has a negative length of {len}");
+
+    let len = len as usize;
+    ensure!(
+        len * elem_len <= rdr.len(),
+        "NBT {what} of length {len} exceeds the remaining {} bytes",
+        rdr.len()
+    );
+
+    Ok(len)
+}
+
+/// The smallest number of bytes a payload of tag `id` can be encoded in.
+fn min_payload_len(id: u8) -> usize {
+    match id {
+        1 | 10 => 1,
+        2 | 8 => 2,
+        3 | 5 | 7 | 11 | 12 => 4,
+        4 | 6 => 8,
+        9 => 5,
+        _ => 0,
+    }
+}
+
+fn encode_len(len: usize, what: &str) -> anyhow::Result<i32> {
+    i32::try_from(len).map_err(|_| anyhow::anyhow!("NBT {what} of length {len} is too long"))
+}
+
+fn decode_string(rdr: &mut &[u8]) -> anyhow::Result<String> {
+    let len = u16::decode(rdr)? as usize;
+    ensure_remaining(rdr, len, "NBT string")?;
+
+    let (val, rest) = rdr.split_at(len);
+    *rdr = rest;
+    decode_mutf8(val)
+}
+
+/// NBT strings are Java's modified UTF-8, which encodes NUL as two bytes and
+/// chars outside the BMP as two 3 byte surrogates.
+fn encode_string(val: &str, wtr: &mut BytesMut) -> anyhow::Result<()> {
+    if val.bytes().all(|b| b != 0 && b < 0xf0) {
+        ensure!(
+            val.len() <= u16::MAX as usize,
+            "NBT string of {} bytes is too long",
+            val.len()
+        );
+
+        wtr.put_u16(val.len() as u16);
+        wtr.put_slice(val.as_bytes());
+        return Ok(());
+    }
+
+    let mut buf = Vec::with_capacity(val.len() + 2);
+    for unit in val.encode_utf16() {
+        match unit {
+            0x01..=0x7f => buf.push(unit as u8),
+            0x00 | 0x80..=0x7ff => {
+                buf.push(0xc0 | (unit >> 6) as u8);
+                buf.push(0x80 | (unit & 0x3f) as u8);
+            }
+            _ => {
+                buf.push(0xe0 | (unit >> 12) as u8);
+                buf.push(0x80 | ((unit >> 6) & 0x3f) as u8);
+                buf.push(0x80 | (unit & 0x3f) as u8);
+            }
+        }
+    }
+
+    ensure!(
+        buf.len() <= u16::MAX as usize,
+        "NBT string of {} bytes is too long",
+        buf.len()
+    );
+
+    wtr.put_u16(buf.len() as u16);
+    wtr.put_slice(&buf);
+    Ok(())
+}
+
+fn decode_mutf8(bytes: &[u8]) -> anyhow::Result<String> {
+    // Anything without NULs and supplementary chars is plain UTF-8 as well.
+    if let Ok(val) = std::str::from_utf8(bytes) {
+        return Ok(val.to_owned());
+    }
+
+    let continuation = |i: usize| match bytes.get(i) {
+        Some(&b) if b & 0xc0 == 0x80 => Ok((b & 0x3f) as u16),
+        _ => Err(anyhow::anyhow!(
+            "truncated modified UTF-8 sequence at byte {i}"
+        )),
+    };
+
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        let (unit, len) = match b {
+            0x00..=0x7f => (b as u16, 1),
+            0xc0..=0xdf => (((b & 0x1f) as u16) << 6 | continuation(i + 1)?, 2),
+            0xe0..=0xef => (
+                ((b & 0x0f) as u16) << 12 | continuation(i + 1)? << 6 | continuation(i + 2)?,
+                3,
+            ),
+            _ => anyhow::bail!("invalid modified UTF-8 byte {b:#04x} at byte {i}"),
+        };
+
+        units.push(unit);
+        i += len;
+    }
+
+    Ok(String::from_utf16(&units)?)
+}
+
+#[derive(Debug, Clone, Deref, DerefMut, PartialEq)]
+pub struct List(Vec<NBT>);
+
+impl List {
+    fn new() -> Self {
+        Self(Vec::<NBT>::new())
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        Self(Vec::<NBT>::with_capacity(capacity))
+    }
+}
+
+#[derive(Debug, Clone, Deref, DerefMut, PartialEq)]
+pub struct Compound(IndexMap<String, NBT>);
+
+impl Compound {
+    fn new() -> Self {
+        Self(IndexMap::<String, NBT>::new())
+    }
+}
+
+#[cfg(any(test, feature = "arbitrary"))]
+impl<'a> arbitrary::Arbitrary<'a> for NBT {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let id = u.int_in_range(1..=12)?;
+        arbitrary_tag(u, id, 0)
+    }
+}
+
+#[cfg(any(test, feature = "arbitrary"))]
+fn arbitrary_tag(u: &mut arbitrary::Unstructured, id: u8, depth: usize) -> arbitrary::Result<NBT> {
+    // Deeper trees don't find more bugs, they just make the input huge.
+    const MAX_ARBITRARY_DEPTH: usize = 4;
+
+    Ok(match id {
+        1 => NBT::Byte(u.arbitrary()?),
+        2 => NBT::Short(u.arbitrary()?),
+        3 => NBT::Int(u.arbitrary()?),
+        4 => NBT::Long(u.arbitrary()?),
+        5 => NBT::Float(u.arbitrary()?),
+        6 => NBT::Double(u.arbitrary()?),
+        7 => NBT::ByteArray(Bytes::copy_from_slice(u.arbitrary()?)),
+        8 => NBT::String(u.arbitrary()?),
+        9 => {
+            let mut list = List::new();
+            if depth < MAX_ARBITRARY_DEPTH {
+                let id = u.int_in_range(1..=12)?;
+                for _ in 0..u.int_in_range(0..=4)? {
+                    list.push(arbitrary_tag(u, id, depth + 1)?);
+                }
+            }
+            NBT::List(list)
+        }
+        10 => {
+            let mut map = Compound::new();
+            if depth < MAX_ARBITRARY_DEPTH {
+                for _ in 0..u.int_in_range(0..=4)? {
+                    let id = u.int_in_range(1..=12)?;
+                    map.insert(u.arbitrary()?, arbitrary_tag(u, id, depth + 1)?);
+                }
+            }
+            NBT::Compound(map)
+        }
+        11 => NBT::IntArray(u.arbitrary()?),
+        _ => NBT::LongArray(u.arbitrary()?),
+    })
+}
+
+impl Encode for NBT {
+    fn encode(&self, wtr: &mut BytesMut) -> anyhow::Result<()> {
+        self.write_network(wtr)
+    }
+}
+
+impl<'a> Decode<'a> for NBT {
+    fn decode(rdr: &mut &'a [u8]) -> anyhow::Result<Self> {
+        NBT::read_network(rdr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arbitrary::{Arbitrary, Unstructured};
+    use proptest::prelude::*;
+
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn round_trip(seed: Vec<u8>) {
+            if let Ok(tag) = NBT::arbitrary(&mut Unstructured::new(&seed)) {
+                assert_round_trip!(NBT, tag);
+            }
+        }
+    }
+
+    #[test]
+    fn list() {
+        let mut list = List::new();
+
+        list.push(NBT::End());
+        list.push(NBT::Byte(7));
+
+        assert!(matches!(list.first().unwrap(), NBT::End()));
+        assert!(matches!(list.get(1).unwrap(), NBT::Byte(7)));
+    }
+
+    #[test]
+    fn compound() {
+        let mut compound = Compound::new();
+
+        compound.insert("test".to_owned(), NBT::End());
+
+        assert!(compound.contains_key(&"test".to_string()));
+        assert!(matches!(
+            compound.get(&"test".to_string()).unwrap(),
+            NBT::End()
+        ));
+    }
+
+    #[test]
+    fn test_test() {
+        let data = include_bytes!("../testdata/test.nbt");
+
+        let (name, tag) = NBT::read_named(&mut &data[..]).unwrap();
+        assert_eq!(name, "hello world");
+
+        let mut buf = BytesMut::new();
+        tag.write_named(&name, &mut buf).unwrap();
+        assert_eq!(&buf[..], &data[..]);
+
+        let NBT::Compound(mut hello) = tag else {
+            panic!("root is not a compound");
+        };
+
+        assert_eq!(
+            hello.get(&"name".to_owned()).unwrap(),
+            &NBT::String("Bananrama".to_owned())
+        );
+
+        hello.insert("name".to_owned(), NBT::String("awawa".to_owned()));
+
+        assert_eq!(
+            hello.get(&"name".to_owned()).unwrap(),
+            &NBT::String("awawa".to_owned())
+        );
+    }
+
+    #[test]
+    fn bigtest_test() {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+
+        let uncompressed = include_bytes!("../testdata/bigtest.nbt");
+        let mut data = vec![];
+
+        let mut gz = GzDecoder::new(uncompressed.as_slice());
+        gz.read_to_end(&mut data).unwrap();
+
+        let mut rdr = data.as_slice();
+        let (name, tag) = NBT::read_named(&mut rdr).unwrap();
+        assert!(rdr.is_empty());
+        assert_eq!(name, "Level");
+
+        let NBT::Compound(level) = &tag else {
+            panic!("root is not a compound");
+        };
+        assert_eq!(level.get("longTest"), Some(&NBT::Long(i64::MAX)));
+        assert_eq!(level.get("shortTest"), Some(&NBT::Short(i16::MAX)));
+        assert_eq!(
+            level.get("stringTest"),
+            Some(&NBT::String(
+                "HELLO WORLD THIS IS A TEST STRING \u{c5}\u{c4}\u{d6}!".to_owned()
+            ))
+        );
+        let Some(NBT::ByteArray(bytes)) = level.get(
+            "byteArrayTest (the first 1000 values of (n*n*255+n*7)%100, starting with n=0 (0, 62, 34, 16, 8, ...))",
+        ) else {
+            panic!("missing byte array");
+        };
+        assert_eq!(bytes.len(), 1000);
+        assert!((0..1000).all(|n| bytes[n] as usize == (n * n * 255 + n * 7) % 100));
+
+        let mut buf = BytesMut::new();
+        tag.write_named(&name, &mut buf).unwrap();
+        assert_eq!(&buf[..], &data[..]);
+    }
+
+    #[test]
+    fn network() {
+        let mut compound = Compound::new();
+        compound.insert("a".to_owned(), NBT::Int(1));
+        let tag = NBT::Compound(compound);
+
+        let mut buf = BytesMut::new();
+        tag.write_network(&mut buf).unwrap();
+        assert_eq!(&buf[..], [10, 3, 0, 1, b'a', 0, 0, 0, 1, 0]);
+        assert_eq!(NBT::read_network(&mut &buf[..]).unwrap(), tag);
+
+        // Text components may be sent as a bare string.
+        let mut buf = BytesMut::new();
+        NBT::String("hi".to_owned()).encode(&mut buf).unwrap();
+        assert_eq!(&buf[..], [8, 0, 2, b'h', b'i']);
+    }
+
+    #[test]
+    fn modified_utf8() {
+        let val = "nul \0 and \u{1f600}";
+
+        let mut buf = BytesMut::new();
+        encode_string(val, &mut buf).unwrap();
+        assert!(!buf[2..].contains(&0));
+        assert!(buf[2..].windows(2).any(|w| w == [0xc0, 0x80]));
+        assert_eq!(&buf[buf.len() - 6..], [0xed, 0xa0, 0xbd, 0xed, 0xb8, 0x80]);
+
+        assert_eq!(decode_string(&mut &buf[..]).unwrap(), val);
+    }
+
+    #[test]
+    fn invalid_tags() {
+        let mut buf = BytesMut::new();
+
+        let mixed = NBT::List(List(vec![NBT::Byte(1), NBT::Int(1)]));
+        assert!(mixed.encode(&mut buf).is_err());
+
+        let mut compound = Compound::new();
+        compound.insert("end".to_owned(), NBT::End());
+        assert!(NBT::Compound(compound).encode(&mut buf).is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        let data = include_bytes!("../testdata/test.nbt");
+
+        // Cuts the root name, a key, a value and the final end tag short.
+        for len in [2, 8, 19, 30, data.len() - 1] {
+            assert!(NBT::read_named(&mut &data[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn oversized_length() {
+        // A long array claiming i32::MAX elements with no data behind it.
+        let data = [12, 0x7f, 0xff, 0xff, 0xff];
+        assert!(<NBT as Decode>::decode(&mut &data[..]).is_err());
+
+        let data = [9, 10, 0x7f, 0xff, 0xff, 0xff, 0];
+        assert!(<NBT as Decode>::decode(&mut &data[..]).is_err());
+
+        let data = [9, 0, 0, 0, 0, 1];
+        assert!(<NBT as Decode>::decode(&mut &data[..]).is_err());
+    }
+
+    #[test]
+    fn max_depth() {
+        // Lists of a single list, with a byte at `depth`.
+        let nested = |depth: usize| {
+            let mut data = vec![9];
+            for _ in 1..depth {
+                data.extend_from_slice(&[9, 0, 0, 0, 1]);
+            }
+            data.extend_from_slice(&[1, 0, 0, 0, 1, 7]);
+            data
+        };
+
+        assert!(<NBT as Decode>::decode(&mut &nested(MAX_DEPTH)[..]).is_ok());
+        assert!(<NBT as Decode>::decode(&mut &nested(MAX_DEPTH + 1)[..]).is_err());
+    }
+}
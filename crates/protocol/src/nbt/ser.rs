@@ -0,0 +1,518 @@
+use bytes::Bytes;
+use serde::ser::{self, Serialize};
+
+use super::{Compound, Error, List, NBT};
+
+const INT_ARRAY: &str = "__nbt_int_array";
+const LONG_ARRAY: &str = "__nbt_long_array";
+
+/// Serializes `value` to `NBT`. Structs and maps become compounds, with `None`
+/// fields left out, sequences and tuples become lists, so their elements need
+/// to have the same type, and enums are externally tagged. Unsigned integers
+/// keep their bits in the signed tag of the same size. Use `serde_bytes`,
+/// [`int_array`] or [`long_array`] to get array tags instead of lists.
+pub fn to_nbt<T: Serialize + ?Sized>(value: &T) -> Result<NBT, Error> {
+    value.serialize(Serializer)
+}
+
+/// Serializes an `[i32]` as an int array tag, for `#[serde(with = "...")]`.
+pub mod int_array {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(val: &[i32], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(super::INT_ARRAY, val)
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: From<Vec<i32>>,
+    {
+        Vec::deserialize(deserializer).map(T::from)
+    }
+}
+
+/// Serializes an `[i64]` as a long array tag, for `#[serde(with = "...")]`.
+pub mod long_array {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(val: &[i64], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(super::LONG_ARRAY, val)
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: From<Vec<i64>>,
+    {
+        Vec::deserialize(deserializer).map(T::from)
+    }
+}
+
+struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = NBT;
+    type Error = Error;
+
+    type SerializeSeq = SerializeList;
+    type SerializeTuple = SerializeList;
+    type SerializeTupleStruct = SerializeList;
+    type SerializeTupleVariant = Variant<SerializeList>;
+    type SerializeMap = SerializeCompound;
+    type SerializeStruct = SerializeCompound;
+    type SerializeStructVariant = Variant<SerializeCompound>;
+
+    fn serialize_bool(self, v: bool) -> Result<NBT, Error> {
+        Ok(NBT::Byte(v as i8))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<NBT, Error> {
+        Ok(NBT::Byte(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<NBT, Error> {
+        Ok(NBT::Short(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<NBT, Error> {
+        Ok(NBT::Int(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<NBT, Error> {
+        Ok(NBT::Long(v))
+    }
+
+    // NBT has no unsigned types, so these keep their bits in the signed tag
+    // of the same size.
+    fn serialize_u8(self, v: u8) -> Result<NBT, Error> {
+        Ok(NBT::Byte(v as i8))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<NBT, Error> {
+        Ok(NBT::Short(v as i16))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<NBT, Error> {
+        Ok(NBT::Int(v as i32))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<NBT, Error> {
+        Ok(NBT::Long(v as i64))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<NBT, Error> {
+        Ok(NBT::Float(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<NBT, Error> {
+        Ok(NBT::Double(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<NBT, Error> {
+        Ok(NBT::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<NBT, Error> {
+        Ok(NBT::String(v.to_owned()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<NBT, Error> {
+        Ok(NBT::ByteArray(Bytes::copy_from_slice(v)))
+    }
+
+    fn serialize_none(self) -> Result<NBT, Error> {
+        Ok(NBT::End())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<NBT, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<NBT, Error> {
+        Ok(NBT::End())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<NBT, Error> {
+        Ok(NBT::End())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<NBT, Error> {
+        Ok(NBT::String(variant.to_owned()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<NBT, Error> {
+        let nbt = value.serialize(self)?;
+
+        match name {
+            INT_ARRAY => collect_array(nbt, "int", |tag| match tag {
+                NBT::Int(val) => Some(val),
+                _ => None,
+            })
+            .map(NBT::IntArray),
+            LONG_ARRAY => collect_array(nbt, "long", |tag| match tag {
+                NBT::Long(val) => Some(val),
+                _ => None,
+            })
+            .map(NBT::LongArray),
+            _ => Ok(nbt),
+        }
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<NBT, Error> {
+        let mut compound = Compound::new();
+        let value = value.serialize(self).map_err(|e| e.in_key(variant))?;
+        compound.insert(variant.to_owned(), value);
+        Ok(NBT::Compound(compound))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeList, Error> {
+        Ok(SerializeList(List::with_capacity(len.unwrap_or(0))))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeList, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeList, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Variant<SerializeList>, Error> {
+        Ok(Variant {
+            variant,
+            inner: self.serialize_seq(Some(len))?,
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeCompound, Error> {
+        Ok(SerializeCompound {
+            compound: Compound::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SerializeCompound, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Variant<SerializeCompound>, Error> {
+        Ok(Variant {
+            variant,
+            inner: self.serialize_map(Some(len))?,
+        })
+    }
+}
+
+fn collect_array<T>(
+    nbt: NBT,
+    kind: &str,
+    element: impl Fn(NBT) -> Option<T>,
+) -> Result<Vec<T>, Error> {
+    let NBT::List(list) = nbt else {
+        return Err(Error::new(format!("{kind} array must be a sequence")));
+    };
+
+    list.0
+        .into_iter()
+        .enumerate()
+        .map(|(i, tag)| {
+            element(tag).ok_or_else(|| Error::new(format!("expected {kind}")).in_index(i))
+        })
+        .collect()
+}
+
+struct SerializeList(List);
+
+impl ser::SerializeSeq for SerializeList {
+    type Ok = NBT;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let index = self.0.len();
+        let tag = value.serialize(Serializer).map_err(|e| e.in_index(index))?;
+
+        if matches!(tag, NBT::End()) {
+            return Err(Error::new("list can't contain unit or `None`").in_index(index));
+        }
+        if let Some(first) = self.0.first() {
+            if first.id() != tag.id() {
+                return Err(Error::new("list elements must all have the same type").in_index(index));
+            }
+        }
+
+        self.0.push(tag);
+        Ok(())
+    }
+
+    fn end(self) -> Result<NBT, Error> {
+        Ok(NBT::List(self.0))
+    }
+}
+
+impl ser::SerializeTuple for SerializeList {
+    type Ok = NBT;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<NBT, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeList {
+    type Ok = NBT;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<NBT, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+struct SerializeCompound {
+    compound: Compound,
+    key: Option<String>,
+}
+
+impl SerializeCompound {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: String, value: &T) -> Result<(), Error> {
+        let tag = value.serialize(Serializer).map_err(|e| e.in_key(&key))?;
+
+        // `None` fields are left out, compounds can't hold end tags anyway.
+        if !matches!(tag, NBT::End()) {
+            self.compound.insert(key, tag);
+        }
+
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for SerializeCompound {
+    type Ok = NBT;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        match key.serialize(Serializer)? {
+            NBT::String(key) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            _ => Err(Error::new("compound keys must be strings")),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| Error::new("value serialized before key"))?;
+
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<NBT, Error> {
+        Ok(NBT::Compound(self.compound))
+    }
+}
+
+impl ser::SerializeStruct for SerializeCompound {
+    type Ok = NBT;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(key.to_owned(), value)
+    }
+
+    fn end(self) -> Result<NBT, Error> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+/// Wraps the value of a tuple or struct variant in a compound with the
+/// variant name as its only key.
+struct Variant<S> {
+    variant: &'static str,
+    inner: S,
+}
+
+impl<S> Variant<S> {
+    fn wrap(variant: &str, value: Result<NBT, Error>) -> Result<NBT, Error> {
+        let mut compound = Compound::new();
+        compound.insert(variant.to_owned(), value?);
+        Ok(NBT::Compound(compound))
+    }
+}
+
+impl ser::SerializeTupleVariant for Variant<SerializeList> {
+    type Ok = NBT;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(&mut self.inner, value)
+            .map_err(|e| e.in_key(self.variant))
+    }
+
+    fn end(self) -> Result<NBT, Error> {
+        Self::wrap(self.variant, ser::SerializeSeq::end(self.inner))
+    }
+}
+
+impl ser::SerializeStructVariant for Variant<SerializeCompound> {
+    type Ok = NBT;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
+            .map_err(|e| e.in_key(self.variant))
+    }
+
+    fn end(self) -> Result<NBT, Error> {
+        Self::wrap(self.variant, ser::SerializeMap::end(self.inner))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::nbt::from_nbt;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    enum Kind {
+        Chest,
+        Sign { text: String },
+        Spawner(i16),
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct BlockEntity {
+        id: String,
+        x: i32,
+        keep_packed: bool,
+        custom_name: Option<String>,
+        #[serde(with = "serde_bytes")]
+        light: Vec<u8>,
+        #[serde(with = "int_array")]
+        uuid: Vec<i32>,
+        #[serde(with = "long_array")]
+        heights: Vec<i64>,
+        slots: Vec<u8>,
+        pos: (i32, i32, i32),
+        kind: Kind,
+    }
+
+    #[test]
+    fn round_trip() {
+        let entity = BlockEntity {
+            id: "minecraft:sign".to_owned(),
+            x: -7,
+            keep_packed: true,
+            custom_name: None,
+            light: vec![0, 15, 255],
+            uuid: vec![1, 2, 3, 4],
+            heights: vec![],
+            slots: vec![0, 200],
+            pos: (1, 2, 3),
+            kind: Kind::Sign {
+                text: "hi".to_owned(),
+            },
+        };
+
+        let nbt = to_nbt(&entity).unwrap();
+        let NBT::Compound(compound) = &nbt else {
+            panic!("struct is not a compound");
+        };
+        assert!(!compound.contains_key("custom_name"));
+        assert_eq!(compound["keep_packed"], NBT::Byte(1));
+        assert_eq!(
+            compound["light"],
+            NBT::ByteArray(Bytes::from_static(&[0, 15, 255]))
+        );
+        assert_eq!(compound["uuid"], NBT::IntArray(vec![1, 2, 3, 4]));
+        assert_eq!(compound["heights"], NBT::LongArray(vec![]));
+
+        assert_eq!(
+            compound["slots"],
+            NBT::List(List(vec![NBT::Byte(0), NBT::Byte(-56)]))
+        );
+        assert_eq!(from_nbt::<BlockEntity>(&nbt).unwrap(), entity);
+
+        for kind in [Kind::Chest, Kind::Spawner(3)] {
+            assert_eq!(from_nbt::<Kind>(&to_nbt(&kind).unwrap()).unwrap(), kind);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        #[derive(Serialize)]
+        struct Mixed {
+            values: Vec<Value>,
+        }
+
+        #[derive(Serialize)]
+        #[serde(untagged)]
+        enum Value {
+            Int(i32),
+            Str(&'static str),
+        }
+
+        let mixed = Mixed {
+            values: vec![Value::Int(1), Value::Str("two")],
+        };
+        let err = to_nbt(&mixed).unwrap_err();
+        assert_eq!(err.path(), "values[1]");
+
+        let err = to_nbt(&[Some(1), None]).unwrap_err();
+        assert_eq!(err.path(), "[1]");
+
+        let map = std::collections::BTreeMap::from([(1, 2)]);
+        assert!(to_nbt(&map).is_err());
+    }
+}
@@ -0,0 +1,515 @@
+use std::fmt::{self, Display, Write};
+use std::str::FromStr;
+
+use anyhow::{bail, ensure};
+use bytes::Bytes;
+
+use super::{Compound, List, MAX_DEPTH, NBT};
+
+/// Parses SNBT, the text format used by commands and data packs, like
+/// `{name:"x",list:[1b,2b],arr:[I;1,2]}`. Compound keys keep their order.
+impl FromStr for NBT {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut parser = Parser { input: s, pos: 0 };
+
+        let tag = parser.parse_value(0)?;
+        parser.skip_whitespace();
+        ensure!(
+            parser.pos == s.len(),
+            "trailing characters at position {}",
+            parser.pos
+        );
+
+        Ok(tag)
+    }
+}
+
+/// Prints the tag as SNBT. `{}` prints it on one line, `{:#}` indents nested
+/// lists and compounds by four spaces. Parsing the output gives back the same
+/// tag.
+impl Display for NBT {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let indent = if f.alternate() { Some("    ") } else { None };
+        write_snbt(self, f, indent, 0)
+    }
+}
+
+fn write_snbt(
+    tag: &NBT,
+    f: &mut fmt::Formatter<'_>,
+    indent: Option<&str>,
+    level: usize,
+) -> fmt::Result {
+    match tag {
+        // There is no SNBT for end tags, an empty compound is the closest.
+        NBT::End() => f.write_str("{}"),
+        NBT::Byte(val) => write!(f, "{val}b"),
+        NBT::Short(val) => write!(f, "{val}s"),
+        NBT::Int(val) => write!(f, "{val}"),
+        NBT::Long(val) => write!(f, "{val}L"),
+        NBT::Float(val) => write!(f, "{val:?}f"),
+        NBT::Double(val) => write!(f, "{val:?}d"),
+        NBT::String(val) => write_string(val, f),
+        NBT::ByteArray(vals) => {
+            write_array(f, 'B', vals.iter().map(|v| *v as i8), "b", indent.is_some())
+        }
+        NBT::IntArray(vals) => write_array(f, 'I', vals.iter(), "", indent.is_some()),
+        NBT::LongArray(vals) => write_array(f, 'L', vals.iter(), "L", indent.is_some()),
+        NBT::List(list) => {
+            if list.is_empty() {
+                return f.write_str("[]");
+            }
+
+            f.write_char('[')?;
+            for (i, tag) in list.iter().enumerate() {
+                if i > 0 {
+                    f.write_char(',')?;
+                }
+                write_newline(f, indent, level + 1)?;
+                write_snbt(tag, f, indent, level + 1)?;
+            }
+            write_newline(f, indent, level)?;
+            f.write_char(']')
+        }
+        NBT::Compound(compound) => {
+            if compound.is_empty() {
+                return f.write_str("{}");
+            }
+
+            f.write_char('{')?;
+            for (i, (key, tag)) in compound.iter().enumerate() {
+                if i > 0 {
+                    f.write_char(',')?;
+                }
+                write_newline(f, indent, level + 1)?;
+
+                if !key.is_empty() && key.chars().all(is_unquoted_char) {
+                    f.write_str(key)?;
+                } else {
+                    write_string(key, f)?;
+                }
+                f.write_str(if indent.is_some() { ": " } else { ":" })?;
+
+                write_snbt(tag, f, indent, level + 1)?;
+            }
+            write_newline(f, indent, level)?;
+            f.write_char('}')
+        }
+    }
+}
+
+fn write_newline(f: &mut fmt::Formatter<'_>, indent: Option<&str>, level: usize) -> fmt::Result {
+    let Some(indent) = indent else {
+        return Ok(());
+    };
+
+    f.write_char('\n')?;
+    for _ in 0..level {
+        f.write_str(indent)?;
+    }
+
+    Ok(())
+}
+
+fn write_array<T: Display>(
+    f: &mut fmt::Formatter<'_>,
+    kind: char,
+    vals: impl Iterator<Item = T>,
+    suffix: &str,
+    pretty: bool,
+) -> fmt::Result {
+    write!(f, "[{kind};")?;
+    for (i, val) in vals.enumerate() {
+        match (i, pretty) {
+            (0, false) => {}
+            (0, true) => f.write_char(' ')?,
+            (_, false) => f.write_char(',')?,
+            (_, true) => f.write_str(", ")?,
+        }
+        write!(f, "{val}{suffix}")?;
+    }
+    f.write_char(']')
+}
+
+/// Quotes `val` with double quotes, or single quotes if that avoids escaping.
+fn write_string(val: &str, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let quote = if val.contains('"') && !val.contains('\'') {
+        '\''
+    } else {
+        '"'
+    };
+
+    f.write_char(quote)?;
+    for c in val.chars() {
+        if c == quote || c == '\\' {
+            f.write_char('\\')?;
+        }
+        f.write_char(c)?;
+    }
+    f.write_char(quote)
+}
+
+fn is_unquoted_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+')
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.input[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn expect(&mut self, expected: char) -> anyhow::Result<()> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.pos += c.len_utf8();
+                Ok(())
+            }
+            Some(c) => bail!(
+                "expected `{expected}` but found `{c}` at position {}",
+                self.pos
+            ),
+            None => bail!("expected `{expected}` but found the end of input"),
+        }
+    }
+
+    /// Consumes `c` if it is the next character after any whitespace.
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_value(&mut self, depth: usize) -> anyhow::Result<NBT> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.parse_compound(depth),
+            Some('[') => self.parse_list(depth),
+            Some('"' | '\'') => Ok(NBT::String(self.parse_quoted()?)),
+            Some(_) => Ok(parse_literal(self.parse_unquoted()?)),
+            None => bail!("expected a value but found the end of input"),
+        }
+    }
+
+    fn parse_compound(&mut self, depth: usize) -> anyhow::Result<NBT> {
+        ensure!(depth < MAX_DEPTH, "SNBT is nested deeper than {MAX_DEPTH}");
+        self.expect('{')?;
+
+        let mut compound = Compound::new();
+        if self.eat('}') {
+            return Ok(NBT::Compound(compound));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let start = self.pos;
+            let key = match self.peek() {
+                Some('"' | '\'') => self.parse_quoted()?,
+                _ => self.parse_unquoted()?.to_owned(),
+            };
+            self.expect(':')?;
+
+            let tag = self.parse_value(depth + 1)?;
+            ensure!(
+                compound.insert(key, tag).is_none(),
+                "duplicate key at position {start}"
+            );
+
+            if !self.eat(',') {
+                break;
+            }
+        }
+
+        self.expect('}')?;
+        Ok(NBT::Compound(compound))
+    }
+
+    fn parse_list(&mut self, depth: usize) -> anyhow::Result<NBT> {
+        ensure!(depth < MAX_DEPTH, "SNBT is nested deeper than {MAX_DEPTH}");
+        self.expect('[')?;
+
+        let rest = &self.input[self.pos..];
+        if let Some(kind @ ('B' | 'I' | 'L')) = rest.chars().next() {
+            if rest[1..].trim_start().starts_with(';') {
+                self.pos += 1;
+                self.expect(';')?;
+
+                return Ok(match kind {
+                    'B' => {
+                        NBT::ByteArray(Bytes::from(self.parse_array(kind, |tag| match tag {
+                            NBT::Byte(val) => Some(val as u8),
+                            _ => None,
+                        })?))
+                    }
+                    'I' => NBT::IntArray(self.parse_array(kind, |tag| match tag {
+                        NBT::Int(val) => Some(val),
+                        _ => None,
+                    })?),
+                    _ => NBT::LongArray(self.parse_array(kind, |tag| match tag {
+                        NBT::Long(val) => Some(val),
+                        _ => None,
+                    })?),
+                });
+            }
+        }
+
+        let mut list = List::new();
+        if self.eat(']') {
+            return Ok(NBT::List(list));
+        }
+
+        loop {
+            let start = self.pos;
+            let tag = self.parse_value(depth + 1)?;
+            if let Some(first) = list.first() {
+                ensure!(
+                    first.id() == tag.id(),
+                    "list element at position {start} has a different type than the first"
+                );
+            }
+            list.push(tag);
+
+            if !self.eat(',') {
+                break;
+            }
+        }
+
+        self.expect(']')?;
+        Ok(NBT::List(list))
+    }
+
+    fn parse_array<T>(
+        &mut self,
+        kind: char,
+        element: impl Fn(NBT) -> Option<T>,
+    ) -> anyhow::Result<Vec<T>> {
+        let mut vals = vec![];
+        if self.eat(']') {
+            return Ok(vals);
+        }
+
+        loop {
+            self.skip_whitespace();
+            let start = self.pos;
+            let Some(val) = element(parse_literal(self.parse_unquoted()?)) else {
+                bail!("invalid {kind} array element at position {start}");
+            };
+            vals.push(val);
+
+            if !self.eat(',') {
+                break;
+            }
+        }
+
+        self.expect(']')?;
+        Ok(vals)
+    }
+
+    fn parse_quoted(&mut self) -> anyhow::Result<String> {
+        let start = self.pos;
+        let mut chars = self.input[self.pos..].char_indices();
+        let Some((_, quote)) = chars.next() else {
+            bail!("expected a string but found the end of input");
+        };
+
+        let mut val = String::new();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some((_, c @ ('\\' | '"' | '\''))) => val.push(c),
+                    _ => bail!("invalid escape at position {}", start + i),
+                },
+                c if c == quote => {
+                    self.pos += i + c.len_utf8();
+                    return Ok(val);
+                }
+                c => val.push(c),
+            }
+        }
+
+        bail!("unterminated string at position {start}")
+    }
+
+    fn parse_unquoted(&mut self) -> anyhow::Result<&'a str> {
+        let rest = &self.input[self.pos..];
+        let len = rest.find(|c| !is_unquoted_char(c)).unwrap_or(rest.len());
+        ensure!(len > 0, "expected a value at position {}", self.pos);
+
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+}
+
+/// Parses an unquoted value, a number with an optional type suffix, `true`,
+/// `false` or a plain string.
+fn parse_literal(token: &str) -> NBT {
+    match token {
+        "true" => return NBT::Byte(1),
+        "false" => return NBT::Byte(0),
+        "NaNf" => return NBT::Float(f32::NAN),
+        "inff" => return NBT::Float(f32::INFINITY),
+        "NaNd" => return NBT::Double(f64::NAN),
+        "infd" => return NBT::Double(f64::INFINITY),
+        _ => {}
+    }
+
+    let starts_numeric = token
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.'));
+    if !starts_numeric {
+        return NBT::String(token.to_owned());
+    }
+
+    let (body, suffix) = token.split_at(token.len() - 1);
+    let tag = match suffix {
+        "b" | "B" => body.parse().ok().map(NBT::Byte),
+        "s" | "S" => body.parse().ok().map(NBT::Short),
+        "l" | "L" => body.parse().ok().map(NBT::Long),
+        "f" | "F" => body.parse().ok().map(NBT::Float),
+        "d" | "D" => body.parse().ok().map(NBT::Double),
+        _ => token.parse().ok().map(NBT::Int).or_else(|| {
+            // Unsuffixed decimals are doubles.
+            token
+                .contains(['.', 'e', 'E'])
+                .then(|| token.parse().ok().map(NBT::Double))
+                .flatten()
+        }),
+    };
+
+    tag.unwrap_or_else(|| NBT::String(token.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use arbitrary::{Arbitrary, Unstructured};
+    use proptest::prelude::*;
+
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn round_trip(seed: Vec<u8>) {
+            // NaN never compares equal, so compare the printed form.
+            if let Ok(tag) = NBT::arbitrary(&mut Unstructured::new(&seed)) {
+                let snbt = tag.to_string();
+                let parsed: NBT = snbt.parse().unwrap();
+                prop_assert_eq!(parsed.to_string(), snbt);
+            }
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let tag: NBT = r#"{name:"x",list:[1b,2b],arr:[I;1,2]}"#.parse().unwrap();
+        let NBT::Compound(compound) = &tag else {
+            panic!("root is not a compound");
+        };
+
+        assert_eq!(compound["name"], NBT::String("x".to_owned()));
+        assert_eq!(
+            compound["list"],
+            NBT::List(List(vec![NBT::Byte(1), NBT::Byte(2)]))
+        );
+        assert_eq!(compound["arr"], NBT::IntArray(vec![1, 2]));
+        assert_eq!(tag.to_string(), r#"{name:"x",list:[1b,2b],arr:[I;1,2]}"#);
+    }
+
+    #[test]
+    fn literals() {
+        let cases = [
+            ("12b", NBT::Byte(12)),
+            ("-3s", NBT::Short(-3)),
+            ("7", NBT::Int(7)),
+            ("9000000000L", NBT::Long(9000000000)),
+            ("1.5f", NBT::Float(1.5)),
+            ("2.5", NBT::Double(2.5)),
+            ("1e3d", NBT::Double(1000.0)),
+            ("true", NBT::Byte(1)),
+            ("stone", NBT::String("stone".to_owned())),
+            ("9000000000", NBT::String("9000000000".to_owned())),
+            ("'it\\'s \"x\"'", NBT::String("it's \"x\"".to_owned())),
+            (
+                "[B; 1b, -1b]",
+                NBT::ByteArray(Bytes::from_static(&[1, 255])),
+            ),
+            ("[L;]", NBT::LongArray(vec![])),
+            ("[]", NBT::List(List::new())),
+        ];
+
+        for (snbt, tag) in cases {
+            assert_eq!(snbt.parse::<NBT>().unwrap(), tag, "{snbt}");
+        }
+    }
+
+    #[test]
+    fn keeps_order() {
+        let tag: NBT = "{ z: 1, a: 2, m: { y: 3, b: 4 } }".parse().unwrap();
+        assert_eq!(tag.to_string(), "{z:1,a:2,m:{y:3,b:4}}");
+    }
+
+    #[test]
+    fn pretty() {
+        let tag: NBT = r#"{name:"x",list:[{}],arr:[I;1,2],"a key":[]}"#.parse().unwrap();
+
+        let pretty = format!("{tag:#}");
+        assert_eq!(
+            pretty,
+            "{\n    name: \"x\",\n    list: [\n        {}\n    ],\n    arr: [I; 1, 2],\n    \"a key\": []\n}"
+        );
+        assert_eq!(pretty.parse::<NBT>().unwrap(), tag);
+    }
+
+    #[test]
+    fn invalid() {
+        for snbt in [
+            "",
+            "{",
+            "{a:1,}",
+            "{a 1}",
+            "[1,2b]",
+            "[I;1b]",
+            "\"open",
+            "{a:1,a:2}",
+            "1 2",
+            "'\\n'",
+        ] {
+            assert!(snbt.parse::<NBT>().is_err(), "{snbt}");
+        }
+
+        let deep = "[".repeat(MAX_DEPTH + 1);
+        assert!(deep.parse::<NBT>().is_err());
+    }
+
+    #[test]
+    fn bigtest() {
+        use std::io::Read;
+
+        let mut data = vec![];
+        flate2::read::GzDecoder::new(include_bytes!("../testdata/bigtest.nbt").as_slice())
+            .read_to_end(&mut data)
+            .unwrap();
+        let (_, tag) = NBT::read_named(&mut data.as_slice()).unwrap();
+
+        let compact = tag.to_string();
+        assert_eq!(compact.parse::<NBT>().unwrap(), tag);
+        assert_eq!(compact.parse::<NBT>().unwrap().to_string(), compact);
+        assert_eq!(format!("{tag:#}").parse::<NBT>().unwrap(), tag);
+    }
+}
@@ -95,7 +95,7 @@ define_protocol!(767 {
                 data: Bounded<RawBytes<'a>, 1048576>,
             },
             0x02 DisconnectConfiguration {
-                reason: &'a str, // Text
+                reason: NBT, // Text
             },
             0x03 FinishConfiguration {},
             0x04 KeepAliveClientConfiguration {
@@ -106,15 +106,16 @@ define_protocol!(767 {
             },
             0x06 ResetChat {},
             0x07 RegistryData {
-                registry_codec: NBT,
+                registry_id: String, // Ident
+                entries: LenPrefixed<(String, Option<NBT>)>, // Ident, data
             },
             0x08 RemoveResourcePackConfiguration {
                 uuid: Option<Uuid>,
             },
             0x09 AddResourcePackConfiguration {
                 uuid: Uuid,
-                url: &'a str,
-                hash: Bounded<&'a str, 40>,
+                url: String,
+                hash: Bounded<String, 40>,
                 forced: bool,
                 option: Option<String> // Text
             },
@@ -132,6 +133,9 @@ define_protocol!(767 {
             0x0d UpdateTagsConfiguration {
                 tags: LenPrefixed<TagArray>,
             },
+            0x0e ClientKnownPacks {
+                known_packs: LenPrefixed<KnownPack<'a>>,
+            },
         },
         Server {
             0x00 ClientInformationConfiguration {
@@ -164,14 +168,21 @@ define_protocol!(767 {
                 result: ResourcePackResponseConfigurationResult,
             },
             0x07 ServerKnownPacks {
-                count: VarInt,
-                known_packs: Vec<KnownPack<'a>>,
+                known_packs: LenPrefixed<KnownPack<'a>>,
             },
         },
     },
 
     Play {
         Client {
+            0x09 BlockUpdate {
+                location: Position,
+                block_id: VarInt,
+            },
+            0x21 UnloadChunk {
+                chunk_z: i32,
+                chunk_x: i32,
+            },
             0x24 ClientKeepAlivePlay {
                 id: i64,
             },
@@ -188,7 +199,17 @@ define_protocol!(767 {
                 sky_lights: LenPrefixed<LenPrefixed<u8>>,
                 block_lights: LenPrefixed<LenPrefixed<u8>>,
             },
-            0x29 LoginPlay {
+            0x28 UpdateLight {
+                chunk_x: VarInt,
+                chunk_z: VarInt,
+                sky_light_mask: LenPrefixed<i64>,
+                block_light_mask: LenPrefixed<i64>,
+                empty_sky_light_mask: LenPrefixed<i64>,
+                empty_block_light_mask: LenPrefixed<i64>,
+                sky_lights: LenPrefixed<LenPrefixed<u8>>,
+                block_lights: LenPrefixed<LenPrefixed<u8>>,
+            },
+            0x2b LoginPlay {
                 id: i32,
                 is_hardcore: bool,
                 dimensions: LenPrefixed<String>,
@@ -197,15 +218,17 @@ define_protocol!(767 {
                 simulation_distance: VarInt,
                 reduced_debug_info: bool,
                 enable_respawn_screen: bool,
-                do_limited_craftingn: bool,
-                dimension_type: &'a str,
-                dimension_name: &'a str,
+                do_limited_crafting: bool,
+                dimension_type: VarInt, // ID in the minecraft:dimension_type registry
+                dimension_name: String, // Ident
                 hashed_seed: i64,
                 gamemode: u8,
                 previous_gamemode: i8,
                 is_debug: bool,
                 is_flat: bool,
                 death_location: Option<(String, Position)>,
+                portal_cooldown: VarInt,
+                enforces_secure_chat: bool,
             },
             0x3e SynchronizePlayerPosition {
                 x: f64,
@@ -216,6 +239,18 @@ define_protocol!(767 {
                 flags: u8,
                 teleport_id: VarInt,
             },
+            0x47 Respawn {
+                dimension_type: VarInt, // ID in the minecraft:dimension_type registry
+                dimension_name: String, // Ident
+                hashed_seed: i64,
+                gamemode: u8,
+                previous_gamemode: i8,
+                is_debug: bool,
+                is_flat: bool,
+                death_location: Option<(String, Position)>,
+                portal_cooldown: VarInt,
+                data_kept: u8,
+            },
         },
         Server {
             0x00 ConfirmTeleport {
@@ -228,12 +263,14 @@ define_protocol!(767 {
     },
 });
 
+#[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
 #[derive(Encode, Decode, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum HandshakeNextState {
     Status,
     Login,
 }
 
+#[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
 #[derive(Encode, Decode, Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Property<S = String> {
     pub name: S,
@@ -241,6 +278,7 @@ pub struct Property<S = String> {
     pub signature: Option<S>,
 }
 
+#[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
 #[derive(Encode, Decode, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ChatMode {
     Enabled,
@@ -248,24 +286,27 @@ pub enum ChatMode {
     Hidden,
 }
 
+#[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
 #[derive(Encode, Decode, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Hand {
     Left,
     Right,
 }
 
+#[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
 #[derive(Encode, Decode, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ResourcePackResponseConfigurationResult {
     SuccessfullyDownloaded,
     Declined,
     FailedToDownload,
-    Accpeted,
+    Accepted,
     Downloaded,
     InvalidURL,
     FailedToReload,
     Discarded,
 }
 
+#[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
 #[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
 pub struct KnownPack<'a> {
     namespace: &'a str,
@@ -273,12 +314,14 @@ pub struct KnownPack<'a> {
     version: &'a str,
 }
 
+#[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
 #[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
 pub struct TagArray {
     identifier: String,
     tags: LenPrefixed<Tag>,
 }
 
+#[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
 #[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
 pub struct Tag {
     identifier: String,
@@ -1,9 +1,12 @@
 use std::time::Instant;
 
-use bevy::prelude::*;
+use bevy::{ecs::event::ManualEventReader, prelude::*};
 use bytes::Bytes;
 
-use crate::{decoder::PacketDecoder, encoder::PacketEncoder, Decode, Packet, ProtocolRegistries};
+use crate::{
+    decoder::PacketDecoder, encoder::PacketEncoder, packets, Decode, Packet, PacketSide,
+    PacketState, ProtocolRegistries,
+};
 
 pub struct ProtocolPlugin;
 impl Plugin for ProtocolPlugin {
@@ -11,27 +14,58 @@ impl Plugin for ProtocolPlugin {
         // TODO: only registser_type and init when connecting to a server
         app.init_resource::<ProtocolRegistries>();
         app.register_type::<PacketDecoder>();
-        app.init_resource::<PacketDecoder>();
         app.register_type::<PacketEncoder>();
         app.init_resource::<PacketEncoder>();
         app.add_event::<PacketEvent>();
+        packets::register_typed_packets(app);
+        app.add_systems(PreUpdate, dispatch_packets.in_set(DispatchPackets));
     }
 }
 
+/// Systems that turn `PacketEvent`s into `Typed` events. Systems sending
+/// `PacketEvent`s in `PreUpdate` should run before this set.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DispatchPackets;
+
 #[derive(Event, Debug)]
 pub struct PacketEvent {
     pub timestamp: Instant,
+    pub state: PacketState,
     pub id: i32,
     pub data: Bytes,
 }
 
+/// A decoded clientbound packet, sent for every `PacketEvent` whose state and ID
+/// match `P`. Only packets without borrowed fields can be dispatched this way,
+/// the others have to be decoded from the `PacketEvent` directly.
+#[derive(Event, Debug, Deref)]
+pub struct Typed<P> {
+    pub timestamp: Instant,
+    #[deref]
+    pub packet: P,
+}
+
+impl<P> Typed<P> {
+    pub fn new(timestamp: Instant, packet: P) -> Self {
+        Self { timestamp, packet }
+    }
+}
+
+fn dispatch_packets(world: &mut World, mut reader: Local<ManualEventReader<PacketEvent>>) {
+    world.resource_scope(|world, events: Mut<Events<PacketEvent>>| {
+        for event in reader.read(&events) {
+            packets::dispatch_client(event, world);
+        }
+    });
+}
+
 impl PacketEvent {
     #[inline]
     pub fn decode<'a, P>(&'a self) -> Option<P>
     where
         P: Packet + Decode<'a>,
     {
-        if self.id == P::ID {
+        if self.state == P::STATE && P::SIDE == PacketSide::Client && self.id == P::ID {
             let mut r = &self.data[..];
 
             match P::decode(&mut r) {
@@ -57,3 +91,57 @@ impl PacketEvent {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+
+    use super::*;
+    use crate::{
+        packets::{ClientKeepAlivePlay, KeepAliveClientConfiguration},
+        Encode,
+    };
+
+    fn event(state: PacketState, id: i32, pkt: &impl Encode) -> PacketEvent {
+        let mut buf = BytesMut::new();
+        pkt.encode(&mut buf).unwrap();
+
+        PacketEvent {
+            timestamp: Instant::now(),
+            state,
+            id,
+            data: buf.freeze(),
+        }
+    }
+
+    #[test]
+    fn dispatch_by_state() {
+        let mut app = App::new();
+        app.add_event::<PacketEvent>();
+        packets::register_typed_packets(&mut app);
+        app.add_systems(Update, dispatch_packets);
+
+        let keep_alive = ClientKeepAlivePlay { id: 42 };
+        app.world_mut().send_event(event(
+            PacketState::Play,
+            ClientKeepAlivePlay::ID,
+            &keep_alive,
+        ));
+        // Same ID as the configuration keep alive, but sent while playing.
+        app.world_mut().send_event(event(
+            PacketState::Play,
+            KeepAliveClientConfiguration::ID,
+            &keep_alive,
+        ));
+        app.update();
+
+        let play = app.world().resource::<Events<Typed<ClientKeepAlivePlay>>>();
+        let play: Vec<_> = play.get_reader().read(play).map(|pkt| pkt.id).collect();
+        assert_eq!(play, [42]);
+
+        let configuration = app
+            .world()
+            .resource::<Events<Typed<KeepAliveClientConfiguration>>>();
+        assert!(configuration.is_empty());
+    }
+}
@@ -0,0 +1,29 @@
+/// Encodes `$val`, decodes the bytes as `$ty` and checks that the whole input
+/// was consumed, that the decoded value looks the same and that encoding it
+/// again gives the same bytes. `Debug` is compared instead of values since
+/// packets don't implement `PartialEq` and floats may be NaN.
+macro_rules! assert_round_trip {
+    ($ty:ty, $val:expr) => {{
+        #[allow(unused_imports)]
+        use $crate::Encode as _;
+
+        let val = $val;
+
+        let mut buf = ::bytes::BytesMut::new();
+        val.encode(&mut buf).expect("failed to encode");
+
+        let mut rdr = &buf[..];
+        let decoded = <$ty as $crate::Decode>::decode(&mut rdr).expect("failed to decode");
+        assert!(
+            rdr.is_empty(),
+            "{} bytes left after decoding {}",
+            rdr.len(),
+            stringify!($ty)
+        );
+        assert_eq!(format!("{val:?}"), format!("{decoded:?}"));
+
+        let mut again = ::bytes::BytesMut::new();
+        decoded.encode(&mut again).expect("failed to encode again");
+        assert_eq!(buf, again);
+    }};
+}
@@ -1,5 +1,6 @@
+use heck::ToSnakeCase;
 use proc_macro2::TokenStream;
-use quote::quote;
+use quote::{format_ident, quote};
 use syn::{
     braced,
     parse::{Parse, ParseStream},
@@ -18,10 +19,21 @@ pub(super) fn define_protocol(tokens: TokenStream) -> Result<TokenStream> {
         pub const PROTOCOL_VERSION: i32 = #protocol_version;
     });
 
+    // Clientbound packets without a lifetime, grouped by state, which can be
+    // sent as `Typed` events.
+    let mut typed: Vec<(Ident, Vec<(LitInt, Ident)>)> = vec![];
+    let mut round_trip_tests = vec![];
+    let mut all_packets = vec![];
+
     for state in input.states {
         let state_ident = &state.state;
         for side in state.sides {
             let side_ident = &side.side;
+            let clientbound = side_ident == "Client";
+            if clientbound {
+                typed.push((state_ident.clone(), vec![]));
+            }
+
             for packet in side.packets {
                 let packet_ident = &packet.name;
                 let packet_id = &packet.id;
@@ -33,6 +45,10 @@ pub(super) fn define_protocol(tokens: TokenStream) -> Result<TokenStream> {
                 {
                     Some(quote! {<'a>})
                 } else {
+                    if clientbound {
+                        let (_, packets) = typed.last_mut().unwrap();
+                        packets.push((packet_id.clone(), packet_ident.clone()));
+                    }
                     None
                 };
 
@@ -48,7 +64,19 @@ pub(super) fn define_protocol(tokens: TokenStream) -> Result<TokenStream> {
                     })
                     .collect();
 
+                let test_ident = format_ident!("{}", packet_ident.to_string().to_snake_case());
+                round_trip_tests.push(quote! {
+                    #[test]
+                    fn #test_ident(seed in proptest::collection::vec(any::<u8>(), 0..1024)) {
+                        if let Ok(pkt) = #packet_ident::arbitrary(&mut Unstructured::new(&seed)) {
+                            assert_round_trip!(#packet_ident, pkt);
+                        }
+                    }
+                });
+                all_packets.push(packet_ident.clone());
+
                 generated.extend(quote! {
+                    #[cfg_attr(any(test, feature = "arbitrary"), derive(arbitrary::Arbitrary))]
                     #[derive(crate::Encode, crate::Decode, crate::Packet, Debug)]
                     #[packet(id = #packet_id, side = crate::PacketSide::#side_ident, state = crate::PacketState::#state_ident)]
                     pub struct #packet_ident #lifetime {
@@ -59,6 +87,77 @@ pub(super) fn define_protocol(tokens: TokenStream) -> Result<TokenStream> {
         }
     }
 
+    let typed_packets = typed
+        .iter()
+        .flat_map(|(_, packets)| packets)
+        .map(|(_, packet_ident)| packet_ident);
+
+    let state_arms = typed.iter().map(|(state_ident, packets)| {
+        let packet_arms = packets.iter().map(|(packet_id, packet_ident)| {
+            quote! {
+                #packet_id => {
+                    if let Some(pkt) = event.decode::<#packet_ident>() {
+                        world.send_event(crate::Typed::new(event.timestamp, pkt));
+                    }
+                }
+            }
+        });
+
+        quote! {
+            crate::PacketState::#state_ident => match event.id {
+                #(#packet_arms)*
+                _ => {}
+            },
+        }
+    });
+
+    generated.extend(quote! {
+        /// Registers a `Typed` event for every clientbound packet without
+        /// borrowed fields.
+        pub fn register_typed_packets(app: &mut ::bevy::prelude::App) {
+            #(app.add_event::<crate::Typed<#typed_packets>>();)*
+        }
+
+        /// Tries to decode `frame` as every packet with its ID, in every state
+        /// and on both sides, and returns how many succeeded. A frame doesn't
+        /// say which packet it holds, so this is how the fuzz targets reach
+        /// every decoder.
+        pub fn decode_all(frame: &crate::PacketFrame) -> usize {
+            let mut decoded = 0;
+            #(
+                if frame.id == <#all_packets as crate::Packet>::ID
+                    && frame.decode::<#all_packets>().is_ok()
+                {
+                    decoded += 1;
+                }
+            )*
+            decoded
+        }
+
+        #[cfg(test)]
+        mod round_trip_tests {
+            use arbitrary::{Arbitrary, Unstructured};
+            use proptest::prelude::*;
+
+            use super::*;
+
+            proptest! {
+                #(#round_trip_tests)*
+            }
+        }
+
+        /// Decodes a clientbound `event` with the packets of its state and
+        /// sends it as a `Typed` event. Packets with borrowed fields are
+        /// skipped.
+        pub fn dispatch_client(event: &crate::PacketEvent, world: &mut ::bevy::prelude::World) {
+            #[allow(unreachable_patterns)]
+            match event.state {
+                #(#state_arms)*
+                _ => {}
+            }
+        }
+    });
+
     Ok(generated)
 }
 
@@ -72,9 +72,8 @@ impl<'de> Deserialize<'de> for BlockStateVariant {
             where
                 M: MapAccess<'de>,
             {
-                let mut variant =
+                let variant =
                     BlockStateModel::deserialize(de::value::MapAccessDeserializer::new(map))?;
-                variant.weight = 1.0;
                 Ok(BlockStateVariant(vec![variant]))
             }
 
@@ -82,17 +81,9 @@ impl<'de> Deserialize<'de> for BlockStateVariant {
             where
                 A: SeqAccess<'de>,
             {
-                let mut variants = Vec::<BlockStateModel>::deserialize(
+                let variants = Vec::<BlockStateModel>::deserialize(
                     de::value::SeqAccessDeserializer::new(seq),
                 )?;
-                let weight = 1.0 / variants.len() as f32;
-
-                for variant in &mut variants {
-                    if variant.weight == 0.0 {
-                        variant.weight = weight;
-                    }
-                }
-
                 Ok(BlockStateVariant(variants))
             }
         }
@@ -110,8 +101,26 @@ pub struct BlockStateModel {
     pub y: f32,
     #[serde(default)]
     pub uvlock: bool,
-    #[serde(default)]
-    pub weight: f32,
+    /// Chance of being picked among the models of a list, relative to the
+    /// others. At least 1, like vanilla.
+    #[serde(default = "default_weight", deserialize_with = "deserialize_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+fn deserialize_weight<'de, D>(deserializer: D) -> Result<u32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let weight = u32::deserialize(deserializer)?;
+    if weight == 0 {
+        return Err(de::Error::custom("invalid weight 0, must be positive"));
+    }
+
+    Ok(weight)
 }
 
 #[derive(Deserialize, Debug)]
@@ -167,3 +176,24 @@ where
 
     deserializer.deserialize_any(ApplyVisitor)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weights() {
+        let models: BlockStateVariant =
+            serde_json::from_str(r#"[{ "model": "a" }, { "model": "b", "weight": 3 }]"#).unwrap();
+        let weights: Vec<_> = models.iter().map(|model| model.weight).collect();
+        assert_eq!(weights, [1, 3]);
+
+        assert!(
+            serde_json::from_str::<BlockStateModel>(r#"{ "model": "a", "weight": 0 }"#).is_err()
+        );
+        assert!(serde_json::from_str::<BlockState>(
+            r#"{ "variants": { "": [{ "model": "a", "weight": 0 }] } }"#
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,283 @@
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{Indices, PrimitiveTopology},
+        render_asset::RenderAssetUsages,
+    },
+};
+
+use crate::direction::Direction;
+
+/// Triangles with positions and atlas UVs, kept on the CPU so that chunk
+/// meshes can be stitched together from them.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MeshData {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    /// Whether the vertex is darker depending on the direction it faces, from
+    /// the `shade` of its element.
+    pub shade: Vec<bool>,
+    /// Whether the vertex is coloured by the block, like grass by its biome,
+    /// from the `tintindex` of its face.
+    pub tinted: Vec<bool>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Appends the triangles of `other`, moved by `translation`.
+    pub fn append(&mut self, other: &MeshData, translation: Vec3) {
+        let offset = self.positions.len() as u32;
+
+        self.positions.extend(
+            other
+                .positions
+                .iter()
+                .map(|pos| (Vec3::from(*pos) + translation).to_array()),
+        );
+        self.normals.extend_from_slice(&other.normals);
+        self.uvs.extend_from_slice(&other.uvs);
+        self.shade.extend_from_slice(&other.shade);
+        self.tinted.extend_from_slice(&other.tinted);
+        self.indices
+            .extend(other.indices.iter().map(|i| i + offset));
+    }
+
+    pub fn transformed(&self, transform: &Transform) -> MeshData {
+        MeshData {
+            positions: self
+                .positions
+                .iter()
+                .map(|pos| transform.transform_point(Vec3::from(*pos)).to_array())
+                .collect(),
+            normals: self
+                .normals
+                .iter()
+                .map(|normal| (transform.rotation * Vec3::from(*normal)).to_array())
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    pub fn to_mesh(&self) -> Mesh {
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.positions.clone())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals.clone())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs.clone())
+        .with_inserted_indices(Indices::U32(self.indices.clone()))
+    }
+}
+
+/// Corners of the `direction` face of a unit cube, in the order the texture
+/// corners (0, 0), (0, 1), (1, 1) and (1, 0) are mapped to them.
+pub fn face_corners(direction: Direction) -> [Vec3; 4] {
+    let corners = match direction {
+        Direction::Up => [[0, 1, 0], [0, 1, 1], [1, 1, 1], [1, 1, 0]],
+        Direction::Down => [[0, 0, 1], [0, 0, 0], [1, 0, 0], [1, 0, 1]],
+        Direction::North => [[1, 1, 0], [1, 0, 0], [0, 0, 0], [0, 1, 0]],
+        Direction::South => [[0, 1, 1], [0, 0, 1], [1, 0, 1], [1, 1, 1]],
+        Direction::East => [[1, 1, 1], [1, 0, 1], [1, 0, 0], [1, 1, 0]],
+        Direction::West => [[0, 1, 0], [0, 0, 0], [0, 0, 1], [0, 1, 1]],
+    };
+
+    corners.map(|corner| IVec3::from_array(corner).as_vec3())
+}
+
+/// Geometry of a block model or block state, in block units. Faces with a
+/// `cullface` go in the bucket of that direction and are only drawn when the
+/// neighbour on that side doesn't cover them.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BlockGeometry {
+    pub always: MeshData,
+    /// Indexed by `Direction::index`.
+    pub cullable: [MeshData; 6],
+    /// Whether the face on each side covers the whole side with an opaque
+    /// texture, hiding the faces of the neighbour that touch it.
+    pub opaque_faces: [bool; 6],
+    /// Atlas rectangle of each side, as min and size, when the geometry is a
+    /// plain cube with one whole texture per side. The faces of such blocks
+    /// can be merged by greedy meshing.
+    pub cube_faces: Option<[Vec4; 6]>,
+    /// Whether the corners of the faces are darkened by the blocks around
+    /// them, from `ambientocclusion` in the model.
+    pub ambient_occlusion: bool,
+}
+
+impl BlockGeometry {
+    pub fn is_empty(&self) -> bool {
+        self.always.is_empty() && self.cullable.iter().all(MeshData::is_empty)
+    }
+
+    /// Whether the block hides all of its neighbours' faces and darkens
+    /// their corners.
+    pub fn is_opaque_cube(&self) -> bool {
+        self.opaque_faces == [true; 6]
+    }
+
+    pub fn cullable(&self, direction: Direction) -> &MeshData {
+        &self.cullable[direction.index()]
+    }
+
+    pub fn is_opaque(&self, direction: Direction) -> bool {
+        self.opaque_faces[direction.index()]
+    }
+
+    /// Adds the faces of `other`, like the parts of a multipart block state.
+    pub fn append(&mut self, other: &BlockGeometry) {
+        (self.cube_faces, self.ambient_occlusion) = match (self.is_empty(), other.is_empty()) {
+            (true, _) => (other.cube_faces, other.ambient_occlusion),
+            (_, true) => (self.cube_faces, self.ambient_occlusion),
+            _ => (None, self.ambient_occlusion && other.ambient_occlusion),
+        };
+
+        self.always.append(&other.always, Vec3::ZERO);
+        for direction in Direction::ALL {
+            let index = direction.index();
+            self.cullable[index].append(&other.cullable[index], Vec3::ZERO);
+            self.opaque_faces[index] |= other.opaque_faces[index];
+        }
+    }
+
+    /// Moves every face by `transform`. The buckets and opaque faces follow
+    /// the rotation, so the north bucket of a model rotated by 90° around Y
+    /// becomes the east one. Rotated cubes aren't merged, their textures
+    /// would need to turn with them.
+    pub fn transformed(&self, transform: &Transform) -> BlockGeometry {
+        let mut geometry = BlockGeometry {
+            always: self.always.transformed(transform),
+            cube_faces: self
+                .cube_faces
+                .filter(|_| transform.rotation.is_near_identity()),
+            ambient_occlusion: self.ambient_occlusion,
+            ..default()
+        };
+
+        for direction in Direction::ALL {
+            let rotated = Direction::from_normal(transform.rotation * direction.normal().as_vec3());
+            geometry.cullable[rotated.index()]
+                .append(&self.cullable(direction).transformed(transform), Vec3::ZERO);
+            geometry.opaque_faces[rotated.index()] |= self.is_opaque(direction);
+        }
+
+        geometry
+    }
+
+    /// Every face merged into one mesh, as drawn without any neighbour.
+    pub fn to_mesh(&self) -> Mesh {
+        let mut data = self.always.clone();
+        for faces in &self.cullable {
+            data.append(faces, Vec3::ZERO);
+        }
+
+        data.to_mesh()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Quad covering the `direction` side of a unit cube.
+    fn side(direction: Direction) -> MeshData {
+        MeshData {
+            positions: face_corners(direction)
+                .map(|corner| corner.to_array())
+                .to_vec(),
+            normals: vec![direction.normal().as_vec3().to_array(); 4],
+            uvs: vec![[0.0, 0.0]; 4],
+            shade: vec![true; 4],
+            tinted: vec![false; 4],
+            indices: vec![0, 1, 2, 0, 2, 3],
+        }
+    }
+
+    /// Full block with every face culled by its side, like stone, with the
+    /// texture at `rect` on every side.
+    pub(crate) fn cube(rect: Vec4) -> BlockGeometry {
+        BlockGeometry {
+            always: MeshData::default(),
+            cullable: Direction::ALL.map(side),
+            opaque_faces: [true; 6],
+            cube_faces: Some([rect; 6]),
+            ambient_occlusion: true,
+        }
+    }
+
+    #[test]
+    fn append() {
+        let mut geometry = BlockGeometry::default();
+
+        geometry.append(&BlockGeometry {
+            always: side(Direction::Up),
+            ..default()
+        });
+        geometry.append(&cube(Vec4::ONE));
+
+        assert_eq!(geometry.always.positions.len(), 4);
+        assert_eq!(geometry.cullable(Direction::Up).positions.len(), 4);
+        assert_eq!(geometry.cullable(Direction::Up).indices[4], 2);
+        assert_eq!(geometry.opaque_faces, [true; 6]);
+        assert_eq!(geometry.to_mesh().count_vertices(), 7 * 4);
+        assert_eq!(geometry.cube_faces, None);
+        // Without ambient occlusion in the first part.
+        assert!(!geometry.ambient_occlusion);
+
+        let mut geometry = BlockGeometry::default();
+        geometry.append(&cube(Vec4::ONE));
+        geometry.append(&BlockGeometry::default());
+        assert_eq!(geometry.cube_faces, Some([Vec4::ONE; 6]));
+        assert!(geometry.ambient_occlusion);
+        assert!(geometry.is_opaque_cube());
+    }
+
+    #[test]
+    fn transformed() {
+        // Slab-like geometry: only the north face is full.
+        let geometry = BlockGeometry {
+            always: side(Direction::Up),
+            cullable: Direction::ALL.map(|direction| match direction {
+                Direction::North | Direction::Down => side(direction),
+                _ => MeshData::default(),
+            }),
+            opaque_faces: Direction::ALL.map(|direction| direction == Direction::North),
+            cube_faces: None,
+            ambient_occlusion: true,
+        };
+
+        let mut transform = Transform::default();
+        transform.rotate_around(
+            Vec3::splat(0.5),
+            Quat::from_axis_angle(Vec3::Y, -90f32.to_radians()),
+        );
+        let rotated = geometry.transformed(&transform);
+
+        assert!(rotated.cullable(Direction::North).is_empty());
+        assert!(rotated.is_opaque(Direction::East));
+        assert!(!rotated.is_opaque(Direction::North));
+        assert_eq!(rotated.cullable(Direction::Down).positions.len(), 4);
+
+        // The faces in the east bucket are the ones at x = 1, facing east.
+        let east = rotated.cullable(Direction::East);
+        for normal in &east.normals {
+            assert!(Vec3::from(*normal).abs_diff_eq(Vec3::X, 1e-5), "{normal:?}");
+        }
+        for pos in &east.positions {
+            assert!(
+                (pos[0] - 1.0).abs() < 1e-5,
+                "{pos:?} is not on the east side"
+            );
+        }
+        assert_eq!(rotated.always.positions.len(), 4);
+
+        assert!(cube(Vec4::ONE).transformed(&transform).cube_faces.is_none());
+        let scale = Transform::from_scale(Vec3::splat(0.5));
+        assert!(cube(Vec4::ONE).transformed(&scale).cube_faces.is_some());
+    }
+}
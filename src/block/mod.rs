@@ -1,40 +1,168 @@
-use std::{collections::VecDeque, fs};
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fs,
+    sync::Arc,
+};
 
 use bevy::{
     prelude::*,
-    render::{
-        mesh::{Indices, PrimitiveTopology},
-        render_asset::RenderAssetUsages,
-    },
-    utils::HashMap,
+    utils::{HashMap, HashSet},
 };
 use bevy_inspector_egui::prelude::*;
-use bevy_mod_mesh_tools::{mesh_append, mesh_with_transform};
+use indexmap::IndexMap;
 
 use crate::{
-    axis::Axis, block::blockstate::BlockStateMultipartWhen, fly_camera::FlyCamera, state::AppState,
+    block::blockstate::BlockStateMultipartWhen,
+    fly_camera::FlyCamera,
+    resource_pack::{resource_id, resource_path, ResourcePacks},
+    state::AppState,
     texture::TextureRegistry,
 };
 
 use self::{
     blockstate::{BlockDefinition, BlockState, BlockStateModel},
-    model::{build_block_mesh, parse_block_model, BlockModel},
+    geometry::BlockGeometry,
+    model::{
+        build_block_geometry, missing_model, parse_block_model, BlockModel, StateRotation,
+        MISSING_MODEL,
+    },
+    tint::{block_tint, Tint},
+    variant::BlockVariants,
 };
 
 pub mod blockstate;
+pub mod geometry;
 pub mod model;
+pub mod tint;
+pub mod variant;
 
 #[derive(Reflect, Resource, InspectorOptions, Debug, Default)]
 #[reflect(Resource, InspectorOptions)]
 pub struct BlockModelRegistry {
     pub models: HashMap<String, BlockModel>,
-    pub meshes: HashMap<String, Handle<Mesh>>,
+    #[reflect(ignore)]
+    pub geometry: HashMap<String, BlockGeometry>,
 }
 
 #[derive(Resource, Debug, Default)]
 pub struct BlockStateRegistry {
     pub block_definitions: HashMap<String, BlockDefinition>,
     pub blockstates_meshes: HashMap<i32, Handle<Mesh>>,
+    /// Faces of each state split by cullface, used to mesh chunks. Shared
+    /// with the meshing tasks.
+    pub blockstates_geometry: Arc<HashMap<i32, BlockVariants>>,
+    /// Name of the block each state ID belongs to.
+    pub state_blocks: HashMap<i32, String>,
+    /// Light emitted by each state that glows.
+    pub light_emission: HashMap<i32, u8>,
+    /// Colour of the tinted faces of each state that has some. Shared with
+    /// the meshing tasks.
+    pub tints: Arc<HashMap<i32, Tint>>,
+}
+
+impl BlockStateRegistry {
+    pub fn new(block_definitions: HashMap<String, BlockDefinition>) -> Self {
+        let state_blocks = block_definitions
+            .iter()
+            .flat_map(|(block, definition)| {
+                definition.states.keys().map(move |id| (*id, block.clone()))
+            })
+            .collect();
+        let light_emission = block_definitions
+            .iter()
+            .flat_map(|(block, definition)| {
+                definition.states.iter().filter_map(|(id, state)| {
+                    let emission = light_emission(block, &state.properties);
+                    (emission > 0).then_some((*id, emission))
+                })
+            })
+            .collect();
+        let tints = block_definitions
+            .iter()
+            .flat_map(|(block, definition)| {
+                definition
+                    .states
+                    .iter()
+                    .filter_map(|(id, state)| Some((*id, block_tint(block, &state.properties)?)))
+            })
+            .collect();
+
+        Self {
+            block_definitions,
+            blockstates_meshes: HashMap::new(),
+            blockstates_geometry: Arc::default(),
+            state_blocks,
+            light_emission,
+            tints: Arc::new(tints),
+        }
+    }
+
+    pub fn contains(&self, id: i32) -> bool {
+        self.state_blocks.contains_key(&id)
+    }
+
+    pub fn is_air(&self, id: i32) -> bool {
+        matches!(
+            self.state_blocks.get(&id).map(String::as_str),
+            Some("minecraft:air" | "minecraft:cave_air" | "minecraft:void_air")
+        )
+    }
+
+    pub fn light_emission(&self, id: i32) -> u8 {
+        self.light_emission.get(&id).copied().unwrap_or(0)
+    }
+
+    /// Whether the state stops light, which only full opaque cubes do for
+    /// now.
+    pub fn blocks_light(&self, id: i32) -> bool {
+        self.blockstates_geometry
+            .get(&id)
+            .is_some_and(|variants| variants.first().is_opaque_cube())
+    }
+}
+
+/// Light emitted by a block state. The block reports don't have it, so it
+/// comes from this table of the usual light sources. Blocks with a `lit`
+/// property only glow when lit.
+fn light_emission(block: &str, properties: &IndexMap<String, String>) -> u8 {
+    if properties.get("lit").is_some_and(|lit| lit != "true") {
+        return 0;
+    }
+
+    match block.strip_prefix("minecraft:").unwrap_or(block) {
+        "beacon"
+        | "campfire"
+        | "conduit"
+        | "copper_bulb"
+        | "end_gateway"
+        | "end_portal"
+        | "fire"
+        | "glowstone"
+        | "jack_o_lantern"
+        | "lantern"
+        | "lava"
+        | "ochre_froglight"
+        | "pearlescent_froglight"
+        | "redstone_lamp"
+        | "sea_lantern"
+        | "shroomlight"
+        | "verdant_froglight"
+        | "waxed_copper_bulb" => 15,
+        "end_rod" | "torch" | "wall_torch" => 14,
+        "blast_furnace" | "furnace" | "smoker" => 13,
+        "nether_portal" => 11,
+        "crying_obsidian" | "soul_campfire" | "soul_fire" | "soul_lantern" | "soul_torch"
+        | "soul_wall_torch" => 10,
+        "deepslate_redstone_ore" | "redstone_ore" => 9,
+        "enchanting_table"
+        | "ender_chest"
+        | "glow_lichen"
+        | "redstone_torch"
+        | "redstone_wall_torch" => 7,
+        "magma_block" => 3,
+        "brewing_stand" | "brown_mushroom" | "dragon_egg" | "end_portal_frame" => 1,
+        _ => 0,
+    }
 }
 
 pub struct BlockPlugin;
@@ -46,58 +174,80 @@ impl Plugin for BlockPlugin {
             OnEnter(AppState::LoadingModels),
             load_states.after(load_models),
         );
-        app.add_systems(OnEnter(AppState::LoadingModels), spawn.after(load_states));
+        app.add_systems(
+            OnEnter(AppState::LoadingModels),
+            spawn.after(load_states).run_if(run_once()),
+        );
     }
 }
 
 fn load_models(
     mut commands: Commands,
-    mut meshes_res: ResMut<Assets<Mesh>>,
+    packs: Res<ResourcePacks>,
     texture_registry: Res<TextureRegistry>,
 ) {
     let mut models = HashMap::new();
-    let mut meshes = HashMap::new();
-    let blocks_path = "assets/assets/minecraft/models/block";
-
-    let paths = fs::read_dir(blocks_path).unwrap();
+    let mut geometry = HashMap::new();
 
     let mut queue: VecDeque<(String, serde_json::Value)> = VecDeque::new();
 
-    for path in paths {
-        let path = path.unwrap().path();
-        let file_path = path.to_str().unwrap();
-        let json_str = fs::read_to_string(file_path).unwrap();
-        let json_value: serde_json::Value = serde_json::from_str(&json_str).unwrap();
-
-        queue.push_back((
-            path.file_stem().unwrap().to_str().unwrap().to_owned(),
-            json_value,
-        ));
+    for (ident, path) in packs.list("models", "block", ".json") {
+        let json_value = packs
+            .read_to_string(&path)
+            .unwrap()
+            .and_then(|json_str| Ok(serde_json::from_str(&json_str)?));
+        match json_value {
+            Ok(json_value) => queue.push_back((ident, json_value)),
+            Err(e) => warn!("failed to load model {ident}: {e:#}"),
+        }
     }
-
-    while !queue.is_empty() {
-        let (ident, value) = queue.pop_front().unwrap();
-        if let Some(parent) = value.get("parent") {
-            if !models.contains_key(parent.as_str().unwrap().split("/").last().unwrap()) {
-                queue.push_back((ident.clone(), value.clone()));
+    // Models whose parent isn't in any pack are parsed without it, instead of
+    // waiting for it forever. So are models in a cycle of parents, once the
+    // whole queue went round without parsing anything.
+    let mut idents: HashSet<String> = queue.iter().map(|(ident, _)| ident.clone()).collect();
+    let mut waiting = 0;
+
+    while let Some((ident, value)) = queue.pop_front() {
+        if let Some(parent) = value.get("parent").and_then(serde_json::Value::as_str) {
+            let parent = resource_id(parent);
+            if idents.contains(&parent) && !models.contains_key(&parent) && waiting <= queue.len() {
+                queue.push_back((ident, value));
+                waiting += 1;
                 continue;
             }
         }
+        waiting = 0;
 
-        let model = parse_block_model(&models, &value);
-        let mesh = build_block_mesh(&model, &texture_registry);
-        let mesh_handle = meshes_res.add(mesh);
-
-        models.insert(ident.clone(), model.clone());
-        meshes.insert(ident.clone(), mesh_handle);
+        let model = match parse_block_model(&models, &value) {
+            Ok(model) => model,
+            Err(e) => {
+                warn!("failed to parse model {ident}: {e:#}");
+                idents.remove(&ident);
+                continue;
+            }
+        };
+        geometry.insert(
+            ident.clone(),
+            build_block_geometry(&model, default(), &texture_registry),
+        );
+        models.insert(ident, model);
     }
 
-    commands.insert_resource(BlockModelRegistry { models, meshes });
+    let missing = missing_model();
+    geometry.insert(
+        MISSING_MODEL.to_string(),
+        build_block_geometry(&missing, default(), &texture_registry),
+    );
+    models.insert(MISSING_MODEL.to_string(), missing);
+
+    commands.insert_resource(BlockModelRegistry { models, geometry });
 }
 
 fn load_states(
     mut commands: Commands,
+    packs: Res<ResourcePacks>,
     models: Res<BlockModelRegistry>,
+    texture_registry: Res<TextureRegistry>,
     mut meshes_res: ResMut<Assets<Mesh>>,
 ) {
     let data = fs::read_to_string("assets/reports/blocks.json").unwrap();
@@ -107,48 +257,70 @@ fn load_states(
         serde_json::from_value(value).unwrap();
 
     // TODO: Load individual blockstate
-    //
-    // TODO: Generate meshes and keep them seperate depending on cullface
-    // maybe put non cullable faces in one mesh and the cullable ones in a HashMap
-    // take neede cullable faces and merge them with the main faces and generate a final mesh on
-    // demand
     let mut blockstates_meshes = HashMap::new();
+    let mut blockstates_geometry = HashMap::new();
+    let mut missing = BTreeSet::new();
     for (block, blockstate_definition) in block_definitions.iter() {
-        let data = fs::read_to_string(format!(
-            "assets/assets/minecraft/blockstates/{}.json",
-            block.clone().split_off("minecraft:".len())
-        ))
-        .unwrap();
+        let path = resource_path(block, "blockstates", ".json");
+        let data = match packs.read_to_string(&path) {
+            Some(Ok(data)) => data,
+            Some(Err(e)) => {
+                warn!("failed to load blockstates of {block}: {e:#}");
+                continue;
+            }
+            None => {
+                warn!("missing blockstates of {block}");
+                continue;
+            }
+        };
+
+        let blockstate: BlockState = match serde_json::from_str(&data) {
+            Ok(blockstate) => blockstate,
+            Err(e) => {
+                warn!("failed to parse blockstates of {block}: {e}");
+                continue;
+            }
+        };
+        if let BlockState::Variants(variants) = &blockstate {
+            let malformed = variants
+                .keys()
+                .filter(|key| !key.is_empty())
+                .find(|key| key.split(',').any(|pair| !pair.contains('=')));
+            if let Some(key) = malformed {
+                warn!("bad variant {key:?} in blockstates of {block}");
+                continue;
+            }
+        }
 
         for (id, state) in &blockstate_definition.states {
-            let mut states = vec![];
-            match serde_json::from_str(&data).unwrap() {
+            // Models to choose one of per block, for each part of the state.
+            let mut parts: Vec<Vec<BlockStateModel>> = vec![];
+            let mut multipart = false;
+            match &blockstate {
                 BlockState::Variants(variants) => {
-                    'variants: for (variant_key, variant) in &variants {
+                    'variants: for (variant_key, variant) in variants {
                         if variant_key.is_empty() {
-                            states = variant.0.clone();
+                            parts = vec![variant.0.clone()];
                             break 'variants;
                         }
 
                         let variant_properties: HashMap<&str, &str> = variant_key
                             .split(',')
-                            .map(|pair| {
-                                let mut iter = pair.split('=');
-                                (iter.next().unwrap(), iter.next().unwrap())
-                            })
+                            .filter_map(|pair| pair.split_once('='))
                             .collect();
 
                         if variant_properties.iter().all(|(key, value)| {
                             state.properties.get(*key).map_or(false, |v| v == value)
                         }) {
-                            states = variant.0.clone();
+                            parts = vec![variant.0.clone()];
                             break 'variants;
                         }
                     }
                 }
-                BlockState::Multipart(multipart) => {
-                    for part in multipart {
-                        match part.when {
+                BlockState::Multipart(multipart_parts) => {
+                    multipart = true;
+                    for part in multipart_parts {
+                        match &part.when {
                             Some(when) => {
                                 if match when {
                                     BlockStateMultipartWhen::State(conditions) => conditions
@@ -185,58 +357,79 @@ fn load_states(
                                         })
                                     }
                                 } {
-                                    states.extend(part.apply);
+                                    parts.push(part.apply.clone());
                                 }
                             }
-                            None => states.extend(part.apply),
+                            None => parts.push(part.apply.clone()),
                         }
                     }
                 }
             }
 
-            // TODO: Generate mesh
-            let mut mesh = Mesh::new(
-                PrimitiveTopology::TriangleList,
-                RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
-            )
-            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, Vec::<[f32; 3]>::new())
-            .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, Vec::<[f32; 2]>::new())
-            .with_inserted_indices(Indices::U32(Vec::<u32>::new()));
-
-            for state in &states {
-                let model_handle = models
-                    .meshes
-                    .get(&state.model.replace("minecraft:block/", ""))
-                    .expect(format!("model {} mesh should be present", &state.model).as_str());
-                let model_mesh = meshes_res.get(model_handle).unwrap().clone();
-
-                let (axis, angle) = if state.x != 0.0 {
-                    (Axis::X, -state.x)
-                } else {
-                    (Axis::Y, -state.y)
-                };
-                let mut transform = Transform::default();
-                transform.rotate_around(
-                    Vec3::splat(0.5),
-                    Quat::from_axis_angle(axis.into(), angle.to_radians()),
-                );
-
-                let model_mesh = mesh_with_transform(&model_mesh, &transform).unwrap();
-
-                mesh_append(&mut mesh, &model_mesh).unwrap();
-            }
-
-            let new_mesh_handle = meshes_res.add(mesh);
-            blockstates_meshes.insert(*id, new_mesh_handle);
+            let parts = parts
+                .iter()
+                .map(|choices| {
+                    choices
+                        .iter()
+                        .map(|state| {
+                            (
+                                state.weight,
+                                state_model_geometry(
+                                    &models,
+                                    &texture_registry,
+                                    state,
+                                    &mut missing,
+                                ),
+                            )
+                        })
+                        .collect()
+                })
+                .collect();
+            let variants = BlockVariants::new(multipart, parts);
+
+            blockstates_meshes.insert(*id, meshes_res.add(variants.first().to_mesh()));
+            blockstates_geometry.insert(*id, variants);
         }
     }
 
+    if !missing.is_empty() {
+        let missing: Vec<_> = missing.into_iter().collect();
+        warn!("missing block models: {}", missing.join(", "));
+    }
+
     commands.insert_resource(BlockStateRegistry {
-        block_definitions,
         blockstates_meshes,
+        blockstates_geometry: Arc::new(blockstates_geometry),
+        ..BlockStateRegistry::new(block_definitions)
     })
 }
 
+/// Geometry of a model of a block state, rotated as the state says. Models
+/// are only built again when the state turns them. Models no pack has are
+/// added to `missing` and replaced by the missing model.
+fn state_model_geometry(
+    models: &BlockModelRegistry,
+    texture_registry: &TextureRegistry,
+    state: &BlockStateModel,
+    missing: &mut BTreeSet<String>,
+) -> BlockGeometry {
+    let mut id = resource_id(&state.model);
+    if !models.models.contains_key(&id) {
+        missing.insert(id);
+        id = MISSING_MODEL.to_string();
+    }
+
+    let rotation = StateRotation {
+        x: state.x,
+        y: state.y,
+        uvlock: state.uvlock,
+    };
+    if rotation.is_identity() {
+        return models.geometry[&id].clone();
+    }
+    build_block_geometry(&models.models[&id], rotation, texture_registry)
+}
+
 fn spawn(
     mut commands: Commands,
     blockstates: Res<BlockStateRegistry>,
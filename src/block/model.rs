@@ -1,17 +1,20 @@
 use bevy::prelude::*;
-use bevy::render::mesh::{Indices, PrimitiveTopology};
-use bevy::render::render_asset::RenderAssetUsages;
 use bevy::{reflect::Reflect, utils::HashMap};
-use bevy_mod_mesh_tools::{mesh_append, mesh_with_transform};
 use serde::Deserialize;
 
-use crate::texture::TextureRegistry;
 use crate::{axis::Axis, direction::Direction};
+use crate::{
+    resource_pack::resource_id,
+    texture::{TextureRegistry, DEBUG_TEXTURE},
+};
+
+use super::geometry::{face_corners, BlockGeometry, MeshData};
 
 #[derive(Reflect, Deserialize, Debug, Default, Clone)]
 pub struct BlockModel {
+    /// Inherited from the parent when missing, true by default.
     #[serde(rename = "ambientocclusion", default)]
-    ambient_occlusion: bool,
+    ambient_occlusion: Option<bool>,
     #[serde(default)]
     display: HashMap<String, ModelDisplay>,
     #[serde(default)]
@@ -38,12 +41,16 @@ pub struct ModelElement {
     to: Vec3,
     #[serde(default)]
     rotation: ModelRotation,
-    #[serde(default)]
+    #[serde(default = "default_shade")]
     shade: bool,
     #[serde(default)]
     faces: HashMap<Direction, ModelFace>,
 }
 
+fn default_shade() -> bool {
+    true
+}
+
 #[derive(Reflect, Deserialize, Debug, Default, Clone)]
 struct ModelRotation {
     #[serde(default)]
@@ -63,108 +70,233 @@ pub struct ModelFace {
     #[serde(default)]
     texture: String,
     #[serde(default)]
-    cullface: CullfaceDirection,
+    cullface: Option<Direction>,
     #[serde(default)]
     rotation: i32,
     #[serde(rename = "tintindex", default)]
-    tint_index: i32,
+    tint_index: Option<i32>,
 }
 
-#[derive(Reflect, Deserialize, Debug, Default, Clone)]
-#[serde(rename_all = "lowercase")]
-pub enum CullfaceDirection {
-    #[default]
-    Bottom,
-    Down,
-    Up,
-    North,
-    South,
-    West,
-    East,
+/// Rotation of a whole model by a block state, around the centre of the
+/// block: `x` degrees around the X axis, then `y` degrees around the Y axis.
+/// With `uvlock`, textures keep their orientation in the world instead of
+/// turning with the faces.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct StateRotation {
+    pub x: f32,
+    pub y: f32,
+    pub uvlock: bool,
+}
+
+impl StateRotation {
+    /// Clockwise when looking along the axis from its positive side, like
+    /// vanilla.
+    pub fn quat(&self) -> Quat {
+        Quat::from_rotation_y(-self.y.to_radians()) * Quat::from_rotation_x(-self.x.to_radians())
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.x % 360.0 == 0.0 && self.y % 360.0 == 0.0
+    }
+
+    pub fn transform(&self) -> Transform {
+        let mut transform = Transform::default();
+        transform.rotate_around(Vec3::splat(0.5), self.quat());
+        transform
+    }
+}
+
+/// Model of the states whose model no pack has, a cube with the debug
+/// texture like vanilla's missing model.
+pub const MISSING_MODEL: &str = "minecraft:builtin/missing";
+
+pub fn missing_model() -> BlockModel {
+    let face = |cullface: &str| serde_json::json!({ "texture": "#all", "cullface": cullface });
+    let json = serde_json::json!({
+        "textures": { "all": "block/debug" },
+        "elements": [{
+            "from": [0, 0, 0],
+            "to": [16, 16, 16],
+            "faces": {
+                "down": face("down"),
+                "up": face("up"),
+                "north": face("north"),
+                "south": face("south"),
+                "west": face("west"),
+                "east": face("east")
+            }
+        }]
+    });
+
+    parse_block_model(&HashMap::new(), &json).expect("the missing model should be valid")
 }
 
 pub fn parse_block_model(
     models: &HashMap<String, BlockModel>,
     json: &serde_json::Value,
-) -> BlockModel {
-    let new_model: BlockModel = serde_json::from_value(json.clone()).unwrap();
-
-    let parent = models.get(
-        json.get("parent")
-            .unwrap_or(&serde_json::Value::String("".to_string()))
-            .as_str()
-            .unwrap()
-            .split("/")
-            .last()
-            .unwrap_or(""),
-    );
+) -> anyhow::Result<BlockModel> {
+    let mut new_model: BlockModel = serde_json::from_value(json.clone())?;
+    for element in &mut new_model.elements {
+        let (from, to) = (element.from / 16.0, element.to / 16.0);
+        for (direction, face) in element.faces.iter_mut() {
+            if face.uv == Vec4::ZERO {
+                // The element's projection on the face, like vanilla.
+                let (a, b) = (face_uv(*direction, from), face_uv(*direction, to));
+                let (min, max) = (a.min(b) * 16.0, a.max(b) * 16.0);
+                face.uv = Vec4::new(min.x, min.y, max.x, max.y);
+            }
+        }
+    }
+
+    let parent = json
+        .get("parent")
+        .and_then(serde_json::Value::as_str)
+        .and_then(|parent| models.get(&resource_id(parent)));
 
     if parent.is_none() {
-        return new_model;
+        return Ok(new_model);
     }
 
     let mut model = parent.unwrap().clone();
 
-    model.ambient_occlusion = new_model.ambient_occlusion;
+    if new_model.ambient_occlusion.is_some() {
+        model.ambient_occlusion = new_model.ambient_occlusion;
+    }
     for (key, val) in new_model.display {
         model.display.insert(key, val);
     }
     for (key, val) in new_model.textures {
         model.textures.insert(key, val);
     }
-    for mut element in new_model.elements {
-        let (from, to) = (element.from, element.to);
-        for (direction, face) in element.faces.iter_mut() {
-            if face.uv == Vec4::ZERO {
-                face.uv = match direction {
-                    Direction::Down => Vec4::new(from.x, from.z, to.x, to.z),
-                    Direction::Up => Vec4::new(from.x, from.z, to.x, to.z),
-                    Direction::North => Vec4::new(from.y, from.z, to.y, to.z),
-                    Direction::South => Vec4::new(from.y, from.z, to.y, to.z),
-                    Direction::West => Vec4::new(from.x, from.y, to.x, to.y),
-                    Direction::East => Vec4::new(from.x, from.y, to.x, to.y),
-                };
+    model.elements.extend(new_model.elements);
+
+    Ok(model)
+}
+
+/// Builds the faces of every element, split by `cullface`, and turns them by
+/// the `rotation` of the block state. A face also marks its side as opaque
+/// when it covers the whole side of the block with a fully opaque texture.
+pub fn build_block_geometry(
+    model: &BlockModel,
+    rotation: StateRotation,
+    texture_registry: &TextureRegistry,
+) -> BlockGeometry {
+    let mut geometry = BlockGeometry::default();
+    let mut cube_faces = [None; 6];
+    let scale = Transform::from_scale(Vec3::splat(1.0 / 16.0));
+
+    for element in &model.elements {
+        let transform = scale * element.rotation.transform();
+
+        for (direction, face) in &element.faces {
+            let texture = texture_key(&face.texture, &model.textures, texture_registry);
+            let mesh = create_face_mesh(
+                element,
+                *direction,
+                face,
+                &texture,
+                rotation,
+                texture_registry,
+            )
+            .transformed(&transform);
+
+            match face.cullface {
+                Some(cullface) => geometry.cullable[cullface.index()].append(&mesh, Vec3::ZERO),
+                None => geometry.always.append(&mesh, Vec3::ZERO),
+            }
+
+            if element.rotation.angle == 0.0
+                && covers_side(element, *direction)
+                && texture_registry.opaque.contains(&texture)
+            {
+                geometry.opaque_faces[direction.index()] = true;
+            }
+
+            if model.elements.len() == 1 && is_plain_cube_face(element, *direction, face) {
+                cube_faces[direction.index()] = Some(atlas_rect(&texture, texture_registry));
             }
         }
-        model.elements.push(element);
     }
 
-    model
+    if cube_faces.iter().all(Option::is_some) {
+        geometry.cube_faces = Some(cube_faces.map(Option::unwrap));
+    }
+    geometry.ambient_occlusion = model.ambient_occlusion.unwrap_or(true);
+
+    if rotation.is_identity() {
+        geometry
+    } else {
+        geometry.transformed(&rotation.transform())
+    }
 }
 
-pub fn build_block_mesh(model: &BlockModel, texture_registry: &Res<TextureRegistry>) -> Mesh {
-    let mut mesh = Mesh::new(
-        PrimitiveTopology::TriangleList,
-        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
-    )
-    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, Vec::<[f32; 3]>::new())
-    .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, Vec::<[f32; 2]>::new())
-    .with_inserted_indices(Indices::U32(Vec::<u32>::new()));
+impl ModelRotation {
+    /// Rotation of the element around its origin. With `rescale`, the element
+    /// is stretched across the other two axes so that a face turned by 45°
+    /// still spans the whole block, like the faces of cross-shaped plants.
+    fn transform(&self) -> Transform {
+        let axis: Vec3 = self.axis.into();
+        let rotation = Quat::from_axis_angle(axis, self.angle.to_radians());
+        let scale = match self.rescale {
+            true => Vec3::ONE + (1.0 / self.angle.to_radians().cos() - 1.0) * (Vec3::ONE - axis),
+            false => Vec3::ONE,
+        };
 
-    for element in &model.elements {
-        let mut transform = Transform::default();
-        transform.rotate_around(
-            element.rotation.origin,
-            Quat::from_axis_angle(
-                element.rotation.axis.into(),
-                element.rotation.angle.to_radians(),
-            ),
-        );
+        // The scale is the same across the rotation plane, so it doesn't
+        // matter whether it's applied before or after turning.
+        Transform {
+            translation: self.origin - rotation * (scale * self.origin),
+            rotation,
+            scale,
+        }
+    }
+}
 
-        let element_mesh = create_element_mesh(element, &model.textures, texture_registry);
-        let element_mesh = mesh_with_transform(&element_mesh, &transform).unwrap();
+/// Whether `face` is the side of a full cube showing its whole texture
+/// upright, so that it can be merged with the same face of the next cube.
+fn is_plain_cube_face(element: &ModelElement, direction: Direction, face: &ModelFace) -> bool {
+    element.from == Vec3::ZERO
+        && element.to == Vec3::splat(16.0)
+        && element.rotation.angle == 0.0
+        && face.cullface == Some(direction)
+        && face.rotation == 0
+        && face.uv == Vec4::new(0.0, 0.0, 16.0, 16.0)
+}
 
-        mesh_append(&mut mesh, &element_mesh).unwrap();
-    }
+/// Rectangle of a texture in the atlas, as min and size in atlas UVs.
+fn atlas_rect(texture: &str, texture_registry: &TextureRegistry) -> Vec4 {
+    let size = texture_registry.block_atlas.size.as_vec2();
+    let rect = get_texture_uv(texture, texture_registry).as_rect();
+    let (min, rect_size) = (rect.min / size, rect.size() / size);
+
+    min.extend(rect_size.x).extend(rect_size.y)
+}
+
+/// Whether the `direction` face of an unrotated element is the whole side of
+/// the block.
+fn covers_side(element: &ModelElement, direction: Direction) -> bool {
+    let on_side = match direction {
+        Direction::Down => element.from.y == 0.0,
+        Direction::Up => element.to.y == 16.0,
+        Direction::North => element.from.z == 0.0,
+        Direction::South => element.to.z == 16.0,
+        Direction::West => element.from.x == 0.0,
+        Direction::East => element.to.x == 16.0,
+    };
+    let tangent = Vec3::ONE - direction.normal().abs().as_vec3();
 
-    mesh_with_transform(&mesh, &Transform::from_scale(Vec3::splat(1.0 / 16.0))).unwrap()
+    on_side && element.from * tangent == Vec3::ZERO && element.to * tangent == tangent * 16.0
 }
 
-fn get_texture_uv(
+/// Key in `TextureRegistry::textures` of a face texture like `#side`,
+/// following references between the model textures. Unknown textures resolve
+/// to the debug texture.
+fn texture_key(
     face_texture: &str,
     model_textures: &HashMap<String, String>,
-    texture_registry: &Res<TextureRegistry>,
-) -> URect {
+    texture_registry: &TextureRegistry,
+) -> String {
+    let face_texture = face_texture.strip_prefix('#').unwrap_or(face_texture);
     let texture_name_dbg = "debug".to_owned();
     let mut texture_name = model_textures
         .get(face_texture)
@@ -183,17 +315,16 @@ fn get_texture_uv(
         }
     }
 
-    let texture_name = texture_name.split("/").last().unwrap();
-    let texture_id = &texture_registry
-        .textures
-        .get(&format!("minecraft:block/{}", texture_name))
-        .unwrap_or(
-            texture_registry
-                .textures
-                .get(&"minecraft:block/debug".to_string())
-                .unwrap(),
-        )
-        .0;
+    let key = resource_id(texture_name);
+    if texture_registry.textures.contains_key(&key) {
+        key
+    } else {
+        DEBUG_TEXTURE.to_string()
+    }
+}
+
+fn get_texture_uv(texture: &str, texture_registry: &TextureRegistry) -> URect {
+    let texture_id = &texture_registry.textures.get(texture).unwrap().0;
     let texture_index = texture_registry
         .block_atlas
         .get_texture_index(texture_id)
@@ -201,109 +332,675 @@ fn get_texture_uv(
     texture_registry.block_atlas.textures[texture_index]
 }
 
-fn create_element_mesh(
+/// Point of the unit cube's `direction` side projected to `uv`, both in block
+/// units. The inverse of `face_uv`.
+fn face_point(direction: Direction, uv: Vec2) -> Vec3 {
+    let Vec2 { x: u, y: v } = uv;
+    match direction {
+        Direction::Down => Vec3::new(u, 0.0, 1.0 - v),
+        Direction::Up => Vec3::new(u, 1.0, v),
+        Direction::North => Vec3::new(1.0 - u, 1.0 - v, 0.0),
+        Direction::South => Vec3::new(u, 1.0 - v, 1.0),
+        Direction::West => Vec3::new(0.0, 1.0 - v, u),
+        Direction::East => Vec3::new(1.0, 1.0 - v, 1.0 - u),
+    }
+}
+
+/// Texture coordinates of `point` projected on the `direction` side, the
+/// default UVs vanilla gives to faces.
+fn face_uv(direction: Direction, point: Vec3) -> Vec2 {
+    match direction {
+        Direction::Down => Vec2::new(point.x, 1.0 - point.z),
+        Direction::Up => Vec2::new(point.x, point.z),
+        Direction::North => Vec2::new(1.0 - point.x, 1.0 - point.y),
+        Direction::South => Vec2::new(point.x, 1.0 - point.y),
+        Direction::West => Vec2::new(point.z, 1.0 - point.y),
+        Direction::East => Vec2::new(1.0 - point.z, 1.0 - point.y),
+    }
+}
+
+/// Face UV, from 0 to 16, that keeps the texture upright in the world once
+/// the `direction` face is turned by `rotation`: the point of the face it
+/// maps to is turned, then projected on the side the face ends up on.
+fn lock_uv(direction: Direction, uv: Vec2, rotation: Quat) -> Vec2 {
+    let point = rotation * (face_point(direction, uv / 16.0) - 0.5) + 0.5;
+    let rotated = Direction::from_normal(rotation * direction.normal().as_vec3());
+
+    face_uv(rotated, point) * 16.0
+}
+
+fn create_face_mesh(
     el: &ModelElement,
-    model_textures: &HashMap<String, String>,
-    texture_registry: &Res<TextureRegistry>,
-) -> Mesh {
+    direction: Direction,
+    face: &ModelFace,
+    texture: &str,
+    rotation: StateRotation,
+    texture_registry: &TextureRegistry,
+) -> MeshData {
     let (min, max) = (el.from, el.to);
+    let texture_uv = get_texture_uv(texture, texture_registry);
 
-    let mut vertices: Vec<[f32; 3]> = vec![];
-    let mut indices: Vec<u32> = vec![];
-    let mut uvs: Vec<[f32; 2]> = vec![];
-
-    for (direction, face) in &el.faces {
-        let face_texture = &face.texture.clone().split_off(1);
-        let texture_uv = get_texture_uv(face_texture, model_textures, texture_registry);
-
-        let v_len = vertices.len();
-        let mut v;
-        match direction {
-            Direction::Up => {
-                v = [
-                    [min.x, max.y, min.z],
-                    [min.x, max.y, max.z],
-                    [max.x, max.y, max.z],
-                    [max.x, max.y, min.z],
-                ];
-            }
-            Direction::Down => {
-                v = [
-                    [min.x, min.y, max.z],
-                    [min.x, min.y, min.z],
-                    [max.x, min.y, min.z],
-                    [max.x, min.y, max.z],
-                ];
-            }
-            Direction::North => {
-                v = [
-                    [max.x, max.y, min.z],
-                    [max.x, min.y, min.z],
-                    [min.x, min.y, min.z],
-                    [min.x, max.y, min.z],
-                ];
-            }
-            Direction::South => {
-                v = [
-                    [min.x, max.y, max.z],
-                    [min.x, min.y, max.z],
-                    [max.x, min.y, max.z],
-                    [max.x, max.y, max.z],
-                ];
-            }
-            Direction::East => {
-                v = [
-                    [max.x, max.y, max.z],
-                    [max.x, min.y, max.z],
-                    [max.x, min.y, min.z],
-                    [max.x, max.y, min.z],
-                ];
-            }
-            Direction::West => {
-                v = [
-                    [min.x, max.y, min.z],
-                    [min.x, min.y, min.z],
-                    [min.x, min.y, max.z],
-                    [min.x, max.y, max.z],
-                ];
-            }
-        };
-        indices.extend(&[0, 1, 2, 0, 2, 3].map(|i| i + v_len as u32));
+    let mut v = face_corners(direction).map(|corner| (min + corner * (max - min)).to_array());
+
+    match face.rotation {
+        90 => v.rotate_right(1),
+        180 => v.rotate_left(2),
+        270 => v.rotate_left(1),
+        _ => {}
+    };
+
+    // Face UVs go from 0 to 16 over the whole texture, whatever its size.
+    let atlas_size = texture_registry.block_atlas.size.as_vec2();
+    let texture_uv = texture_uv.as_rect();
+    let mut uv = [face.uv.xy(), face.uv.xw(), face.uv.zw(), face.uv.zy()];
+    if rotation.uvlock && !rotation.is_identity() {
+        uv = uv.map(|uv| lock_uv(direction, uv, rotation.quat()));
+    }
+    let uv =
+        uv.map(|uv| ((texture_uv.min + uv / 16.0 * texture_uv.size()) / atlas_size).to_array());
+
+    MeshData {
+        positions: v.to_vec(),
+        normals: vec![direction.normal().as_vec3().to_array(); 4],
+        uvs: uv.to_vec(),
+        shade: vec![el.shade; 4],
+        tinted: vec![face.tint_index.is_some_and(|index| index >= 0); 4],
+        indices: vec![0, 1, 2, 0, 2, 3],
+    }
+}
 
-        match face.rotation {
-            90 => v.rotate_right(1),
-            180 => v.rotate_left(2),
-            270 => v.rotate_left(1),
-            _ => {}
+#[cfg(test)]
+mod tests {
+    use bevy::render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    };
+    use bevy::utils::HashSet;
+
+    use super::*;
+    use crate::texture::atlas::build_atlas;
+
+    fn texture_registry() -> TextureRegistry {
+        let mut textures = HashMap::new();
+        let mut images = vec![];
+
+        // Glass has twice the resolution of the others.
+        for (i, (name, alpha, size)) in [("debug", 255, 16), ("stone", 255, 16), ("glass", 0, 32)]
+            .into_iter()
+            .enumerate()
+        {
+            let handle = Handle::<Image>::weak_from_u128(i as u128 + 1);
+            let image = Image::new_fill(
+                Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: 1,
+                },
+                TextureDimension::D2,
+                &[255, 255, 255, alpha],
+                TextureFormat::Rgba8UnormSrgb,
+                RenderAssetUsages::MAIN_WORLD,
+            );
+            textures.insert(
+                format!("minecraft:block/{name}"),
+                (handle.clone(), handle.id()),
+            );
+            images.push((handle.id(), image));
+        }
+        let (layout, _) = build_atlas(images.iter().map(|(id, image)| (*id, image))).unwrap();
+
+        TextureRegistry {
+            block: Handle::default(),
+            block_atlas: layout,
+            textures,
+            opaque: HashSet::from_iter([
+                DEBUG_TEXTURE.to_string(),
+                "minecraft:block/stone".to_string(),
+            ]),
+        }
+    }
+
+    fn model(json: serde_json::Value) -> BlockModel {
+        parse_block_model(&HashMap::new(), &json).unwrap()
+    }
+
+    #[test]
+    fn cullface_buckets() {
+        let registry = texture_registry();
+        let slab = model(serde_json::json!({
+            "textures": { "side": "block/stone", "top": "#side" },
+            "elements": [{
+                "from": [0, 0, 0],
+                "to": [16, 8, 16],
+                "faces": {
+                    "down": { "uv": [0, 0, 16, 16], "texture": "#side", "cullface": "down" },
+                    "up": { "uv": [0, 0, 16, 16], "texture": "#top", "tintindex": 0 },
+                    "north": { "uv": [0, 8, 16, 16], "texture": "#side", "cullface": "north" },
+                    "south": { "uv": [0, 8, 16, 16], "texture": "#side", "cullface": "south" }
+                }
+            }]
+        }));
+
+        let geometry = build_block_geometry(&slab, default(), &registry);
+        assert_eq!(geometry.always.positions.len(), 4);
+        assert_eq!(geometry.cullable(Direction::Down).positions.len(), 4);
+        assert_eq!(geometry.cullable(Direction::North).positions.len(), 4);
+        assert!(geometry.cullable(Direction::Up).is_empty());
+        assert!(geometry.cullable(Direction::East).is_empty());
+        assert_eq!(
+            geometry.opaque_faces,
+            Direction::ALL.map(|direction| direction == Direction::Down)
+        );
+
+        assert_eq!(geometry.cube_faces, None);
+        assert_eq!(geometry.always.tinted, [true; 4]);
+        assert_eq!(geometry.cullable(Direction::Down).tinted, [false; 4]);
+
+        // Block units, the top of the slab is halfway up.
+        for pos in &geometry.always.positions {
+            assert_eq!(pos[1], 0.5);
+        }
+    }
+
+    #[test]
+    fn transparent_faces() {
+        let registry = texture_registry();
+        let glass = model(serde_json::json!({
+            "textures": { "all": "block/glass" },
+            "elements": [{
+                "from": [0, 0, 0],
+                "to": [16, 16, 16],
+                "faces": {
+                    "up": { "uv": [0, 0, 16, 16], "texture": "#all", "cullface": "up" },
+                    "down": { "uv": [0, 0, 16, 16], "texture": "#all", "cullface": "bottom" }
+                }
+            }]
+        }));
+
+        let geometry = build_block_geometry(&glass, default(), &registry);
+        assert_eq!(geometry.cullable(Direction::Up).positions.len(), 4);
+        assert!(geometry.opaque_faces.iter().all(|opaque| !opaque));
+
+        // Face UVs cover the whole texture, 32 pixels wide.
+        let size = registry.block_atlas.size.as_vec2();
+        let glass = &registry.textures["minecraft:block/glass"].0;
+        let index = registry.block_atlas.get_texture_index(glass).unwrap();
+        let min = registry.block_atlas.textures[index].min.as_vec2() / size;
+        let uvs = &geometry.cullable(Direction::Up).uvs;
+        assert!(uvs.contains(&min.to_array()));
+        assert!(uvs.contains(&(min + 32.0 / size).to_array()));
+    }
+
+    #[test]
+    fn shade_and_ambient_occlusion() {
+        let registry = texture_registry();
+        let face = serde_json::json!({ "uv": [0, 0, 16, 16], "texture": "#all" });
+        let parent = model(serde_json::json!({
+            "ambientocclusion": false,
+            "textures": { "all": "block/stone" },
+            "elements": [
+                { "from": [0, 0, 0], "to": [16, 16, 16], "faces": { "up": face } },
+                { "from": [0, 0, 0], "to": [16, 16, 16], "shade": false, "faces": { "east": face } }
+            ]
+        }));
+        let models = HashMap::from_iter([("minecraft:block/parent".to_string(), parent.clone())]);
+        let child =
+            parse_block_model(&models, &serde_json::json!({ "parent": "block/parent" })).unwrap();
+
+        for model in [parent, child] {
+            let geometry = build_block_geometry(&model, default(), &registry);
+            assert!(!geometry.ambient_occlusion);
+
+            let faces = &geometry.always;
+            let up = faces
+                .normals
+                .iter()
+                .position(|normal| *normal == [0.0, 1.0, 0.0]);
+            let east = faces
+                .normals
+                .iter()
+                .position(|normal| *normal == [1.0, 0.0, 0.0]);
+            assert!(faces.shade[up.unwrap()]);
+            assert!(!faces.shade[east.unwrap()]);
+        }
+
+        let geometry = build_block_geometry(&cube_all(0), default(), &registry);
+        assert!(geometry.ambient_occlusion);
+    }
+
+    #[test]
+    fn invalid_and_missing_models() {
+        let json = serde_json::json!({ "elements": [{ "from": "nowhere" }] });
+        assert!(parse_block_model(&HashMap::new(), &json).is_err());
+
+        let registry = texture_registry();
+        let geometry = build_block_geometry(&missing_model(), default(), &registry);
+        assert_eq!(geometry.opaque_faces, [true; 6]);
+        let debug = atlas_rect(DEBUG_TEXTURE, &registry);
+        assert_eq!(geometry.cube_faces, Some([debug; 6]));
+    }
+
+    fn cube_all(up_rotation: i32) -> BlockModel {
+        let face = |direction: &str, rotation: i32| {
+            serde_json::json!({
+                "uv": [0, 0, 16, 16],
+                "texture": "#all",
+                "cullface": direction,
+                "rotation": rotation,
+            })
         };
 
-        let padding = 0.1;
-        let center = (face.uv.xy() + face.uv.zw()).div_euclid(Vec2::splat(2.0));
-        let mut uv = [
-            face.uv.xy() + (center - face.uv.xy()).signum() * padding,
-            face.uv.xw() + (center - face.uv.xw()).signum() * padding,
-            face.uv.zw() + (center - face.uv.zw()).signum() * padding,
-            face.uv.zy() + (center - face.uv.zy()).signum() * padding,
-        ];
-        let uv: Vec<[f32; 2]> = uv
-            .iter_mut()
-            .map(|i: &mut Vec2| {
-                [
-                    (texture_uv.min.x as f32 + i.x) / 1024.0,
-                    (texture_uv.min.y as f32 + i.y) / 1024.0,
-                ]
+        model(serde_json::json!({
+            "textures": { "all": "block/stone" },
+            "elements": [{
+                "from": [0, 0, 0],
+                "to": [16, 16, 16],
+                "faces": {
+                    "down": face("down", 0),
+                    "up": face("up", up_rotation),
+                    "north": face("north", 0),
+                    "south": face("south", 0),
+                    "west": face("west", 0),
+                    "east": face("east", 0)
+                }
+            }]
+        }))
+    }
+
+    #[test]
+    fn cube_faces() {
+        let registry = texture_registry();
+        let geometry = build_block_geometry(&cube_all(0), default(), &registry);
+        assert_eq!(geometry.opaque_faces, [true; 6]);
+
+        let faces = geometry.cube_faces.unwrap();
+        let size = registry.block_atlas.size.as_vec2();
+        let stone = &registry.textures["minecraft:block/stone"].0;
+        let index = registry.block_atlas.get_texture_index(stone).unwrap();
+        let min = registry.block_atlas.textures[index].min.as_vec2() / size;
+        assert_eq!(
+            faces,
+            [Vec4::new(min.x, min.y, 16.0 / size.x, 16.0 / size.y); 6]
+        );
+
+        // The same corners of the texture, whatever its size in the atlas.
+        let uvs = &geometry.cullable(Direction::Up).uvs;
+        assert!(uvs.contains(&min.to_array()));
+        assert!(uvs.contains(&(min + 16.0 / size).to_array()));
+
+        let rotated = build_block_geometry(&cube_all(90), default(), &registry);
+        assert_eq!(rotated.cube_faces, None);
+        assert_eq!(rotated.opaque_faces, [true; 6]);
+    }
+
+    /// Vanilla's stairs model, facing east.
+    fn stairs() -> BlockModel {
+        model(serde_json::json!({
+            "textures": { "bottom": "block/debug", "top": "block/glass", "side": "block/stone" },
+            "elements": [
+                {
+                    "from": [0, 0, 0],
+                    "to": [16, 8, 16],
+                    "faces": {
+                        "down": { "uv": [0, 0, 16, 16], "texture": "#bottom", "cullface": "down" },
+                        "up": { "uv": [0, 0, 16, 16], "texture": "#top" },
+                        "north": { "uv": [0, 8, 16, 16], "texture": "#side", "cullface": "north" },
+                        "south": { "uv": [0, 8, 16, 16], "texture": "#side", "cullface": "south" },
+                        "west": { "uv": [0, 8, 16, 16], "texture": "#side", "cullface": "west" },
+                        "east": { "uv": [0, 8, 16, 16], "texture": "#side", "cullface": "east" }
+                    }
+                },
+                {
+                    "from": [8, 8, 0],
+                    "to": [16, 16, 16],
+                    "faces": {
+                        "up": { "uv": [8, 0, 16, 16], "texture": "#top", "cullface": "up" },
+                        "north": { "uv": [0, 0, 8, 8], "texture": "#side", "cullface": "north" },
+                        "south": { "uv": [8, 0, 16, 8], "texture": "#side", "cullface": "south" },
+                        "west": { "uv": [0, 0, 16, 8], "texture": "#side" },
+                        "east": { "uv": [0, 0, 16, 8], "texture": "#side", "cullface": "east" }
+                    }
+                }
+            ]
+        }))
+    }
+
+    /// Vanilla's `cube_column`, with the default UVs.
+    fn column() -> BlockModel {
+        let face = |texture: &str, cullface: &str| serde_json::json!({ "texture": texture, "cullface": cullface });
+        model(serde_json::json!({
+            "textures": { "end": "block/glass", "side": "block/stone" },
+            "elements": [{
+                "from": [0, 0, 0],
+                "to": [16, 16, 16],
+                "faces": {
+                    "down": face("#end", "down"),
+                    "up": face("#end", "up"),
+                    "north": face("#side", "north"),
+                    "south": face("#side", "south"),
+                    "west": face("#side", "west"),
+                    "east": face("#side", "east")
+                }
+            }]
+        }))
+    }
+
+    /// Vanilla's `cross`, without `rescale` when `rescale` is false.
+    fn cross(rescale: bool) -> BlockModel {
+        let rotation = serde_json::json!({
+            "origin": [8, 8, 8],
+            "axis": "y",
+            "angle": 45,
+            "rescale": rescale,
+        });
+        let face = serde_json::json!({ "uv": [0, 0, 16, 16], "texture": "#cross" });
+        model(serde_json::json!({
+            "ambientocclusion": false,
+            "textures": { "cross": "block/stone" },
+            "elements": [
+                {
+                    "from": [0.8, 0, 8],
+                    "to": [15.2, 16, 8],
+                    "rotation": rotation,
+                    "shade": false,
+                    "faces": { "north": face, "south": face }
+                },
+                {
+                    "from": [8, 0, 0.8],
+                    "to": [8, 16, 15.2],
+                    "rotation": rotation,
+                    "shade": false,
+                    "faces": { "west": face, "east": face }
+                }
+            ]
+        }))
+    }
+
+    /// Top bar of vanilla's `fence_side`, reaching north.
+    fn fence_side() -> BlockModel {
+        model(serde_json::json!({
+            "textures": { "texture": "block/stone" },
+            "elements": [{
+                "from": [7, 12, 0],
+                "to": [9, 15, 9],
+                "faces": {
+                    "down": { "uv": [7, 0, 9, 9], "texture": "#texture" },
+                    "up": { "uv": [7, 0, 9, 9], "texture": "#texture" },
+                    "north": { "uv": [7, 1, 9, 4], "texture": "#texture", "cullface": "north" },
+                    "west": { "uv": [0, 1, 9, 4], "texture": "#texture" },
+                    "east": { "uv": [7, 1, 16, 4], "texture": "#texture" }
+                }
+            }]
+        }))
+    }
+
+    fn rotation(x: f32, y: f32, uvlock: bool) -> StateRotation {
+        StateRotation { x, y, uvlock }
+    }
+
+    /// Facing, texture name and corners of a quad, positions and UVs in
+    /// pixels of the block and of the texture.
+    type Quad = (Direction, String, Vec<(Vec3, Vec2)>);
+
+    fn quads(faces: &MeshData, registry: &TextureRegistry) -> Vec<Quad> {
+        let size = registry.block_atlas.size.as_vec2();
+        (0..faces.positions.len())
+            .step_by(4)
+            .map(|start| {
+                let uvs = faces.uvs[start..start + 4]
+                    .iter()
+                    .map(|uv| Vec2::from(*uv) * size);
+                let centre = uvs.clone().sum::<Vec2>() / 4.0;
+                let (name, rect) = registry
+                    .textures
+                    .keys()
+                    .map(|name| (name, get_texture_uv(name, registry).as_rect()))
+                    .find(|(_, rect)| rect.contains(centre))
+                    .unwrap();
+
+                let corners = faces.positions[start..start + 4]
+                    .iter()
+                    .zip(uvs)
+                    .map(|(pos, uv)| {
+                        (
+                            Vec3::from(*pos) * 16.0,
+                            (uv - rect.min) / rect.size() * 16.0,
+                        )
+                    })
+                    .collect();
+                let direction = Direction::from_normal(Vec3::from(faces.normals[start]));
+                (
+                    direction,
+                    name.trim_start_matches("minecraft:block/").to_string(),
+                    corners,
+                )
             })
+            .collect()
+    }
+
+    /// Text dump of the faces of `geometry`, sorted in each bucket.
+    fn snapshot(geometry: &BlockGeometry, registry: &TextureRegistry) -> String {
+        let round = |value: f32| format!("{:.2}", value).replace("-0.00", "0.00");
+        let buckets = std::iter::once(("always".to_string(), &geometry.always)).chain(
+            Direction::ALL.map(|direction| {
+                (
+                    format!("{direction:?}").to_lowercase(),
+                    geometry.cullable(direction),
+                )
+            }),
+        );
+
+        let mut text = String::new();
+        for (bucket, faces) in buckets {
+            let mut lines: Vec<String> = quads(faces, registry)
+                .into_iter()
+                .map(|(direction, texture, corners)| {
+                    let corners: Vec<String> = corners
+                        .iter()
+                        .map(|(pos, uv)| {
+                            let pos = pos.to_array().map(round).join(" ");
+                            let uv = uv.to_array().map(round).join(" ");
+                            format!("{pos} | {uv}")
+                        })
+                        .collect();
+                    format!("  {direction:?} {texture}: {}", corners.join(", ")).to_lowercase()
+                })
+                .collect();
+            lines.sort();
+            if !lines.is_empty() {
+                text += &format!("{bucket}\n{}\n", lines.join("\n"));
+            }
+        }
+        let opaque: Vec<String> = Direction::ALL
+            .into_iter()
+            .filter(|direction| geometry.is_opaque(*direction))
+            .map(|direction| format!("{direction:?}").to_lowercase())
             .collect();
+        text + &format!("opaque [{}]\n", opaque.join(", "))
+    }
+
+    #[test]
+    fn state_rotation() {
+        let registry = texture_registry();
 
-        vertices.extend(v);
-        uvs.extend_from_slice(&uv);
+        // Logs along X: the ends turn to the north, then to the east.
+        let log = build_block_geometry(&column(), rotation(90.0, 90.0, false), &registry);
+        for direction in Direction::ALL {
+            let quads = quads(log.cullable(direction), &registry);
+            assert_eq!(quads.len(), 1);
+            let (normal, texture, _) = &quads[0];
+            assert_eq!(*normal, direction);
+            assert_eq!(
+                texture == "glass",
+                matches!(direction, Direction::West | Direction::East)
+            );
+        }
+        assert_eq!(log.opaque_faces, [true, true, true, true, false, false]);
+
+        let log = build_block_geometry(&column(), rotation(90.0, 0.0, false), &registry);
+        assert_eq!(
+            quads(log.cullable(Direction::North), &registry)[0].1,
+            "glass"
+        );
+
+        // Upside down stairs facing west: the step is at the bottom, on the
+        // west side.
+        let stairs = build_block_geometry(&stairs(), rotation(180.0, 180.0, true), &registry);
+        let top = quads(&stairs.always, &registry);
+        for (direction, _, corners) in top.iter().filter(|(_, texture, _)| texture == "glass") {
+            assert_eq!(*direction, Direction::Down);
+            assert!(corners.iter().all(|(pos, _)| (pos.y - 8.0).abs() < 1e-4));
+        }
+        for (_, _, corners) in quads(stairs.cullable(Direction::Down), &registry) {
+            assert!(corners
+                .iter()
+                .all(|(pos, _)| pos.x < 8.0 + 1e-4 && pos.y.abs() < 1e-4));
+        }
+    }
+
+    #[test]
+    fn uv_lock() {
+        let registry = texture_registry();
+        // The stairs faces use the default UVs, so with UV lock every corner
+        // shows the texture where a plain cube would in the world.
+        let world_aligned = |geometry: &BlockGeometry| {
+            let mut faces = geometry.always.clone();
+            for direction in Direction::ALL {
+                faces.append(geometry.cullable(direction), Vec3::ZERO);
+            }
+            quads(&faces, &registry)
+                .iter()
+                .all(|(direction, _, corners)| {
+                    corners.iter().all(|(pos, uv)| {
+                        (face_uv(*direction, *pos / 16.0) * 16.0).abs_diff_eq(*uv, 1e-3)
+                    })
+                })
+        };
+
+        assert!(world_aligned(&build_block_geometry(
+            &stairs(),
+            default(),
+            &registry
+        )));
+        for (x, y) in [(0.0, 90.0), (180.0, 0.0), (180.0, 270.0), (90.0, 90.0)] {
+            let locked = build_block_geometry(&stairs(), rotation(x, y, true), &registry);
+            assert!(world_aligned(&locked), "x={x} y={y}");
+            let turned = build_block_geometry(&stairs(), rotation(x, y, false), &registry);
+            assert!(!world_aligned(&turned), "x={x} y={y}");
+        }
     }
 
-    Mesh::new(
-        PrimitiveTopology::TriangleList,
-        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
-    )
-    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vertices)
-    .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
-    .with_inserted_indices(Indices::U32(indices))
+    #[test]
+    fn element_rescale() {
+        let registry = texture_registry();
+
+        // Rescaled, the diagonal faces reach the corners of the element's
+        // bounding square.
+        let geometry = build_block_geometry(&cross(true), default(), &registry);
+        assert_eq!(geometry.always.positions.len(), 16);
+        for pos in &geometry.always.positions {
+            let pos = Vec3::from(*pos) * 16.0;
+            for value in [pos.x, pos.z] {
+                assert!(
+                    (value - 0.8).abs() < 1e-4 || (value - 15.2).abs() < 1e-4,
+                    "{pos}"
+                );
+            }
+        }
+        assert_eq!(geometry.opaque_faces, [false; 6]);
+
+        let geometry = build_block_geometry(&cross(false), default(), &registry);
+        for pos in &geometry.always.positions {
+            let offset = (Vec3::from(*pos) - 0.5) * 16.0;
+            assert!((offset.x.abs() - 7.2 * 45f32.to_radians().cos()).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn default_uvs() {
+        let registry = texture_registry();
+        let geometry = build_block_geometry(&fence_side(), default(), &registry);
+        let fence = fence_side();
+        let faces = &fence.elements[0].faces;
+        assert_eq!(faces[&Direction::Down].uv, Vec4::new(7.0, 0.0, 9.0, 9.0));
+        let quads = quads(geometry.cullable(Direction::North), &registry);
+        let uvs: Vec<Vec2> = quads[0].2.iter().map(|(_, uv)| *uv).collect();
+        assert_eq!(
+            uvs,
+            [
+                Vec2::new(7.0, 1.0),
+                Vec2::new(7.0, 4.0),
+                Vec2::new(9.0, 4.0),
+                Vec2::new(9.0, 1.0)
+            ]
+        );
+
+        let column = column();
+        let faces = &column.elements[0].faces;
+        for direction in Direction::ALL {
+            assert_eq!(faces[&direction].uv, Vec4::new(0.0, 0.0, 16.0, 16.0));
+        }
+
+        let slab = model(serde_json::json!({
+            "elements": [{
+                "from": [2, 0, 4],
+                "to": [10, 8, 16],
+                "faces": { "down": {}, "up": {}, "north": {}, "south": {}, "west": {}, "east": {} }
+            }]
+        }));
+        let uvs = Direction::ALL.map(|direction| slab.elements[0].faces[&direction].uv);
+        assert_eq!(
+            uvs,
+            [
+                Vec4::new(2.0, 0.0, 10.0, 12.0),
+                Vec4::new(2.0, 4.0, 10.0, 16.0),
+                Vec4::new(6.0, 8.0, 14.0, 16.0),
+                Vec4::new(2.0, 8.0, 10.0, 16.0),
+                Vec4::new(4.0, 8.0, 16.0, 16.0),
+                Vec4::new(0.0, 8.0, 12.0, 16.0),
+            ]
+        );
+    }
+
+    /// Faces of vanilla block states that are easy to get wrong, compared
+    /// with `testdata/rotated_models.txt`.
+    #[test]
+    fn rotated_model_snapshots() {
+        let registry = texture_registry();
+        let states = [
+            (
+                "oak_stairs[facing=south,half=bottom,shape=straight]",
+                stairs(),
+                rotation(0.0, 90.0, true),
+            ),
+            (
+                "oak_stairs[facing=west,half=top,shape=straight]",
+                stairs(),
+                rotation(180.0, 180.0, true),
+            ),
+            (
+                "oak_stairs[facing=south,half=top,shape=straight]",
+                stairs(),
+                rotation(180.0, 90.0, true),
+            ),
+            ("oak_log[axis=x]", column(), rotation(90.0, 90.0, false)),
+            ("oak_log[axis=z]", column(), rotation(90.0, 0.0, false)),
+            ("poppy", cross(true), default()),
+            (
+                "oak_fence[east=true] side",
+                fence_side(),
+                rotation(0.0, 90.0, true),
+            ),
+        ];
+
+        let snapshot: String = states
+            .iter()
+            .map(|(state, model, rotation)| {
+                let geometry = build_block_geometry(model, *rotation, &registry);
+                format!("# {state}\n{}", snapshot(&geometry, &registry))
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(snapshot, include_str!("testdata/rotated_models.txt"));
+    }
 }
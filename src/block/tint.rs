@@ -0,0 +1,120 @@
+use bevy::prelude::*;
+use indexmap::IndexMap;
+
+/// Colour the tinted faces of a block state are multiplied by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tint {
+    /// From the grass colormap of the biome.
+    Grass,
+    /// From the foliage colormap of the biome.
+    Foliage,
+    /// The water colour of the biome.
+    Water,
+    /// The same colour everywhere, in linear RGB.
+    Fixed(Vec3),
+}
+
+/// Linear RGB of a colour written as `0xRRGGBB` in sRGB, like the colours in
+/// the biome registry.
+pub fn linear_rgb(rgb: u32) -> Vec3 {
+    let [_, r, g, b] = rgb.to_be_bytes();
+    let color = Color::srgb_u8(r, g, b).to_linear();
+
+    Vec3::new(color.red, color.green, color.blue)
+}
+
+/// Tint of a block state, like vanilla's block colours. The block reports
+/// don't have it, so it comes from this table.
+pub fn block_tint(block: &str, properties: &IndexMap<String, String>) -> Option<Tint> {
+    let property = |name: &str| {
+        properties
+            .get(name)
+            .and_then(|value| value.parse::<u32>().ok())
+            .unwrap_or(0)
+    };
+
+    let tint = match block.strip_prefix("minecraft:").unwrap_or(block) {
+        "fern" | "grass_block" | "large_fern" | "potted_fern" | "short_grass" | "sugar_cane"
+        | "tall_grass" => Tint::Grass,
+        "acacia_leaves" | "dark_oak_leaves" | "jungle_leaves" | "mangrove_leaves"
+        | "oak_leaves" | "vine" => Tint::Foliage,
+        "bubble_column" | "water" | "water_cauldron" => Tint::Water,
+        "birch_leaves" => Tint::Fixed(linear_rgb(0x80a755)),
+        "spruce_leaves" => Tint::Fixed(linear_rgb(0x619961)),
+        "lily_pad" => Tint::Fixed(linear_rgb(0x208030)),
+        "attached_melon_stem" | "attached_pumpkin_stem" => Tint::Fixed(linear_rgb(0xe0c71c)),
+        "melon_stem" | "pumpkin_stem" => {
+            let age = property("age");
+            Tint::Fixed(linear_rgb(
+                (age * 32) << 16 | (255 - age * 8) << 8 | (age * 4),
+            ))
+        }
+        "redstone_wire" => Tint::Fixed(linear_rgb(redstone_wire_color(property("power")))),
+        _ => return None,
+    };
+
+    Some(tint)
+}
+
+/// Redstone wire gets brighter and more orange with its power, from 0 to 15.
+fn redstone_wire_color(power: u32) -> u32 {
+    let power = power as f32 / 15.0;
+    let red = power * 0.6 + if power > 0.0 { 0.4 } else { 0.3 };
+    let green = (power * power * 0.7 - 0.5).clamp(0.0, 1.0);
+    let blue = (power * power * 0.6 - 0.7).clamp(0.0, 1.0);
+
+    u32::from_be_bytes([0.0, red, green, blue].map(|channel| (channel * 255.0) as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties(name: &str, value: &str) -> IndexMap<String, String> {
+        IndexMap::from_iter([(name.to_string(), value.to_string())])
+    }
+
+    #[test]
+    fn tints() {
+        let none = IndexMap::new();
+        assert_eq!(
+            block_tint("minecraft:grass_block", &none),
+            Some(Tint::Grass)
+        );
+        assert_eq!(
+            block_tint("minecraft:oak_leaves", &none),
+            Some(Tint::Foliage)
+        );
+        assert_eq!(block_tint("minecraft:stone", &none), None);
+        assert_eq!(
+            block_tint("minecraft:birch_leaves", &none),
+            Some(Tint::Fixed(linear_rgb(0x80a755)))
+        );
+
+        let Some(Tint::Fixed(off)) =
+            block_tint("minecraft:redstone_wire", &properties("power", "0"))
+        else {
+            panic!("redstone wire isn't tinted");
+        };
+        let Some(Tint::Fixed(on)) =
+            block_tint("minecraft:redstone_wire", &properties("power", "15"))
+        else {
+            panic!("redstone wire isn't tinted");
+        };
+        assert_eq!(off, linear_rgb(0x4c0000));
+        assert_eq!(on, linear_rgb(0xff3200));
+
+        assert_eq!(
+            block_tint("minecraft:pumpkin_stem", &properties("age", "7")),
+            Some(Tint::Fixed(linear_rgb(0xe0c71c)))
+        );
+    }
+
+    #[test]
+    fn srgb() {
+        assert_eq!(linear_rgb(0xffffff), Vec3::ONE);
+        assert_eq!(linear_rgb(0), Vec3::ZERO);
+        let color = linear_rgb(0x808080);
+        assert!((color.x - 0.2158).abs() < 1e-3, "{color}");
+    }
+}
@@ -0,0 +1,234 @@
+use bevy::prelude::*;
+
+use super::geometry::BlockGeometry;
+
+/// Geometry of a block state with all its weighted alternatives, one of
+/// which is picked per block position like vanilla.
+#[derive(Debug, Clone, Default)]
+pub struct BlockVariants {
+    /// Multipart states take one more step of the random generator.
+    multipart: bool,
+    /// Weights of the models to choose from, per part. `variants` states
+    /// have a single part.
+    weights: Vec<Vec<u32>>,
+    /// Geometry of every combination of choices, the choice of the first part
+    /// varying fastest.
+    pub geometry: Vec<BlockGeometry>,
+}
+
+impl BlockVariants {
+    /// Variants of the weighted models of each part. The parts of a multipart
+    /// state are drawn together.
+    pub fn new(multipart: bool, parts: Vec<Vec<(u32, BlockGeometry)>>) -> Self {
+        let mut geometry = vec![BlockGeometry::default()];
+        for part in &parts {
+            geometry = part
+                .iter()
+                .flat_map(|(_, model)| {
+                    geometry.iter().map(move |combination| {
+                        let mut combination = combination.clone();
+                        combination.append(model);
+                        combination
+                    })
+                })
+                .collect();
+        }
+
+        Self {
+            multipart,
+            weights: parts
+                .iter()
+                .map(|part| part.iter().map(|(weight, _)| *weight).collect())
+                .collect(),
+            geometry,
+        }
+    }
+
+    /// Geometry of the block at `pos`, in world coordinates.
+    pub fn get(&self, pos: IVec3) -> &BlockGeometry {
+        if self.geometry.len() == 1 {
+            return &self.geometry[0];
+        }
+
+        let mut random = LegacyRandom::new(position_seed(pos));
+        if self.multipart {
+            random = LegacyRandom::new(random.next_long());
+        }
+        // Every part gets a generator with the same seed, so they all draw
+        // the same value.
+        let value = random.next_long() as i32;
+
+        let mut index = 0;
+        let mut stride = 1;
+        for weights in &self.weights {
+            index += pick(weights, value) * stride;
+            stride *= weights.len();
+        }
+        &self.geometry[index]
+    }
+
+    /// Geometry without randomness, the first choice of every part.
+    pub fn first(&self) -> &BlockGeometry {
+        &self.geometry[0]
+    }
+}
+
+impl From<BlockGeometry> for BlockVariants {
+    fn from(geometry: BlockGeometry) -> Self {
+        Self::new(false, vec![vec![(1, geometry)]])
+    }
+}
+
+/// Choice of vanilla's `WeightedRandom` for a random `value`. A single model
+/// doesn't go through it at all.
+fn pick(weights: &[u32], value: i32) -> usize {
+    if weights.len() <= 1 {
+        return 0;
+    }
+
+    let total: i64 = weights.iter().map(|weight| *weight as i64).sum();
+    if total == 0 {
+        return 0;
+    }
+    // `abs` of `i32::MIN` stays negative, which picks the first choice.
+    let mut index = value.wrapping_abs() as i64 % total;
+    for (i, weight) in weights.iter().enumerate() {
+        index -= *weight as i64;
+        if index < 0 {
+            return i;
+        }
+    }
+    0
+}
+
+/// Seed of the random generator for the block at `pos`, vanilla's
+/// `Mth.getSeed`.
+pub fn position_seed(pos: IVec3) -> i64 {
+    let seed = (pos.x.wrapping_mul(3129871) as i64)
+        ^ (pos.z as i64).wrapping_mul(116129781)
+        ^ pos.y as i64;
+    let seed = seed
+        .wrapping_mul(seed)
+        .wrapping_mul(42317861)
+        .wrapping_add(seed.wrapping_mul(11));
+    seed >> 16
+}
+
+/// `java.util.Random`, the generator vanilla uses when rendering blocks.
+struct LegacyRandom {
+    seed: i64,
+}
+
+impl LegacyRandom {
+    const MULTIPLIER: i64 = 0x5_deec_e66d;
+    const INCREMENT: i64 = 0xb;
+    const MASK: i64 = (1 << 48) - 1;
+
+    fn new(seed: i64) -> Self {
+        Self {
+            seed: (seed ^ Self::MULTIPLIER) & Self::MASK,
+        }
+    }
+
+    fn next(&mut self, bits: u32) -> i32 {
+        self.seed = self
+            .seed
+            .wrapping_mul(Self::MULTIPLIER)
+            .wrapping_add(Self::INCREMENT)
+            & Self::MASK;
+        (self.seed >> (48 - bits)) as i32
+    }
+
+    fn next_long(&mut self) -> i64 {
+        ((self.next(32) as i64) << 32).wrapping_add(self.next(32) as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block::geometry::MeshData;
+
+    use super::*;
+
+    /// Geometry told apart by its number of vertices.
+    fn geometry(vertices: usize) -> BlockGeometry {
+        BlockGeometry {
+            always: MeshData {
+                positions: vec![[0.0; 3]; vertices],
+                ..default()
+            },
+            ..default()
+        }
+    }
+
+    fn vertices(geometry: &BlockGeometry) -> usize {
+        geometry.always.positions.len()
+    }
+
+    #[test]
+    fn vanilla_random() {
+        assert_eq!(LegacyRandom::new(0).next_long(), -4962768465676381896);
+
+        let seeds = [
+            (IVec3::ZERO, 0, -723955400),
+            (IVec3::new(1, 64, -1), 51075559105168, 408653838),
+            (
+                IVec3::new(-30000000, 319, 29999999),
+                -20892113470306,
+                1796938085,
+            ),
+            (IVec3::new(12, -64, 7), -81799225945183, -741143678),
+        ];
+        for (pos, seed, value) in seeds {
+            assert_eq!(position_seed(pos), seed);
+            assert_eq!(LegacyRandom::new(seed).next_long() as i32, value);
+        }
+    }
+
+    #[test]
+    fn weighted_pick() {
+        assert_eq!(pick(&[1, 1], 2), 0);
+        assert_eq!(pick(&[1, 1], -3), 1);
+        assert_eq!(pick(&[1, 3], 4), 0);
+        assert_eq!(pick(&[1, 3], 7), 1);
+        assert_eq!(pick(&[5], 3), 0);
+        assert_eq!(pick(&[1, 1], i32::MIN), 0);
+        assert_eq!(pick(&[0, 0], 5), 0);
+        assert_eq!(pick(&[u32::MAX, u32::MAX, 1], 3), 0);
+
+        let variants = BlockVariants::new(false, vec![vec![(1, geometry(1)), (3, geometry(2))]]);
+        let picked = (0..64)
+            .flat_map(|x| (0..64).map(move |z| IVec3::new(x, 70, z)))
+            .filter(|pos| vertices(variants.get(*pos)) == 1)
+            .count();
+        assert!((900..1150).contains(&picked), "{picked}");
+
+        // Each position always gets the same variant.
+        let pos = IVec3::new(-5, 12, 40);
+        assert_eq!(vertices(variants.get(pos)), vertices(variants.get(pos)));
+    }
+
+    #[test]
+    fn multipart_combinations() {
+        let variants = BlockVariants::new(
+            true,
+            vec![
+                vec![(1, geometry(1)), (1, geometry(2))],
+                vec![(1, geometry(10))],
+                vec![(1, geometry(100)), (1, geometry(200)), (1, geometry(300))],
+            ],
+        );
+        let combinations: Vec<_> = variants.geometry.iter().map(vertices).collect();
+        assert_eq!(combinations, [111, 112, 211, 212, 311, 312]);
+        assert_eq!(vertices(variants.first()), 111);
+
+        // Both weighted parts pick with the same value.
+        let mut random = LegacyRandom::new(position_seed(IVec3::ZERO));
+        let value = LegacyRandom::new(random.next_long()).next_long() as i32;
+        let expected = 10 + [1, 2][pick(&[1, 1], value)] + [100, 200, 300][pick(&[1, 1, 1], value)];
+        assert_eq!(vertices(variants.get(IVec3::ZERO)), expected);
+
+        let single: BlockVariants = geometry(7).into();
+        assert_eq!(vertices(single.get(IVec3::new(3, 2, 1))), 7);
+    }
+}
@@ -1,9 +1,13 @@
-use bevy::reflect::Reflect;
+use bevy::{
+    math::{IVec3, Vec3},
+    reflect::Reflect,
+};
 use serde::Deserialize;
 
 #[derive(Reflect, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum Direction {
+    #[serde(alias = "bottom")]
     Down,
     Up,
     North,
@@ -11,3 +15,54 @@ pub enum Direction {
     West,
     East,
 }
+
+impl Direction {
+    pub const ALL: [Direction; 6] = [
+        Direction::Down,
+        Direction::Up,
+        Direction::North,
+        Direction::South,
+        Direction::West,
+        Direction::East,
+    ];
+
+    /// Position of the direction in `ALL`, for arrays keyed by direction.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Direction::Down => Direction::Up,
+            Direction::Up => Direction::Down,
+            Direction::North => Direction::South,
+            Direction::South => Direction::North,
+            Direction::West => Direction::East,
+            Direction::East => Direction::West,
+        }
+    }
+
+    pub fn normal(self) -> IVec3 {
+        match self {
+            Direction::Down => IVec3::NEG_Y,
+            Direction::Up => IVec3::Y,
+            Direction::North => IVec3::NEG_Z,
+            Direction::South => IVec3::Z,
+            Direction::West => IVec3::NEG_X,
+            Direction::East => IVec3::X,
+        }
+    }
+
+    /// Direction closest to `normal`, like a face normal after rotating a
+    /// model.
+    pub fn from_normal(normal: Vec3) -> Self {
+        Direction::ALL
+            .into_iter()
+            .max_by(|a, b| {
+                let a = a.normal().as_vec3().dot(normal);
+                let b = b.normal().as_vec3().dot(normal);
+                a.total_cmp(&b)
+            })
+            .unwrap()
+    }
+}
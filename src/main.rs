@@ -14,6 +14,8 @@ use fly_camera::FlyCameraPlugin;
 //use network::NetworkPlugin;
 //use player::PlayerPlugin;
 //use protocol::ProtocolPlugin;
+//use resource_pack::server::ServerPackPlugin;
+use resource_pack::ResourcePackPlugin;
 use state::AppState;
 use texture::TexturePlugin;
 //use world::WorldPlugin;
@@ -26,6 +28,7 @@ mod fly_camera;
 mod network;
 mod player;
 mod prelude;
+mod resource_pack;
 mod state;
 mod texture;
 mod world;
@@ -56,12 +59,14 @@ fn main() {
 
     app.add_plugins((
         FlyCameraPlugin,
+        ResourcePackPlugin,
         TexturePlugin,
         BlockPlugin,
         //NetworkPlugin,
         //ProtocolPlugin,
         //PlayerPlugin,
         //WorldPlugin,
+        //ServerPackPlugin,
     ));
 
     app.insert_state(AppState::LoadingTextures);
@@ -0,0 +1,218 @@
+use std::fmt;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anyhow::Context;
+use bytes::BytesMut;
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender, TryRecvError, TrySendError};
+
+use protocol::{
+    packets::{LoginSuccess, SetCompression},
+    Packet, PacketDecoder, PacketFrame,
+};
+
+/// Number of decoded frames the reader thread may queue before it stops
+/// reading from the socket and lets TCP flow control slow the server down.
+pub const INCOMING_CAPACITY: usize = 4096;
+/// Number of outgoing buffers that may wait for the writer thread.
+pub const OUTGOING_CAPACITY: usize = 256;
+
+const READ_BUF_LEN: usize = 8192;
+
+pub enum Incoming {
+    Frame(PacketFrame),
+    Closed,
+    Error(anyhow::Error),
+}
+
+enum ReaderCommand {
+    EnableEncryption([u8; 16]),
+}
+
+enum StreamSlot {
+    Connecting,
+    Connected(TcpStream),
+    Closed,
+}
+
+/// Handle to the threads that own the server socket. The reader thread decodes
+/// frames and queues them for the ECS, the writer thread writes whatever the
+/// `PacketEncoder` produced. Dropping the handle closes the socket, which
+/// stops both threads.
+pub struct ConnectionIo {
+    incoming: Receiver<Incoming>,
+    outgoing: Sender<BytesMut>,
+    reader_commands: Sender<ReaderCommand>,
+    stream: Arc<Mutex<StreamSlot>>,
+}
+
+impl ConnectionIo {
+    pub fn connect(host: String, port: u16) -> anyhow::Result<Self> {
+        let (incoming_tx, incoming_rx) = bounded(INCOMING_CAPACITY);
+        let (outgoing_tx, outgoing_rx) = bounded(OUTGOING_CAPACITY);
+        let (commands_tx, commands_rx) = unbounded();
+        let stream = Arc::new(Mutex::new(StreamSlot::Connecting));
+
+        let slot = stream.clone();
+        thread::Builder::new()
+            .name("network reader".to_owned())
+            .spawn(move || {
+                let result = open_stream(&host, port, &slot).and_then(|stream| {
+                    let Some(stream) = stream else {
+                        return Ok(());
+                    };
+
+                    let writer_stream = stream.try_clone()?;
+                    let writer_incoming = incoming_tx.clone();
+                    thread::Builder::new()
+                        .name("network writer".to_owned())
+                        .spawn(move || write_loop(writer_stream, outgoing_rx, writer_incoming))?;
+
+                    read_loop(stream, &incoming_tx, commands_rx)
+                });
+
+                let _ = incoming_tx.send(match result {
+                    Ok(()) => Incoming::Closed,
+                    Err(e) => Incoming::Error(e),
+                });
+            })
+            .context("failed to spawn the network reader thread")?;
+
+        Ok(Self {
+            incoming: incoming_rx,
+            outgoing: outgoing_tx,
+            reader_commands: commands_tx,
+            stream,
+        })
+    }
+
+    pub fn try_recv(&self) -> Option<Incoming> {
+        match self.incoming.try_recv() {
+            Ok(incoming) => Some(incoming),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Incoming::Closed),
+        }
+    }
+
+    /// Queues `bytes` for the writer thread. The bytes are handed back when
+    /// the queue is full, so the caller can retry on the next frame.
+    pub fn try_send(&self, bytes: BytesMut) -> Result<(), TrySendError<BytesMut>> {
+        self.outgoing.try_send(bytes)
+    }
+
+    pub fn queued_incoming(&self) -> usize {
+        self.incoming.len()
+    }
+
+    pub fn queued_outgoing(&self) -> usize {
+        self.outgoing.len()
+    }
+
+    /// Turns on decryption for every byte the reader receives from now on.
+    /// This has to be called before the `EncryptionResponse` is sent, since
+    /// the server encrypts everything after it.
+    pub fn enable_encryption(&self, key: &[u8; 16]) {
+        let _ = self
+            .reader_commands
+            .send(ReaderCommand::EnableEncryption(*key));
+    }
+}
+
+impl fmt::Debug for ConnectionIo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionIo")
+            .field("queued_incoming", &self.queued_incoming())
+            .field("queued_outgoing", &self.queued_outgoing())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for ConnectionIo {
+    fn drop(&mut self) {
+        let mut slot = self.stream.lock().unwrap();
+        if let StreamSlot::Connected(stream) = &*slot {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        *slot = StreamSlot::Closed;
+    }
+}
+
+fn open_stream(
+    host: &str,
+    port: u16,
+    slot: &Mutex<StreamSlot>,
+) -> anyhow::Result<Option<TcpStream>> {
+    let stream = TcpStream::connect((host, port))
+        .with_context(|| format!("could not connect to the server at {host}:{port}"))?;
+    stream.set_nodelay(true)?;
+
+    let mut slot = slot.lock().unwrap();
+    if let StreamSlot::Closed = *slot {
+        return Ok(None);
+    }
+    *slot = StreamSlot::Connected(stream.try_clone()?);
+
+    Ok(Some(stream))
+}
+
+fn read_loop(
+    mut stream: TcpStream,
+    incoming: &Sender<Incoming>,
+    commands: Receiver<ReaderCommand>,
+) -> anyhow::Result<()> {
+    let mut decoder = PacketDecoder::new();
+    let mut logging_in = true;
+    let mut buf = vec![0; READ_BUF_LEN];
+
+    loop {
+        let len = match stream.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(len) => len,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e).context("failed to read from the server"),
+        };
+
+        for command in commands.try_iter() {
+            match command {
+                ReaderCommand::EnableEncryption(key) => decoder.enable_encryption(&key),
+            }
+        }
+
+        decoder.queue_slice(&buf[..len]);
+
+        while let Some(frame) = decoder
+            .try_next_packet()
+            .context("failed to decode packet frame")?
+        {
+            // Compression applies to the very next frame, which may already be
+            // buffered, so it can't wait for the ECS to handle the packet.
+            if logging_in {
+                match frame.id {
+                    SetCompression::ID => {
+                        let pkt = frame.decode::<SetCompression>()?;
+                        decoder.set_compression(pkt.threshold.0);
+                    }
+                    LoginSuccess::ID => logging_in = false,
+                    _ => {}
+                }
+            }
+
+            if incoming.send(Incoming::Frame(frame)).is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn write_loop(mut stream: TcpStream, outgoing: Receiver<BytesMut>, incoming: Sender<Incoming>) {
+    for bytes in outgoing {
+        if let Err(e) = stream.write_all(&bytes) {
+            let _ = incoming.send(Incoming::Error(
+                anyhow::Error::new(e).context("failed to write to the server"),
+            ));
+            return;
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use anyhow::{bail, ensure, Context};
+use bevy::prelude::*;
+use bytes::BytesMut;
+use rand::{rngs::OsRng, RngCore};
+use rsa::{pkcs8::DecodePublicKey, Pkcs1v15Encrypt, RsaPublicKey};
+
+use protocol::{
+    packets::*, Bounded, Encode, LenPrefixed, Packet, PacketEncoder, PacketFrame, PacketState,
+    RawBytes, NBT,
+};
+
+use super::{ConnectionEvent, ServerConnection};
+
+const MAX_COOKIE_LEN: usize = 5120;
+
+/// What the connection should do after a Login or Configuration packet was
+/// handled.
+pub enum Flow {
+    Continue,
+    EnterPlay,
+    Disconnect(String),
+}
+
+pub fn handle_login(
+    frame: &PacketFrame,
+    connection: &mut ServerConnection,
+    encoder: &mut PacketEncoder,
+    events: &mut EventWriter<ConnectionEvent>,
+) -> anyhow::Result<Flow> {
+    match frame.id {
+        DisconnectLogin::ID => {
+            let pkt = frame.decode::<DisconnectLogin>()?;
+            return Ok(Flow::Disconnect(pkt.reason.to_owned()));
+        }
+        EncryptionRequest::ID => {
+            let pkt = frame.decode::<EncryptionRequest>()?;
+            if pkt.should_authenticate {
+                bail!("the server requires online mode authentication, which is not supported");
+            }
+
+            let public_key = RsaPublicKey::from_public_key_der(&pkt.public_key)
+                .context("failed to parse the server public key")?;
+
+            let mut shared_secret = [0; 16];
+            OsRng.fill_bytes(&mut shared_secret);
+
+            let encrypted_secret = public_key
+                .encrypt(&mut OsRng, Pkcs1v15Encrypt, &shared_secret)
+                .context("failed to encrypt the shared secret")?;
+            let encrypted_token = public_key
+                .encrypt(&mut OsRng, Pkcs1v15Encrypt, &pkt.verify_token)
+                .context("failed to encrypt the verify token")?;
+
+            // The reader has to decrypt before the server sees our response.
+            if let Some(io) = &connection.io {
+                io.enable_encryption(&shared_secret);
+            }
+
+            encoder.append_packet(&EncryptionResponse {
+                shared_secret: LenPrefixed(encrypted_secret),
+                verify_token: LenPrefixed(encrypted_token),
+            })?;
+            encoder.enable_encryption(&shared_secret);
+        }
+        LoginSuccess::ID => {
+            let pkt = frame.decode::<LoginSuccess>()?;
+            info!("logged in as {} ({})", pkt.username, pkt.uuid);
+
+            encoder.append_packet(&LoginAcknowledged {})?;
+            connection.set_state(PacketState::Configuration, events);
+
+            let mut brand = BytesMut::new();
+            "rustcraft".encode(&mut brand)?;
+            encoder.append_packet(&ServerPluginMessageConfiguration {
+                channel: "minecraft:brand",
+                data: Bounded(RawBytes(&brand)),
+            })?;
+            encoder.append_packet(&ClientInformationConfiguration {
+                locale: "en_us".into(),
+                view_distance: 8,
+                chat_mode: ChatMode::Enabled,
+                chat_colors: true,
+                displayed_skin_parts: 0x7f,
+                main_hand: Hand::Right,
+                enable_text_filtering: false,
+                allow_server_listings: true,
+            })?;
+        }
+        SetCompression::ID => {
+            // The reader thread already enabled decompression.
+            let pkt = frame.decode::<SetCompression>()?;
+
+            encoder.set_compression(pkt.threshold.0);
+        }
+        LoginPluginRequest::ID => {
+            let pkt = frame.decode::<LoginPluginRequest>()?;
+            debug!("unhandled login plugin request on channel {}", pkt.channel);
+
+            encoder.append_packet(&LoginPluginResponse {
+                message_id: pkt.message_id,
+                successful: false,
+                data: None,
+            })?;
+        }
+        CookieRequestLogin::ID => {
+            let pkt = frame.decode::<CookieRequestLogin>()?;
+
+            encoder.append_packet(&CookieResponseLogin {
+                key: pkt.key,
+                payload: connection.cookies.get(pkt.key).cloned().map(LenPrefixed),
+            })?;
+        }
+        id => bail!("unexpected packet {id:#04x} during login"),
+    }
+
+    Ok(Flow::Continue)
+}
+
+pub fn handle_configuration(
+    frame: &PacketFrame,
+    connection: &mut ServerConnection,
+    encoder: &mut PacketEncoder,
+    events: &mut EventWriter<ConnectionEvent>,
+) -> anyhow::Result<Flow> {
+    match frame.id {
+        CookieRequest::ID => {
+            let pkt = frame.decode::<CookieRequest>()?;
+
+            encoder.append_packet(&CookieResponseConfiguration {
+                key: pkt.key,
+                payload: connection.cookies.get(pkt.key).cloned().map(LenPrefixed),
+            })?;
+        }
+        ClientPluginMessageConfiguration::ID => {
+            let pkt = frame.decode::<ClientPluginMessageConfiguration>()?;
+            debug!("plugin message on channel {}", pkt.channel);
+        }
+        DisconnectConfiguration::ID => {
+            let pkt = frame.decode::<DisconnectConfiguration>()?;
+            let reason = match pkt.reason {
+                NBT::String(reason) => reason,
+                reason => reason.to_string(),
+            };
+
+            return Ok(Flow::Disconnect(reason));
+        }
+        FinishConfiguration::ID => {
+            frame.decode::<FinishConfiguration>()?;
+
+            encoder.append_packet(&AcknowledgeFinishConfiguration {})?;
+            connection.set_state(PacketState::Play, events);
+
+            return Ok(Flow::EnterPlay);
+        }
+        KeepAliveClientConfiguration::ID => {
+            let pkt = frame.decode::<KeepAliveClientConfiguration>()?;
+
+            encoder.append_packet(&ServerKeepAliveConfiguration { id: pkt.id })?;
+        }
+        PingConfiguration::ID => {
+            let pkt = frame.decode::<PingConfiguration>()?;
+
+            encoder.append_packet(&PongConfiguration { id: pkt.id })?;
+        }
+        ResetChat::ID => {
+            frame.decode::<ResetChat>()?;
+        }
+        RegistryData::ID => {
+            // Consumed by the registry systems through `PacketEvent`.
+        }
+        RemoveResourcePackConfiguration::ID | AddResourcePackConfiguration::ID => {
+            // Consumed by the server resource pack systems through
+            // `PacketEvent`.
+        }
+        StoreCookieConfiguration::ID => {
+            let pkt = frame.decode::<StoreCookieConfiguration>()?;
+            ensure!(
+                pkt.payload.len() <= MAX_COOKIE_LEN,
+                "cookie {} exceeds {MAX_COOKIE_LEN} bytes",
+                pkt.key
+            );
+
+            connection
+                .cookies
+                .insert(pkt.key.to_owned(), pkt.payload.to_vec());
+        }
+        TransferConfiguration::ID => {
+            let pkt = frame.decode::<TransferConfiguration>()?;
+            let port = u16::try_from(pkt.port.0).context("transfer port is out of range")?;
+            info!("transferring to {}:{port}", pkt.host);
+
+            let cookies = std::mem::take(&mut connection.cookies);
+            *encoder = PacketEncoder::new();
+            *connection = ServerConnection::open(pkt.host, port, 3, encoder)?;
+            connection.cookies = cookies;
+
+            events.send(ConnectionEvent::StateChanged(connection.state));
+        }
+        FeatureFlags::ID => {
+            let pkt = frame.decode::<FeatureFlags>()?;
+            debug!("enabled feature flags: {:?}", pkt.feature_flags.0);
+        }
+        UpdateTagsConfiguration::ID => {
+            // Consumed by the registry systems through `PacketEvent`.
+        }
+        ClientKnownPacks::ID => {
+            frame.decode::<ClientKnownPacks>()?;
+
+            // We don't ship any data packs, so ask the server for everything.
+            encoder.append_packet(&ServerKnownPacks {
+                known_packs: LenPrefixed(vec![]),
+            })?;
+        }
+        id => debug!("unhandled configuration packet {id:#04x}"),
+    }
+
+    Ok(Flow::Continue)
+}
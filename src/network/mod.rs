@@ -0,0 +1,265 @@
+#![allow(dead_code)]
+use std::time::Instant;
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_inspector_egui::prelude::*;
+use crossbeam_channel::TrySendError;
+use uuid::Uuid;
+
+use protocol::{packets::*, DispatchPackets, PacketEncoder, PacketEvent, PacketState, VarInt};
+
+use crate::core::LocalPlayer;
+
+use self::io::{ConnectionIo, Incoming};
+
+mod io;
+mod login;
+
+pub const USERNAME: &str = "Rust";
+
+#[derive(Reflect, Resource, InspectorOptions, Debug)]
+#[reflect(Resource, InspectorOptions)]
+pub struct ServerConnection {
+    pub host: String,
+    pub port: u16,
+    #[reflect(ignore)]
+    pub io: Option<ConnectionIo>, // Option for reflect to work
+    #[reflect(ignore)]
+    pub state: PacketState,
+    #[reflect(ignore)]
+    pub cookies: HashMap<String, Vec<u8>>,
+}
+
+impl ServerConnection {
+    /// Starts connecting to `host:port` in the background and queues the
+    /// handshake followed by `LoginStart`. `next` is the handshake intent, 2
+    /// for login and 3 for a transfer from another server.
+    fn open(host: &str, port: u16, next: u8, encoder: &mut PacketEncoder) -> anyhow::Result<Self> {
+        let io = ConnectionIo::connect(host.to_owned(), port)?;
+
+        encoder.append_packet(&Handshake {
+            protocol_version: VarInt(PROTOCOL_VERSION),
+            host,
+            port,
+            next,
+        })?;
+        encoder.append_packet(&LoginStart {
+            name: USERNAME.into(),
+            uuid: Uuid::from_u128(0),
+        })?;
+
+        Ok(ServerConnection {
+            host: host.to_owned(),
+            port,
+            io: Some(io),
+            state: PacketState::Login,
+            cookies: HashMap::new(),
+        })
+    }
+
+    fn set_state(&mut self, state: PacketState, events: &mut EventWriter<ConnectionEvent>) {
+        debug!("connection state {} -> {}", self.state.name(), state.name());
+        self.state = state;
+        events.send(ConnectionEvent::StateChanged(state));
+    }
+}
+
+impl Default for ServerConnection {
+    fn default() -> Self {
+        Self {
+            host: "".to_owned(),
+            port: 25565,
+            io: None,
+            state: PacketState::Handshaking,
+            cookies: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Event, Debug)]
+pub enum ConnectionEvent {
+    StateChanged(PacketState),
+    Disconnected {
+        reason: String,
+    },
+    /// The ECS is not keeping up with the network threads, either because
+    /// decoded frames pile up or because the writer can't take more data.
+    Backpressure {
+        incoming: usize,
+        outgoing: usize,
+    },
+    Error(anyhow::Error),
+}
+
+pub struct NetworkPlugin;
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<ServerConnection>();
+        app.add_event::<ConnectionEvent>();
+        app.add_systems(
+            Startup,
+            connect.run_if(not(resource_exists::<ServerConnection>)),
+        );
+        app.add_systems(
+            PreUpdate,
+            receive_packets
+                .run_if(resource_exists::<ServerConnection>)
+                .before(DispatchPackets),
+        );
+        app.add_systems(
+            PostUpdate,
+            send_packets.run_if(resource_exists::<ServerConnection>),
+        );
+        app.add_systems(Last, log_connection_events);
+    }
+}
+
+fn connect(
+    mut commands: Commands,
+    mut encoder: ResMut<PacketEncoder>,
+    mut events: EventWriter<ConnectionEvent>,
+) {
+    match ServerConnection::open("127.0.0.1", 25565, 2, &mut encoder) {
+        Ok(connection) => {
+            events.send(ConnectionEvent::StateChanged(connection.state));
+            commands.insert_resource(connection);
+        }
+        Err(e) => {
+            events.send(ConnectionEvent::Error(e));
+        }
+    }
+}
+
+fn receive_packets(
+    mut commands: Commands,
+    mut connection: ResMut<ServerConnection>,
+    mut encoder: ResMut<PacketEncoder>,
+    mut packets: EventWriter<PacketEvent>,
+    mut events: EventWriter<ConnectionEvent>,
+) {
+    let Some(io) = connection.io.as_ref() else {
+        return;
+    };
+
+    if io.queued_incoming() >= io::INCOMING_CAPACITY {
+        events.send(ConnectionEvent::Backpressure {
+            incoming: io.queued_incoming(),
+            outgoing: io.queued_outgoing(),
+        });
+    }
+
+    // Only drain what is queued right now, so a flood of packets can't keep
+    // this system busy forever.
+    for _ in 0..io.queued_incoming() {
+        let Some(io) = connection.io.as_ref() else {
+            return;
+        };
+
+        let frame = match io.try_recv() {
+            Some(Incoming::Frame(frame)) => frame,
+            Some(Incoming::Closed) => {
+                events.send(ConnectionEvent::Disconnected {
+                    reason: "connection closed by the server".to_owned(),
+                });
+                disconnect(&mut commands, &mut connection);
+                return;
+            }
+            Some(Incoming::Error(e)) => {
+                events.send(ConnectionEvent::Error(e));
+                disconnect(&mut commands, &mut connection);
+                return;
+            }
+            None => break,
+        };
+
+        let state = connection.state;
+        let result = match state {
+            PacketState::Login => {
+                login::handle_login(&frame, &mut connection, &mut encoder, &mut events)
+            }
+            PacketState::Configuration => {
+                login::handle_configuration(&frame, &mut connection, &mut encoder, &mut events)
+            }
+            _ => Ok(login::Flow::Continue),
+        };
+
+        packets.send(PacketEvent {
+            timestamp: Instant::now(),
+            state,
+            id: frame.id,
+            data: frame.body.freeze(),
+        });
+
+        match result {
+            Ok(login::Flow::Continue) => {}
+            Ok(login::Flow::EnterPlay) => {
+                commands.spawn((LocalPlayer, Transform::default(), Name::new("Player")));
+            }
+            Ok(login::Flow::Disconnect(reason)) => {
+                events.send(ConnectionEvent::Disconnected { reason });
+                disconnect(&mut commands, &mut connection);
+                return;
+            }
+            Err(e) => {
+                events.send(ConnectionEvent::Error(e.context(format!(
+                    "failed to handle packet {:#04x} in state {}",
+                    frame.id,
+                    state.name()
+                ))));
+                disconnect(&mut commands, &mut connection);
+                return;
+            }
+        }
+    }
+}
+
+fn send_packets(
+    mut encoder: ResMut<PacketEncoder>,
+    connection: Res<ServerConnection>,
+    mut events: EventWriter<ConnectionEvent>,
+) {
+    let Some(io) = connection.io.as_ref() else {
+        return;
+    };
+
+    if encoder.buf.is_empty() {
+        return;
+    }
+
+    match io.try_send(encoder.take()) {
+        Ok(()) => {}
+        Err(TrySendError::Full(mut bytes)) => {
+            // Keep the bytes in front of anything appended later.
+            bytes.unsplit(encoder.take());
+            encoder.buf = bytes;
+            events.send(ConnectionEvent::Backpressure {
+                incoming: io.queued_incoming(),
+                outgoing: io.queued_outgoing(),
+            });
+        }
+        Err(TrySendError::Disconnected(_)) => {
+            // The reader reports why the connection closed.
+        }
+    }
+}
+
+pub fn disconnect(commands: &mut Commands, connection: &mut ServerConnection) {
+    connection.io = None;
+    connection.state = PacketState::Handshaking;
+
+    commands.remove_resource::<ServerConnection>();
+    commands.insert_resource(PacketEncoder::new());
+}
+
+fn log_connection_events(mut events: EventReader<ConnectionEvent>) {
+    for event in events.read() {
+        match event {
+            ConnectionEvent::StateChanged(state) => info!("connection state: {}", state.name()),
+            ConnectionEvent::Disconnected { reason } => warn!("disconnected: {reason}"),
+            ConnectionEvent::Backpressure { incoming, outgoing } => {
+                debug!("network backpressure: {incoming} frames and {outgoing} buffers queued")
+            }
+            ConnectionEvent::Error(e) => error!("connection error: {e:#}"),
+        }
+    }
+}
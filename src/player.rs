@@ -3,7 +3,7 @@ use protocol::{
     packets::{
         ClientKeepAlivePlay, ConfirmTeleport, ServerKeepAlivePlay, SynchronizePlayerPosition,
     },
-    PacketEncoder, PacketEvent,
+    PacketEncoder, Typed,
 };
 
 use crate::core::LocalPlayer;
@@ -18,60 +18,59 @@ impl Plugin for PlayerPlugin {
     }
 }
 
-fn handle_keep_alive(mut encoder: ResMut<PacketEncoder>, mut pkts: EventReader<PacketEvent>) {
+fn handle_keep_alive(
+    mut encoder: ResMut<PacketEncoder>,
+    mut pkts: EventReader<Typed<ClientKeepAlivePlay>>,
+) {
     for pkt in pkts.read() {
-        if let Some(pkt) = pkt.decode::<ClientKeepAlivePlay>() {
-            encoder
-                .append_packet(&ServerKeepAlivePlay { id: pkt.id })
-                .unwrap();
-        }
+        encoder
+            .append_packet(&ServerKeepAlivePlay { id: pkt.id })
+            .unwrap();
     }
 }
 
 fn handle_syncrhonize_player_position(
     mut query: Query<&mut Transform, With<LocalPlayer>>,
     mut encoder: ResMut<PacketEncoder>,
-    mut pkts: EventReader<PacketEvent>,
+    mut pkts: EventReader<Typed<SynchronizePlayerPosition>>,
 ) {
     if let Ok(mut transform) = query.get_single_mut() {
         for pkt in pkts.read() {
-            if let Some(pkt) = pkt.decode::<SynchronizePlayerPosition>() {
-                encoder
-                    .append_packet(&ConfirmTeleport {
-                        teleport_id: pkt.teleport_id,
-                    })
-                    .unwrap();
-
-                if pkt.flags & 0x01 == 0 {
-                    transform.translation.x = pkt.x as f32;
-                } else {
-                    transform.translation.x += pkt.x as f32;
-                }
-                if pkt.flags & 0x02 == 0 {
-                    transform.translation.y = pkt.y as f32;
-                } else {
-                    transform.translation.y += pkt.y as f32;
-                }
-                if pkt.flags & 0x04 == 0 {
-                    transform.translation.z = pkt.z as f32;
-                } else {
-                    transform.translation.z += pkt.z as f32;
-                }
+            encoder
+                .append_packet(&ConfirmTeleport {
+                    teleport_id: pkt.teleport_id,
+                })
+                .unwrap();
 
-                let old_rot = transform.rotation.to_euler(EulerRot::YXZ);
-                let mut yaw = pkt.yaw - 90.0;
-                let mut pitch = pkt.pitch;
+            if pkt.flags & 0x01 == 0 {
+                transform.translation.x = pkt.x as f32;
+            } else {
+                transform.translation.x += pkt.x as f32;
+            }
+            if pkt.flags & 0x02 == 0 {
+                transform.translation.y = pkt.y as f32;
+            } else {
+                transform.translation.y += pkt.y as f32;
+            }
+            if pkt.flags & 0x04 == 0 {
+                transform.translation.z = pkt.z as f32;
+            } else {
+                transform.translation.z += pkt.z as f32;
+            }
 
-                if pkt.flags & 0x08 == 0 {
-                    yaw += old_rot.0;
-                }
-                if pkt.flags & 0x10 == 0 {
-                    pitch = (pitch + old_rot.1).clamp(-90.0, 90.0);
-                }
+            let old_rot = transform.rotation.to_euler(EulerRot::YXZ);
+            let mut yaw = pkt.yaw - 90.0;
+            let mut pitch = pkt.pitch;
 
-                transform.rotation =
-                    Quat::from_euler(EulerRot::YXZ, yaw.to_radians(), pitch.to_radians(), 0.0);
+            if pkt.flags & 0x08 == 0 {
+                yaw += old_rot.0;
+            }
+            if pkt.flags & 0x10 == 0 {
+                pitch = (pitch + old_rot.1).clamp(-90.0, 90.0);
             }
+
+            transform.rotation =
+                Quat::from_euler(EulerRot::YXZ, yaw.to_radians(), pitch.to_radians(), 0.0);
         }
     }
 }
@@ -0,0 +1,376 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use self::zip::ZipArchive;
+
+pub mod server;
+pub mod zip;
+
+/// Directory of the vanilla assets, laid out like a resource pack.
+const VANILLA_ASSETS: &str = "assets";
+
+/// Largest resource pack vanilla accepts, and so the largest file in one.
+const MAX_PACK_SIZE: u64 = 250 * 1024 * 1024;
+
+pub struct ResourcePackPlugin;
+impl Plugin for ResourcePackPlugin {
+    fn build(&self, app: &mut App) {
+        let mut packs = ResourcePacks::new(ResourcePack::directory("vanilla", VANILLA_ASSETS));
+        for path in pack_args(std::env::args().skip(1)) {
+            match ResourcePack::open(&path) {
+                Ok(pack) => {
+                    if let Ok(info) = pack.info() {
+                        info!(
+                            "using resource pack {} (format {})",
+                            pack.name, info.pack_format
+                        );
+                    }
+                    packs.push(pack);
+                }
+                Err(e) => warn!("failed to open resource pack {}: {e:#}", path.display()),
+            }
+        }
+
+        app.insert_resource(packs);
+    }
+}
+
+/// Paths given with `--resource-pack`, in order.
+fn pack_args(args: impl IntoIterator<Item = String>) -> Vec<PathBuf> {
+    let mut args = args.into_iter();
+    let mut paths = vec![];
+    while let Some(arg) = args.next() {
+        if let Some(path) = arg.strip_prefix("--resource-pack=") {
+            paths.push(PathBuf::from(path));
+        } else if arg == "--resource-pack" {
+            paths.extend(args.next().map(PathBuf::from));
+        }
+    }
+
+    paths
+}
+
+/// `pack.mcmeta` of a resource pack.
+#[derive(Deserialize, Debug)]
+struct PackMetadata {
+    pack: PackInfo,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PackInfo {
+    pub pack_format: i32,
+}
+
+#[derive(Debug, Clone)]
+enum PackSource {
+    Directory(PathBuf),
+    Zip(ZipArchive),
+}
+
+/// Assets of a resource pack, a directory or a zip archive with an `assets`
+/// directory inside.
+#[derive(Debug, Clone)]
+pub struct ResourcePack {
+    pub name: String,
+    source: PackSource,
+}
+
+impl ResourcePack {
+    /// Pack of the files in the directory at `path`, without checking for a
+    /// `pack.mcmeta`.
+    pub fn directory(name: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        Self {
+            name: name.into(),
+            source: PackSource::Directory(path.into()),
+        }
+    }
+
+    /// Pack of a zip archive in memory.
+    pub fn zip(
+        name: impl Into<String>,
+        data: impl Into<std::sync::Arc<[u8]>>,
+    ) -> anyhow::Result<Self> {
+        let pack = Self {
+            name: name.into(),
+            source: PackSource::Zip(ZipArchive::new(data)?),
+        };
+        pack.info()?;
+
+        Ok(pack)
+    }
+
+    /// Opens the pack at `path`, a directory or a `.zip` file with a
+    /// `pack.mcmeta`.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let name = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        if path.is_dir() {
+            let pack = Self::directory(name, path);
+            pack.info()?;
+            Ok(pack)
+        } else {
+            Self::zip(name, fs::read(path)?)
+        }
+    }
+
+    /// Contents of its `pack.mcmeta`.
+    pub fn info(&self) -> anyhow::Result<PackInfo> {
+        let data = self.read("pack.mcmeta").context("missing pack.mcmeta")??;
+        let metadata: PackMetadata =
+            serde_json::from_slice(&data).context("failed to parse pack.mcmeta")?;
+
+        Ok(metadata.pack)
+    }
+
+    /// Contents of the file at `path` in the pack, `None` when the pack
+    /// doesn't have it.
+    pub fn read(&self, path: &str) -> Option<anyhow::Result<Vec<u8>>> {
+        match &self.source {
+            PackSource::Directory(dir) => {
+                let path = dir.join(path);
+                path.is_file().then(|| {
+                    fs::read(&path).with_context(|| format!("failed to read {}", path.display()))
+                })
+            }
+            PackSource::Zip(archive) => archive.read(path),
+        }
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        match &self.source {
+            PackSource::Directory(dir) => dir.join(path).is_file(),
+            PackSource::Zip(archive) => archive.contains(path),
+        }
+    }
+
+    /// Paths of the files directly in the directory `dir`.
+    fn list(&self, dir: &str) -> Vec<String> {
+        let dir = dir.trim_end_matches('/');
+        match &self.source {
+            PackSource::Directory(root) => fs::read_dir(root.join(dir))
+                .into_iter()
+                .flatten()
+                .flatten()
+                .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_file()))
+                .map(|entry| format!("{dir}/{}", entry.file_name().to_string_lossy()))
+                .collect(),
+            PackSource::Zip(archive) => archive
+                .names()
+                .filter(|name| {
+                    name.strip_prefix(dir)
+                        .and_then(|name| name.strip_prefix('/'))
+                        .is_some_and(|name| !name.contains('/'))
+                })
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+
+    /// Namespaces of the `assets` directory.
+    fn namespaces(&self) -> BTreeSet<String> {
+        match &self.source {
+            PackSource::Directory(root) => fs::read_dir(root.join("assets"))
+                .into_iter()
+                .flatten()
+                .flatten()
+                .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect(),
+            PackSource::Zip(archive) => archive
+                .names()
+                .filter_map(|name| name.strip_prefix("assets/")?.split_once('/'))
+                .map(|(namespace, _)| namespace.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// Stack of resource packs, files of later packs overriding the same files
+/// of earlier ones. The vanilla assets are at the bottom.
+#[derive(Resource, Debug, Clone)]
+pub struct ResourcePacks {
+    packs: Vec<ResourcePack>,
+}
+
+impl ResourcePacks {
+    pub fn new(base: ResourcePack) -> Self {
+        Self { packs: vec![base] }
+    }
+
+    /// Puts `pack` on top of the others.
+    pub fn push(&mut self, pack: ResourcePack) {
+        self.packs.push(pack);
+    }
+
+    /// Removes the packs named `name` other than the base one.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.packs.len();
+        let mut index = 0;
+        self.packs.retain(|pack| {
+            index += 1;
+            index == 1 || pack.name != name
+        });
+
+        self.packs.len() != len
+    }
+
+    /// Pack of the file at `path`, the last one that has it.
+    pub fn find(&self, path: &str) -> Option<&ResourcePack> {
+        self.packs.iter().rev().find(|pack| pack.contains(path))
+    }
+
+    pub fn read(&self, path: &str) -> Option<anyhow::Result<Vec<u8>>> {
+        self.find(path)?.read(path)
+    }
+
+    pub fn read_to_string(&self, path: &str) -> Option<anyhow::Result<String>> {
+        self.read(path).map(|data| Ok(String::from_utf8(data?)?))
+    }
+
+    /// Resources of a `kind` like `textures` in the `dir` directory of every
+    /// namespace, not in its subdirectories. Returns the identifiers of the
+    /// files with `extension`, like `minecraft:block/stone`, with their path.
+    pub fn list(&self, kind: &str, dir: &str, extension: &str) -> Vec<(String, String)> {
+        let mut resources = BTreeSet::new();
+        for pack in &self.packs {
+            for namespace in pack.namespaces() {
+                for path in pack.list(&format!("assets/{namespace}/{kind}/{dir}")) {
+                    let Some(name) = path
+                        .rsplit('/')
+                        .next()
+                        .and_then(|name| name.strip_suffix(extension))
+                    else {
+                        continue;
+                    };
+                    resources.insert((format!("{namespace}:{dir}/{name}"), path));
+                }
+            }
+        }
+
+        resources.into_iter().collect()
+    }
+}
+
+/// Identifier with its namespace, `minecraft` when it has none.
+pub fn resource_id(id: &str) -> String {
+    match id.split_once(':') {
+        Some(_) => id.to_string(),
+        None => format!("minecraft:{id}"),
+    }
+}
+
+/// Path in a pack of the resource `id` of a `kind` like `models` or
+/// `blockstates`, with the `extension` of its files.
+pub fn resource_path(id: &str, kind: &str, extension: &str) -> String {
+    let id = resource_id(id);
+    let (namespace, path) = id.split_once(':').unwrap();
+
+    format!("assets/{namespace}/{kind}/{path}{extension}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{zip::tests::zip, *};
+
+    fn pack(name: &str, files: &[(&str, &[u8])]) -> ResourcePack {
+        let mcmeta: &[(&str, &[u8])] = &[("pack.mcmeta", br#"{ "pack": { "pack_format": 34 } }"#)];
+        ResourcePack::zip(name, zip(&[mcmeta, files].concat(), true)).unwrap()
+    }
+
+    #[test]
+    fn override_precedence() {
+        let mut packs = ResourcePacks::new(pack(
+            "vanilla",
+            &[
+                (
+                    "assets/minecraft/textures/block/stone.png",
+                    b"vanilla stone",
+                ),
+                ("assets/minecraft/textures/block/dirt.png", b"vanilla dirt"),
+            ],
+        ));
+        packs.push(pack(
+            "custom",
+            &[
+                ("assets/minecraft/textures/block/stone.png", b"custom stone"),
+                ("assets/custom/textures/block/ruby.png", b"ruby"),
+                ("assets/custom/textures/block/nested/gem.png", b"gem"),
+                ("assets/custom/textures/block/ruby.png.mcmeta", b"{}"),
+            ],
+        ));
+
+        let read = |packs: &ResourcePacks, path: &str| packs.read(path).map(|data| data.unwrap());
+        assert_eq!(
+            read(&packs, "assets/minecraft/textures/block/stone.png").unwrap(),
+            b"custom stone"
+        );
+        assert_eq!(
+            read(&packs, "assets/minecraft/textures/block/dirt.png").unwrap(),
+            b"vanilla dirt"
+        );
+        assert!(read(&packs, "assets/minecraft/textures/block/ruby.png").is_none());
+        assert_eq!(
+            packs
+                .find("assets/custom/textures/block/ruby.png")
+                .unwrap()
+                .name,
+            "custom"
+        );
+
+        assert_eq!(
+            packs.list("textures", "block", ".png"),
+            [
+                (
+                    "custom:block/ruby".to_string(),
+                    "assets/custom/textures/block/ruby.png".to_string()
+                ),
+                (
+                    "minecraft:block/dirt".to_string(),
+                    "assets/minecraft/textures/block/dirt.png".to_string()
+                ),
+                (
+                    "minecraft:block/stone".to_string(),
+                    "assets/minecraft/textures/block/stone.png".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn pack_metadata() {
+        assert_eq!(pack("a", &[]).info().unwrap().pack_format, 34);
+        assert!(ResourcePack::zip("b", zip(&[("assets/x", b"")], false)).is_err());
+    }
+
+    #[test]
+    fn identifiers() {
+        assert_eq!(resource_id("block/stone"), "minecraft:block/stone");
+        assert_eq!(resource_id("custom:block/ruby"), "custom:block/ruby");
+        assert_eq!(
+            resource_path("block/stone", "models", ".json"),
+            "assets/minecraft/models/block/stone.json"
+        );
+        assert_eq!(
+            resource_path("minecraft:stone", "blockstates", ".json"),
+            "assets/minecraft/blockstates/stone.json"
+        );
+    }
+
+    #[test]
+    fn args() {
+        let args = ["--resource-pack", "a.zip", "--other", "--resource-pack=b"].map(String::from);
+        assert_eq!(
+            pack_args(args),
+            [PathBuf::from("a.zip"), PathBuf::from("b")]
+        );
+    }
+}
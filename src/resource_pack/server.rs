@@ -0,0 +1,651 @@
+#![allow(dead_code)]
+use std::{fs, io::Read, path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::{ensure, Context};
+use bevy::{
+    prelude::*,
+    tasks::{block_on, futures_lite::future, IoTaskPool, Task},
+};
+use protocol::{
+    packets::{
+        AddResourcePackConfiguration, RemoveResourcePackConfiguration,
+        ResourcePackResponseConfiguration, ResourcePackResponseConfigurationResult as Response,
+    },
+    PacketEncoder, Typed,
+};
+use sha1::{Digest, Sha1};
+use uuid::Uuid;
+
+use crate::{
+    network::{disconnect, ConnectionEvent, ServerConnection},
+    state::AppState,
+};
+
+use super::{ResourcePack, ResourcePacks, MAX_PACK_SIZE};
+
+/// Directory of the downloaded server packs, named by their hash.
+const CACHE_DIR: &str = "server-resource-packs";
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct ServerPackPlugin;
+impl Plugin for ServerPackPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Fetcher(Arc::new(HttpFetcher::new())));
+        app.insert_resource(ServerPacks::new(CACHE_DIR));
+        app.add_systems(
+            Update,
+            (
+                handle_add_resource_pack,
+                handle_remove_resource_pack,
+                poll_downloads,
+            )
+                .chain(),
+        );
+        app.add_systems(OnEnter(AppState::LoadingModels), finish_reload);
+    }
+}
+
+/// Downloads server resource packs. Swapped out in tests to serve packs
+/// without a real server.
+pub trait PackFetcher: Send + Sync {
+    fn fetch(&self, url: &str) -> anyhow::Result<Vec<u8>>;
+}
+
+#[derive(Resource, Clone)]
+pub struct Fetcher(pub Arc<dyn PackFetcher>);
+
+/// Fetches packs over HTTP or HTTPS, following redirects, up to the size of
+/// the largest pack.
+#[derive(Debug, Clone)]
+pub struct HttpFetcher(ureq::Agent);
+
+impl HttpFetcher {
+    pub fn new() -> Self {
+        Self(
+            ureq::AgentBuilder::new()
+                .timeout_connect(HTTP_TIMEOUT)
+                .timeout_read(HTTP_TIMEOUT)
+                .user_agent(concat!("rustcraft/", env!("CARGO_PKG_VERSION")))
+                .build(),
+        )
+    }
+}
+
+impl Default for HttpFetcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PackFetcher for HttpFetcher {
+    fn fetch(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        let response = self.0.get(url).call()?;
+        let len = response
+            .header("Content-Length")
+            .and_then(|len| len.parse::<u64>().ok());
+        if let Some(len) = len {
+            ensure!(len <= MAX_PACK_SIZE, "pack of {len} bytes is too large");
+        }
+
+        let mut data = vec![];
+        response
+            .into_reader()
+            .take(MAX_PACK_SIZE + 1)
+            .read_to_end(&mut data)?;
+        ensure!(data.len() as u64 <= MAX_PACK_SIZE, "pack is too large");
+
+        Ok(data)
+    }
+}
+
+/// Serves packs from a directory, by the file name at the end of their URL.
+#[derive(Debug, Clone)]
+pub struct DirectoryFetcher(pub PathBuf);
+
+impl PackFetcher for DirectoryFetcher {
+    fn fetch(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        let name = url.rsplit('/').next().unwrap_or_default();
+        ensure!(!name.is_empty() && name != "..", "no file name in {url}");
+
+        let path = self.0.join(name);
+        fs::read(&path).with_context(|| format!("failed to read {}", path.display()))
+    }
+}
+
+/// Packs the server asked for. Downloads run on the IO task pool, then the
+/// pack goes on top of `ResourcePacks` and the textures and models reload.
+#[derive(Resource)]
+pub struct ServerPacks {
+    /// Declines every pack when unset, which drops the connection when the
+    /// server forces one.
+    pub accept: bool,
+    cache: PathBuf,
+    downloads: Vec<Download>,
+    /// Packs in `ResourcePacks`, in order.
+    applied: Vec<Uuid>,
+    /// Packs waiting for the reload to answer the server.
+    reloading: Vec<Uuid>,
+}
+
+struct Download {
+    uuid: Uuid,
+    forced: bool,
+    task: Task<Result<Vec<u8>, Response>>,
+}
+
+impl ServerPacks {
+    pub fn new(cache: impl Into<PathBuf>) -> Self {
+        Self {
+            accept: true,
+            cache: cache.into(),
+            downloads: vec![],
+            applied: vec![],
+            reloading: vec![],
+        }
+    }
+}
+
+/// Name of a server pack in `ResourcePacks`.
+fn pack_name(uuid: Uuid) -> String {
+    format!("server/{uuid}")
+}
+
+/// Only web URLs are allowed, like vanilla.
+fn is_valid_url(url: &str) -> bool {
+    url.strip_prefix("http://")
+        .or_else(|| url.strip_prefix("https://"))
+        .is_some_and(|rest| !rest.is_empty() && !rest.starts_with('/'))
+}
+
+/// Pack at `url`, from the cache when a pack with the same `hash` was
+/// downloaded before. Packs are only cached and verified when the server sent
+/// a valid hash.
+fn download(
+    fetcher: &dyn PackFetcher,
+    cache: &std::path::Path,
+    url: &str,
+    hash: Option<&str>,
+) -> Result<Vec<u8>, Response> {
+    let cached = hash.map(|hash| cache.join(format!("{hash}.zip")));
+    if let Some((hash, cached)) = hash.zip(cached.as_ref()) {
+        if let Some(data) = fs::read(cached).ok().filter(|data| sha1_hex(data) == hash) {
+            debug!("using cached resource pack {}", cached.display());
+            return Ok(data);
+        }
+    }
+
+    let data = fetcher.fetch(url).map_err(|e| {
+        warn!("failed to download resource pack {url}: {e:#}");
+        Response::FailedToDownload
+    })?;
+
+    if let Some((hash, cached)) = hash.zip(cached) {
+        let actual = sha1_hex(&data);
+        if actual != hash {
+            warn!("resource pack {url} has hash {actual}, expected {hash}");
+            return Err(Response::FailedToDownload);
+        }
+
+        if let Err(e) = fs::create_dir_all(cache).and_then(|()| fs::write(&cached, &data)) {
+            warn!("failed to cache resource pack {}: {e}", cached.display());
+        }
+    }
+
+    Ok(data)
+}
+
+/// SHA-1 digest of `data` in lowercase hex, like the hashes in
+/// `AddResourcePack` packets.
+fn sha1_hex(data: &[u8]) -> String {
+    Sha1::digest(data)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn respond(encoder: &mut PacketEncoder, uuid: Uuid, result: Response) {
+    encoder
+        .append_packet(&ResourcePackResponseConfiguration { uuid, result })
+        .unwrap();
+}
+
+/// Drops the connection after a pack the server forced failed.
+fn reject_forced(
+    commands: &mut Commands,
+    connection: Option<Mut<ServerConnection>>,
+    events: &mut EventWriter<ConnectionEvent>,
+) {
+    events.send(ConnectionEvent::Disconnected {
+        reason: "the server requires a resource pack that couldn't be loaded".to_owned(),
+    });
+    if let Some(mut connection) = connection {
+        disconnect(commands, &mut connection);
+    }
+}
+
+pub fn handle_add_resource_pack(
+    mut commands: Commands,
+    mut server_packs: ResMut<ServerPacks>,
+    fetcher: Res<Fetcher>,
+    mut encoder: ResMut<PacketEncoder>,
+    mut connection: Option<ResMut<ServerConnection>>,
+    mut events: EventWriter<ConnectionEvent>,
+    mut pkts: EventReader<Typed<AddResourcePackConfiguration>>,
+) {
+    for pkt in pkts.read() {
+        let result = if !server_packs.accept {
+            Response::Declined
+        } else if !is_valid_url(&pkt.url) {
+            Response::InvalidURL
+        } else {
+            Response::Accepted
+        };
+        respond(&mut encoder, pkt.uuid, result);
+
+        if result != Response::Accepted {
+            warn!("rejected server resource pack {}: {result:?}", pkt.url);
+            if pkt.forced {
+                reject_forced(
+                    &mut commands,
+                    connection.as_mut().map(|c| c.reborrow()),
+                    &mut events,
+                );
+            }
+            continue;
+        }
+
+        // Vanilla downloads without verifying when the hash isn't SHA-1.
+        let hash = Some(pkt.hash.0.to_ascii_lowercase())
+            .filter(|hash| hash.len() == 40 && hash.bytes().all(|b| b.is_ascii_hexdigit()));
+        let fetcher = fetcher.0.clone();
+        let cache = server_packs.cache.clone();
+        let url = pkt.url.clone();
+        let task = IoTaskPool::get()
+            .spawn(async move { download(fetcher.as_ref(), &cache, &url, hash.as_deref()) });
+
+        // A pack sent again under the same UUID replaces the previous one.
+        server_packs
+            .downloads
+            .retain(|download| download.uuid != pkt.uuid);
+        server_packs.downloads.push(Download {
+            uuid: pkt.uuid,
+            forced: pkt.forced,
+            task,
+        });
+    }
+}
+
+pub fn handle_remove_resource_pack(
+    mut server_packs: ResMut<ServerPacks>,
+    mut packs: ResMut<ResourcePacks>,
+    mut encoder: ResMut<PacketEncoder>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut pkts: EventReader<Typed<RemoveResourcePackConfiguration>>,
+) {
+    for pkt in pkts.read() {
+        // Packs still downloading and applied ones, the server is told each
+        // of them was discarded.
+        let removed: Vec<_> = (server_packs.downloads.iter())
+            .map(|download| download.uuid)
+            .chain(server_packs.applied.iter().copied())
+            .filter(|uuid| pkt.uuid.is_none() || pkt.uuid == Some(*uuid))
+            .collect();
+        server_packs
+            .downloads
+            .retain(|download| !removed.contains(&download.uuid));
+        server_packs.applied.retain(|uuid| !removed.contains(uuid));
+        server_packs
+            .reloading
+            .retain(|uuid| !removed.contains(uuid));
+
+        let mut changed = false;
+        for uuid in removed {
+            changed |= packs.remove(&pack_name(uuid));
+            respond(&mut encoder, uuid, Response::Discarded);
+        }
+        if changed {
+            next_state.set(AppState::LoadingTextures);
+        }
+    }
+}
+
+/// Puts finished downloads on top of the resource packs and reloads the
+/// textures and models.
+pub fn poll_downloads(
+    mut commands: Commands,
+    mut server_packs: ResMut<ServerPacks>,
+    mut packs: ResMut<ResourcePacks>,
+    mut encoder: ResMut<PacketEncoder>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut connection: Option<ResMut<ServerConnection>>,
+    mut events: EventWriter<ConnectionEvent>,
+) {
+    let mut finished = vec![];
+    server_packs.downloads.retain_mut(|download| {
+        match block_on(future::poll_once(&mut download.task)) {
+            Some(result) => {
+                finished.push((download.uuid, download.forced, result));
+                false
+            }
+            None => true,
+        }
+    });
+
+    for (uuid, forced, result) in finished {
+        let pack = result.and_then(|data| {
+            respond(&mut encoder, uuid, Response::Downloaded);
+            ResourcePack::zip(pack_name(uuid), data).map_err(|e| {
+                warn!("failed to open server resource pack {uuid}: {e:#}");
+                Response::FailedToReload
+            })
+        });
+
+        match pack {
+            Ok(pack) => {
+                packs.remove(&pack.name);
+                packs.push(pack);
+                server_packs.applied.retain(|applied| *applied != uuid);
+                server_packs.applied.push(uuid);
+                server_packs.reloading.push(uuid);
+                next_state.set(AppState::LoadingTextures);
+            }
+            Err(result) => {
+                respond(&mut encoder, uuid, result);
+                if forced {
+                    reject_forced(
+                        &mut commands,
+                        connection.as_mut().map(|c| c.reborrow()),
+                        &mut events,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Tells the server its packs are in use once the models were reloaded.
+pub fn finish_reload(mut server_packs: ResMut<ServerPacks>, mut encoder: ResMut<PacketEncoder>) {
+    for uuid in server_packs.reloading.drain(..) {
+        respond(&mut encoder, uuid, Response::SuccessfullyDownloaded);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, net::TcpListener};
+
+    use bevy::{ecs::system::RunSystemOnce, tasks::TaskPool, utils::Instant};
+    use protocol::{packets::ResourcePackResponseConfiguration, Bounded, PacketDecoder};
+
+    use super::{super::zip::tests::zip, *};
+
+    /// Directory in the system temp dir, emptied first.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rustcraft-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn pack_zip() -> Vec<u8> {
+        zip(
+            &[
+                ("pack.mcmeta", br#"{ "pack": { "pack_format": 34 } }"#),
+                ("assets/minecraft/textures/block/stone.png", b"server stone"),
+            ],
+            true,
+        )
+    }
+
+    fn world(name: &str) -> (World, PathBuf) {
+        IoTaskPool::get_or_init(TaskPool::new);
+
+        let dir = temp_dir(name);
+        let served = dir.join("served");
+        fs::create_dir_all(&served).unwrap();
+
+        let mut world = World::new();
+        world.insert_resource(ResourcePacks::new(ResourcePack::directory(
+            "vanilla",
+            dir.join("vanilla"),
+        )));
+        world.insert_resource(ServerPacks::new(dir.join("cache")));
+        world.insert_resource(Fetcher(Arc::new(DirectoryFetcher(served.clone()))));
+        world.insert_resource(PacketEncoder::new());
+        world.init_resource::<NextState<AppState>>();
+        world.init_resource::<Events<ConnectionEvent>>();
+        world.init_resource::<Events<Typed<AddResourcePackConfiguration>>>();
+        world.init_resource::<Events<Typed<RemoveResourcePackConfiguration>>>();
+
+        (world, served)
+    }
+
+    fn add(world: &mut World, uuid: u128, url: &str, hash: &str, forced: bool) {
+        world.send_event(Typed::new(
+            Instant::now(),
+            AddResourcePackConfiguration {
+                uuid: Uuid::from_u128(uuid),
+                url: url.to_owned(),
+                hash: Bounded(hash.to_owned()),
+                forced,
+                option: None,
+            },
+        ));
+        world.run_system_once(handle_add_resource_pack);
+        // Each run has a new reader, which would see the event again.
+        world
+            .resource_mut::<Events<Typed<AddResourcePackConfiguration>>>()
+            .clear();
+        while !world.resource::<ServerPacks>().downloads.is_empty() {
+            std::thread::sleep(Duration::from_millis(1));
+            world.run_system_once(poll_downloads);
+        }
+        world.run_system_once(finish_reload);
+    }
+
+    fn remove(world: &mut World, uuid: Option<u128>) {
+        world.send_event(Typed::new(
+            Instant::now(),
+            RemoveResourcePackConfiguration {
+                uuid: uuid.map(Uuid::from_u128),
+            },
+        ));
+        world.run_system_once(handle_remove_resource_pack);
+        world
+            .resource_mut::<Events<Typed<RemoveResourcePackConfiguration>>>()
+            .clear();
+    }
+
+    fn responses(world: &mut World) -> Vec<(u128, Response)> {
+        let mut decoder = PacketDecoder::new();
+        decoder.queue_bytes(world.resource_mut::<PacketEncoder>().take());
+
+        let mut responses = vec![];
+        while let Some(frame) = decoder.try_next_packet().unwrap() {
+            let pkt = frame.decode::<ResourcePackResponseConfiguration>().unwrap();
+            responses.push((pkt.uuid.as_u128(), pkt.result));
+        }
+        responses
+    }
+
+    fn pack_names(world: &World) -> Vec<String> {
+        let packs = world.resource::<ResourcePacks>();
+        packs.packs.iter().map(|pack| pack.name.clone()).collect()
+    }
+
+    #[test]
+    fn hashes() {
+        assert_eq!(sha1_hex(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(sha1_hex(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    }
+
+    #[test]
+    fn download_and_reload() {
+        let (mut world, served) = world("server-packs");
+        let data = pack_zip();
+        let hash = sha1_hex(&data);
+        fs::write(served.join("pack.zip"), &data).unwrap();
+
+        add(
+            &mut world,
+            1,
+            "http://example.com/pack.zip",
+            &hash.to_uppercase(),
+            true,
+        );
+        assert_eq!(
+            responses(&mut world),
+            [
+                (1, Response::Accepted),
+                (1, Response::Downloaded),
+                (1, Response::SuccessfullyDownloaded)
+            ]
+        );
+        assert_eq!(
+            pack_names(&world),
+            ["vanilla".to_string(), pack_name(Uuid::from_u128(1))]
+        );
+        assert_eq!(
+            world
+                .resource::<ResourcePacks>()
+                .read("assets/minecraft/textures/block/stone.png")
+                .unwrap()
+                .unwrap(),
+            b"server stone"
+        );
+        assert!(matches!(
+            world.resource::<NextState<AppState>>(),
+            NextState::Pending(AppState::LoadingTextures)
+        ));
+        assert!(world.resource::<Events<ConnectionEvent>>().is_empty());
+
+        // The second time it comes from the cache.
+        fs::remove_file(served.join("pack.zip")).unwrap();
+        add(&mut world, 2, "http://example.com/pack.zip", &hash, false);
+        assert_eq!(
+            responses(&mut world).last(),
+            Some(&(2, Response::SuccessfullyDownloaded))
+        );
+
+        // Unknown packs are ignored, known ones are discarded.
+        remove(&mut world, Some(3));
+        assert_eq!(responses(&mut world), []);
+        remove(&mut world, Some(2));
+        assert_eq!(responses(&mut world), [(2, Response::Discarded)]);
+
+        // Including ones still downloading.
+        fs::write(served.join("pack.zip"), &data).unwrap();
+        world.send_event(Typed::new(
+            Instant::now(),
+            AddResourcePackConfiguration {
+                uuid: Uuid::from_u128(3),
+                url: "http://example.com/pack.zip".to_owned(),
+                hash: Bounded(String::new()),
+                forced: false,
+                option: None,
+            },
+        ));
+        world.run_system_once(handle_add_resource_pack);
+        remove(&mut world, None);
+        assert_eq!(
+            responses(&mut world),
+            [
+                (3, Response::Accepted),
+                (3, Response::Discarded),
+                (1, Response::Discarded)
+            ]
+        );
+        assert!(world.resource::<ServerPacks>().downloads.is_empty());
+        assert_eq!(pack_names(&world), ["vanilla"]);
+    }
+
+    #[test]
+    fn failures() {
+        let (mut world, served) = world("server-pack-failures");
+        fs::write(served.join("pack.zip"), pack_zip()).unwrap();
+        fs::write(served.join("broken.zip"), b"not a zip").unwrap();
+
+        add(
+            &mut world,
+            1,
+            "http://example.com/pack.zip",
+            &"0".repeat(40),
+            false,
+        );
+        add(&mut world, 2, "example.com/pack.zip", "", false);
+        add(&mut world, 3, "http://example.com/missing.zip", "", false);
+        add(&mut world, 4, "http://example.com/broken.zip", "", false);
+        assert_eq!(
+            responses(&mut world),
+            [
+                (1, Response::Accepted),
+                (1, Response::FailedToDownload),
+                (2, Response::InvalidURL),
+                (3, Response::Accepted),
+                (3, Response::FailedToDownload),
+                (4, Response::Accepted),
+                (4, Response::Downloaded),
+                (4, Response::FailedToReload),
+            ]
+        );
+        assert_eq!(pack_names(&world), ["vanilla"]);
+        assert!(world.resource::<Events<ConnectionEvent>>().is_empty());
+
+        world.resource_mut::<ServerPacks>().accept = false;
+        add(&mut world, 5, "http://example.com/pack.zip", "", true);
+        assert_eq!(responses(&mut world), [(5, Response::Declined)]);
+        assert!(!world.resource::<Events<ConnectionEvent>>().is_empty());
+    }
+
+    /// Answers one connection with each of `responses`, returning the
+    /// request lines.
+    fn serve(responses: Vec<&'static [u8]>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            responses
+                .into_iter()
+                .map(|response| {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let mut request = vec![];
+                    let mut buf = [0; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        let len = stream.read(&mut buf).unwrap();
+                        request.extend(&buf[..len]);
+                    }
+                    stream.write_all(response).unwrap();
+                    let request = String::from_utf8(request).unwrap();
+                    request.lines().next().unwrap().to_owned()
+                })
+                .collect()
+        });
+
+        (address, server)
+    }
+
+    #[test]
+    fn http() {
+        let (address, server) = serve(vec![
+            b"HTTP/1.1 302 Found\r\nLocation: /packs/b.zip\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n2\r\npa\r\n2\r\nck\r\n0\r\n\r\n",
+            b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nContent-Length: 999999999999\r\nConnection: close\r\n\r\npack",
+        ]);
+
+        let fetcher = HttpFetcher::new();
+        let data = fetcher.fetch(&format!("{address}/packs/a.zip")).unwrap();
+        assert_eq!(data, b"pack");
+        assert!(fetcher.fetch(&format!("{address}/missing.zip")).is_err());
+        assert!(fetcher.fetch(&format!("{address}/large.zip")).is_err());
+
+        assert_eq!(
+            server.join().unwrap(),
+            [
+                "GET /packs/a.zip HTTP/1.1",
+                "GET /packs/b.zip HTTP/1.1",
+                "GET /missing.zip HTTP/1.1",
+                "GET /large.zip HTTP/1.1"
+            ]
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    io::{Cursor, Read},
+    sync::Arc,
+};
+
+use anyhow::{ensure, Context};
+
+use super::MAX_PACK_SIZE;
+
+/// Read-only zip archive held in memory.
+#[derive(Debug, Clone)]
+pub struct ZipArchive {
+    archive: ::zip::ZipArchive<Cursor<Arc<[u8]>>>,
+}
+
+impl ZipArchive {
+    pub fn new(data: impl Into<Arc<[u8]>>) -> anyhow::Result<Self> {
+        let archive =
+            ::zip::ZipArchive::new(Cursor::new(data.into())).context("not a zip archive")?;
+
+        Ok(Self { archive })
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.archive.index_for_name(name).is_some()
+    }
+
+    /// Names of the files in the archive, directories excluded.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.archive
+            .file_names()
+            .filter(|name| !name.ends_with('/'))
+    }
+
+    /// Contents of the file `name`, `None` when the archive doesn't have it.
+    pub fn read(&self, name: &str) -> Option<anyhow::Result<Vec<u8>>> {
+        let index = self.archive.index_for_name(name)?;
+        Some(
+            self.read_entry(index)
+                .with_context(|| format!("failed to read {name}")),
+        )
+    }
+
+    /// Inflates at most the size the archive says the file has, which can't
+    /// be more than a whole pack, so that a small archive can't fill the
+    /// memory.
+    fn read_entry(&self, index: usize) -> anyhow::Result<Vec<u8>> {
+        // Entries borrow the archive mutably to seek in it, clones share the
+        // data and the central directory.
+        let mut archive = self.archive.clone();
+        let entry = archive.by_index(index)?;
+        let size = entry.size();
+        ensure!(size <= MAX_PACK_SIZE, "file of {size} bytes is too large");
+
+        let mut data = Vec::with_capacity(size as usize);
+        entry.take(size + 1).read_to_end(&mut data)?;
+        ensure!(data.len() as u64 == size, "expected {size} bytes, got more");
+
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Write;
+
+    use ::zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+    use super::*;
+
+    /// Zip archive of `files`, deflated when `deflate` is set.
+    pub(crate) fn zip(files: &[(&str, &[u8])], deflate: bool) -> Vec<u8> {
+        let method = match deflate {
+            true => CompressionMethod::Deflated,
+            false => CompressionMethod::Stored,
+        };
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        for (name, contents) in files {
+            writer
+                .start_file(
+                    *name,
+                    SimpleFileOptions::default().compression_method(method),
+                )
+                .unwrap();
+            writer.write_all(contents).unwrap();
+        }
+
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn read_files() {
+        let files: &[(&str, &[u8])] = &[
+            ("pack.mcmeta", br#"{ "pack": { "pack_format": 34 } }"#),
+            (
+                "assets/minecraft/textures/block/stone.png",
+                &[1, 2, 3, 4, 5],
+            ),
+        ];
+        for deflate in [false, true] {
+            let archive = ZipArchive::new(zip(files, deflate)).unwrap();
+            for (name, contents) in files {
+                assert_eq!(archive.read(name).unwrap().unwrap(), *contents);
+            }
+            assert!(archive.read("missing").is_none());
+            assert_eq!(archive.names().count(), 2);
+        }
+    }
+
+    /// Offsets of the uncompressed size of the first file, in its local
+    /// header and in the central directory.
+    fn size_offsets(data: &[u8]) -> [usize; 2] {
+        let central = data
+            .windows(4)
+            .position(|bytes| bytes == [0x50, 0x4b, 0x01, 0x02])
+            .unwrap();
+        [22, central + 24]
+    }
+
+    #[test]
+    fn reject_broken_archives() {
+        assert!(ZipArchive::new(b"not a zip".to_vec()).is_err());
+
+        // Corrupted contents fail the CRC check.
+        let mut data = zip(&[("a", b"abc")], false);
+        let start = data.windows(3).position(|bytes| bytes == b"abc").unwrap();
+        data[start] = b'x';
+        let archive = ZipArchive::new(data).unwrap();
+        assert!(archive.read("a").unwrap().is_err());
+    }
+
+    #[test]
+    fn reject_zip_bombs() {
+        // A megabyte of zeros that says it is 16 bytes long.
+        let mut data = zip(&[("bomb", &vec![0; 1 << 20])], true);
+        for offset in size_offsets(&data) {
+            data[offset..offset + 4].copy_from_slice(&16u32.to_le_bytes());
+        }
+        let archive = ZipArchive::new(data).unwrap();
+        assert!(archive.read("bomb").unwrap().is_err());
+
+        // One that says it is larger than any pack.
+        let mut data = zip(&[("bomb", b"abc")], true);
+        for offset in size_offsets(&data) {
+            data[offset..offset + 4].copy_from_slice(&(300u32 << 20).to_le_bytes());
+        }
+        let archive = ZipArchive::new(data).unwrap();
+        assert!(archive.read("bomb").unwrap().is_err());
+    }
+}
@@ -0,0 +1,476 @@
+use std::time::Duration;
+
+use anyhow::{bail, ensure};
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::ExtractResource,
+        render_asset::{RenderAssetUsages, RenderAssets},
+        render_resource::{
+            Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, TextureAspect, TextureDimension,
+            TextureFormat,
+        },
+        renderer::RenderQueue,
+        texture::GpuImage,
+    },
+};
+use serde::Deserialize;
+
+use super::{
+    atlas::{extrude, mip_chain, padded_rect},
+    TextureRegistry,
+};
+
+/// Length of a game tick, the unit of frame times.
+const TICK: Duration = Duration::from_millis(50);
+
+/// Contents of a `.png.mcmeta` file.
+#[derive(Deserialize, Debug)]
+pub struct TextureMetadata {
+    pub animation: Option<AnimationMetadata>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct AnimationMetadata {
+    /// Ticks each frame is shown for, unless the frame has its own time.
+    #[serde(default = "default_frametime")]
+    pub frametime: u32,
+    /// Whether the frames fade into each other instead of switching at once.
+    #[serde(default)]
+    pub interpolate: bool,
+    /// Order of the frames, every frame of the image in order by default.
+    pub frames: Option<Vec<AnimationFrame>>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+fn default_frametime() -> u32 {
+    1
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(untagged)]
+pub enum AnimationFrame {
+    Index(usize),
+    Timed { index: usize, time: Option<u32> },
+}
+
+impl AnimationMetadata {
+    /// Size of a frame in an image of `size`: the size in the metadata, and
+    /// squares as wide as the thinner side of the image otherwise.
+    fn frame_size(&self, size: UVec2) -> UVec2 {
+        match (self.width, self.height) {
+            (Some(width), Some(height)) => UVec2::new(width, height),
+            (Some(width), None) => UVec2::new(width, size.y),
+            (None, Some(height)) => UVec2::new(size.x, height),
+            (None, None) => UVec2::splat(size.min_element()),
+        }
+    }
+}
+
+/// An animated texture: the frames of its image, of which the atlas only
+/// holds the current one.
+#[derive(Debug, Clone)]
+pub struct TextureAnimation {
+    size: UVec2,
+    format: TextureFormat,
+    /// RGBA pixels of each frame, row by row.
+    frames: Vec<Vec<u8>>,
+    /// Frame index and ticks to show it for, in order.
+    sequence: Vec<(usize, u32)>,
+    interpolate: bool,
+    /// Where the frame goes in the atlas, set once it is built.
+    pub rect: URect,
+    step: usize,
+    ticks: u32,
+}
+
+impl TextureAnimation {
+    pub fn new(image: &Image, metadata: &AnimationMetadata) -> anyhow::Result<Self> {
+        let format = image.texture_descriptor.format;
+        ensure!(
+            matches!(
+                format,
+                TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb
+            ),
+            "unsupported texture format {format:?}"
+        );
+
+        let image_size = image.size();
+        let size = metadata.frame_size(image_size);
+        ensure!(
+            size.cmpgt(UVec2::ZERO).all() && (image_size % size) == UVec2::ZERO,
+            "{image_size} image can't be split into {size} frames"
+        );
+        let columns = image_size.x / size.x;
+        let count = (columns * (image_size.y / size.y)) as usize;
+
+        let row = size.x as usize * 4;
+        let stride = image_size.x as usize * 4;
+        let frames = (0..count as u32)
+            .map(|index| {
+                let min = UVec2::new(index % columns, index / columns) * size;
+                (min.y..min.y + size.y)
+                    .flat_map(|y| {
+                        let start = y as usize * stride + min.x as usize * 4;
+                        &image.data[start..start + row]
+                    })
+                    .copied()
+                    .collect()
+            })
+            .collect();
+
+        let sequence: Vec<_> = match &metadata.frames {
+            Some(frames) => frames
+                .iter()
+                .map(|frame| match *frame {
+                    AnimationFrame::Index(index) => (index, metadata.frametime),
+                    AnimationFrame::Timed { index, time } => {
+                        (index, time.unwrap_or(metadata.frametime))
+                    }
+                })
+                .collect(),
+            None => (0..count)
+                .map(|index| (index, metadata.frametime))
+                .collect(),
+        };
+        if sequence.is_empty() {
+            bail!("animation without frames");
+        }
+        if let Some((index, _)) = sequence.iter().find(|(index, _)| *index >= count) {
+            bail!("frame {index} out of the {count} frames of the image");
+        }
+
+        Ok(Self {
+            size,
+            format,
+            frames,
+            sequence,
+            interpolate: metadata.interpolate,
+            rect: URect::default(),
+            step: 0,
+            ticks: 0,
+        })
+    }
+
+    /// First frame, to put in the atlas.
+    pub fn first_frame(&self) -> Image {
+        Image::new(
+            Extent3d {
+                width: self.size.x,
+                height: self.size.y,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            self.frames[self.sequence[0].0].clone(),
+            self.format,
+            RenderAssetUsages::MAIN_WORLD,
+        )
+    }
+
+    /// Advances the animation by a tick. Returns whether the texture changed.
+    fn tick(&mut self) -> bool {
+        let frame = self.sequence[self.step].0;
+        self.ticks += 1;
+        if self.ticks >= self.sequence[self.step].1 {
+            self.step = (self.step + 1) % self.sequence.len();
+            self.ticks = 0;
+            return self.interpolate || self.sequence[self.step].0 != frame;
+        }
+
+        self.interpolate
+    }
+
+    /// Pixels of the current frame. When interpolating, the colour moves
+    /// towards the next frame over the time of the current one, like vanilla
+    /// keeping the alpha of the current frame.
+    fn pixels(&self) -> Vec<u8> {
+        let (frame, time) = self.sequence[self.step];
+        let current = &self.frames[frame];
+        if !self.interpolate {
+            return current.clone();
+        }
+
+        let next = &self.frames[self.sequence[(self.step + 1) % self.sequence.len()].0];
+        let ratio = 1.0 - self.ticks as f64 / time as f64;
+        current
+            .chunks_exact(4)
+            .zip(next.chunks_exact(4))
+            .flat_map(|(current, next)| {
+                let mix =
+                    |i: usize| (ratio * current[i] as f64 + (1.0 - ratio) * next[i] as f64) as u8;
+                [mix(0), mix(1), mix(2), current[3]]
+            })
+            .collect()
+    }
+
+    /// The current frame at its place in the atlas, with its border and mip
+    /// levels.
+    fn writes(&self) -> impl Iterator<Item = AtlasWrite> {
+        let (pixels, size) = extrude(&self.pixels(), self.size);
+        let rect = padded_rect(self.rect);
+        mip_chain(pixels, size)
+            .into_iter()
+            .zip(0..)
+            .map(move |(data, mip_level)| AtlasWrite {
+                mip_level,
+                rect: URect::from_corners(rect.min >> mip_level, rect.max >> mip_level),
+                data,
+            })
+    }
+}
+
+/// Pixels of a rectangle of one mip level of the block atlas.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AtlasWrite {
+    pub mip_level: u32,
+    pub rect: URect,
+    pub data: Vec<u8>,
+}
+
+/// Parts of the block atlas the animations changed, written straight into
+/// its texture in the render world. Modifying the atlas image instead would
+/// upload all of it again.
+#[derive(Resource, ExtractResource, Debug, Clone, Default)]
+pub struct AtlasWrites {
+    pub atlas: AssetId<Image>,
+    pub writes: Vec<AtlasWrite>,
+}
+
+/// Animated textures of the block atlas.
+#[derive(Resource, Debug)]
+pub struct TextureAnimations {
+    pub animations: Vec<TextureAnimation>,
+    timer: Timer,
+}
+
+impl TextureAnimations {
+    pub fn new(animations: Vec<TextureAnimation>) -> Self {
+        Self {
+            animations,
+            timer: Timer::new(TICK, TimerMode::Repeating),
+        }
+    }
+}
+
+/// Queues the frames of the animated textures that changed to be written
+/// into the block atlas, once per tick.
+pub fn animate_textures(
+    time: Res<Time>,
+    mut animations: ResMut<TextureAnimations>,
+    texture_registry: Res<TextureRegistry>,
+    mut writes: ResMut<AtlasWrites>,
+) {
+    let animations = &mut *animations;
+    animations.timer.tick(time.delta());
+    let ticks = animations.timer.times_finished_this_tick();
+    if ticks == 0 {
+        return;
+    }
+
+    let mut changed = vec![];
+    for (i, animation) in animations.animations.iter_mut().enumerate() {
+        let mut animation_changed = false;
+        for _ in 0..ticks {
+            animation_changed |= animation.tick();
+        }
+        if animation_changed {
+            changed.push(i);
+        }
+    }
+    if changed.is_empty() {
+        return;
+    }
+
+    *writes = AtlasWrites {
+        atlas: texture_registry.block.id(),
+        writes: changed
+            .into_iter()
+            .flat_map(|i| animations.animations[i].writes())
+            .collect(),
+    };
+}
+
+/// Copies the queued writes into the texture of the block atlas. They are
+/// dropped when it isn't on the GPU yet, its image already has the first
+/// frames.
+pub fn write_atlas(
+    mut writes: ResMut<AtlasWrites>,
+    images: Res<RenderAssets<GpuImage>>,
+    queue: Res<RenderQueue>,
+) {
+    let AtlasWrites { atlas, writes } = &mut *writes;
+    let Some(atlas) = images.get(*atlas) else {
+        writes.clear();
+        return;
+    };
+
+    for write in writes.drain(..) {
+        let size = write.rect.size();
+        queue.write_texture(
+            ImageCopyTexture {
+                texture: &atlas.texture,
+                mip_level: write.mip_level,
+                origin: Origin3d {
+                    x: write.rect.min.x,
+                    y: write.rect.min.y,
+                    z: 0,
+                },
+                aspect: TextureAspect::All,
+            },
+            &write.data,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(size.x * 4),
+                rows_per_image: None,
+            },
+            Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 2×6 strip of three frames, every pixel of a frame set to its index.
+    fn strip() -> Image {
+        let data = (0..3u8).flat_map(|frame| [frame; 2 * 2 * 4]).collect();
+        Image::new(
+            Extent3d {
+                width: 2,
+                height: 6,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::MAIN_WORLD,
+        )
+    }
+
+    fn metadata(json: &str) -> AnimationMetadata {
+        serde_json::from_str::<TextureMetadata>(json)
+            .unwrap()
+            .animation
+            .unwrap()
+    }
+
+    fn frame(animation: &TextureAnimation) -> u8 {
+        animation.pixels()[0]
+    }
+
+    #[test]
+    fn parse_metadata() {
+        let meta = metadata(r#"{ "animation": { "frames": [1, { "index": 0, "time": 3 }] } }"#);
+        assert_eq!(meta.frametime, 1);
+        assert!(!meta.interpolate);
+        assert_eq!(
+            meta.frames,
+            Some(vec![
+                AnimationFrame::Index(1),
+                AnimationFrame::Timed {
+                    index: 0,
+                    time: Some(3)
+                }
+            ])
+        );
+        assert_eq!(meta.frame_size(UVec2::new(16, 64)), UVec2::splat(16));
+        assert_eq!(
+            metadata(r#"{ "animation": { "height": 8 } }"#).frame_size(UVec2::new(16, 64)),
+            UVec2::new(16, 8)
+        );
+
+        let meta: TextureMetadata = serde_json::from_str(r#"{ "villager": {} }"#).unwrap();
+        assert!(meta.animation.is_none());
+    }
+
+    #[test]
+    fn frame_order() {
+        let meta = metadata(
+            r#"{ "animation": { "frametime": 2, "frames": [2, { "index": 0, "time": 1 }] } }"#,
+        );
+        let mut animation = TextureAnimation::new(&strip(), &meta).unwrap();
+        assert_eq!(animation.first_frame().size(), UVec2::new(2, 2));
+        assert_eq!(animation.first_frame().data, [2; 16]);
+
+        assert!(!animation.tick());
+        assert_eq!(frame(&animation), 2);
+        assert!(animation.tick());
+        assert_eq!(frame(&animation), 0);
+        assert!(animation.tick());
+        assert_eq!(frame(&animation), 2);
+
+        // Every frame of the image in order by default.
+        let mut animation =
+            TextureAnimation::new(&strip(), &metadata(r#"{ "animation": {} }"#)).unwrap();
+        let frames: Vec<_> = (0..4)
+            .map(|_| {
+                animation.tick();
+                frame(&animation)
+            })
+            .collect();
+        assert_eq!(frames, [1, 2, 0, 1]);
+
+        let out_of_range = metadata(r#"{ "animation": { "frames": [3] } }"#);
+        assert!(TextureAnimation::new(&strip(), &out_of_range).is_err());
+        let uneven = metadata(r#"{ "animation": { "height": 4 } }"#);
+        assert!(TextureAnimation::new(&strip(), &uneven).is_err());
+    }
+
+    #[test]
+    fn interpolate() {
+        let mut image = strip();
+        for pixel in image.data.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&[pixel[0] * 100, 0, 0, 255 - pixel[0]]);
+        }
+        let meta = metadata(
+            r#"{ "animation": { "frametime": 4, "interpolate": true, "frames": [0, 2] } }"#,
+        );
+        let mut animation = TextureAnimation::new(&image, &meta).unwrap();
+
+        assert_eq!(animation.pixels()[..4], [0, 0, 0, 255]);
+        assert!(animation.tick());
+        assert_eq!(animation.pixels()[..4], [50, 0, 0, 255]);
+        animation.tick();
+        assert_eq!(animation.pixels()[..4], [100, 0, 0, 255]);
+        animation.tick();
+        animation.tick();
+        assert_eq!(animation.pixels()[..4], [200, 0, 0, 253]);
+    }
+
+    #[test]
+    fn write_to_atlas() {
+        let mut animation =
+            TextureAnimation::new(&strip(), &metadata(r#"{ "animation": {} }"#)).unwrap();
+        animation.rect = URect::new(40, 8, 42, 10);
+        animation.tick();
+
+        // The frame and its border on every mip level, but not the textures
+        // around it.
+        let writes: Vec<_> = animation.writes().collect();
+        let rects: Vec<_> = writes.iter().map(|write| write.rect).collect();
+        assert_eq!(
+            rects,
+            [
+                URect::new(32, 0, 64, 32),
+                URect::new(16, 0, 32, 16),
+                URect::new(8, 0, 16, 8),
+                URect::new(4, 0, 8, 4),
+                URect::new(2, 0, 4, 2),
+            ]
+        );
+        for (level, write) in writes.iter().enumerate() {
+            assert_eq!(write.mip_level, level as u32);
+            assert_eq!(
+                write.data.len(),
+                write.rect.size().element_product() as usize * 4
+            );
+            assert!(write.data.chunks_exact(4).all(|pixel| pixel[0] == 1));
+        }
+    }
+}
@@ -0,0 +1,396 @@
+use bevy::{
+    color::Srgba,
+    prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        texture::{ImageFilterMode, ImageSampler, ImageSamplerDescriptor, TextureFormatPixelInfo},
+    },
+};
+
+/// Mip levels of the block atlas below the full size one.
+pub const MIP_LEVELS: u32 = 4;
+/// Textures take whole multiples of this many pixels in the atlas, with the
+/// packer placing them on multiples of it too, so that the smallest mip
+/// level doesn't mix neighbouring textures.
+const ALIGNMENT: u32 = 1 << MIP_LEVELS;
+/// Pixels the edges of the textures are repeated by around them, for the
+/// mip levels and the filtering at the edges to only see the texture.
+const BORDER: u32 = ALIGNMENT / 2;
+/// Alpha under which the chunk shader discards a pixel.
+const ALPHA_CUTOFF: u8 = 128;
+
+const FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+/// Packs `textures` into an atlas with extruded edges and a mip chain. The
+/// rectangles of the layout are the textures without their border.
+pub fn build_atlas<'a>(
+    textures: impl IntoIterator<Item = (AssetId<Image>, &'a Image)>,
+) -> anyhow::Result<(TextureAtlasLayout, Image)> {
+    let mut sizes = vec![];
+    let mut extruded = vec![];
+    for (id, texture) in textures {
+        let texture = match texture.texture_descriptor.format {
+            FORMAT => texture.clone(),
+            format => texture
+                .convert(FORMAT)
+                .ok_or_else(|| anyhow::anyhow!("can't convert {format:?} texture"))?,
+        };
+        let (data, size) = extrude(&texture.data, texture.size());
+        sizes.push((id, texture.size()));
+        extruded.push((id, image(data, size)));
+    }
+
+    let mut builder = TextureAtlasBuilder::default();
+    builder.format(FORMAT).max_size(UVec2::splat(8192));
+    for (id, image) in &extruded {
+        builder.add_texture(Some(*id), image);
+    }
+    let (mut layout, mut atlas) = builder.build()?;
+
+    let mut padded = vec![];
+    for (id, size) in sizes {
+        let index = layout.get_texture_index(id).unwrap();
+        let min = layout.textures[index].min + BORDER;
+        layout.textures[index] = URect::from_corners(min, min + size);
+        padded.push(padded_rect(layout.textures[index]));
+    }
+
+    atlas.texture_descriptor.mip_level_count = MIP_LEVELS + 1;
+    atlas
+        .data
+        .resize(mip_offset(atlas.size(), MIP_LEVELS + 1), 0);
+    for rect in padded {
+        update_mips(&mut atlas, rect);
+    }
+    atlas.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+        mipmap_filter: ImageFilterMode::Linear,
+        ..ImageSamplerDescriptor::nearest()
+    });
+
+    Ok((layout, atlas))
+}
+
+fn image(data: Vec<u8>, size: UVec2) -> Image {
+    Image::new(
+        Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        FORMAT,
+        RenderAssetUsages::MAIN_WORLD,
+    )
+}
+
+/// Rectangle a texture at `rect` takes in the atlas with its border.
+pub fn padded_rect(rect: URect) -> URect {
+    let min = rect.min - BORDER;
+    min_rect(min, rect.size())
+}
+
+fn min_rect(min: UVec2, size: UVec2) -> URect {
+    let size = size + 2 * BORDER;
+    let size = UVec2::new(
+        size.x.next_multiple_of(ALIGNMENT),
+        size.y.next_multiple_of(ALIGNMENT),
+    );
+    URect::from_corners(min, min + size)
+}
+
+/// Repeats the edge pixels of an RGBA image of `size` around it, `BORDER`
+/// pixels on the top left and enough on the bottom right to get to a
+/// multiple of `ALIGNMENT`. Returns the pixels and their size.
+pub fn extrude(pixels: &[u8], size: UVec2) -> (Vec<u8>, UVec2) {
+    let padded = min_rect(UVec2::ZERO, size).size();
+    let pixel_size = FORMAT.pixel_size();
+
+    let mut data = Vec::with_capacity((padded.x * padded.y) as usize * pixel_size);
+    for y in 0..padded.y {
+        let src_y = y.saturating_sub(BORDER).min(size.y - 1);
+        for x in 0..padded.x {
+            let src_x = x.saturating_sub(BORDER).min(size.x - 1);
+            let start = (src_y * size.x + src_x) as usize * pixel_size;
+            data.extend_from_slice(&pixels[start..start + pixel_size]);
+        }
+    }
+
+    (data, padded)
+}
+
+/// Where mip `level` of an atlas of `size` starts in its data.
+fn mip_offset(size: UVec2, level: u32) -> usize {
+    (0..level)
+        .map(|level| mip_size(size, level))
+        .map(|size| (size.x * size.y) as usize * FORMAT.pixel_size())
+        .sum()
+}
+
+fn mip_size(size: UVec2, level: u32) -> UVec2 {
+    (size >> level).max(UVec2::ONE)
+}
+
+/// Recomputes the mip levels of the atlas in `rect`, the padded rectangle of
+/// a texture, after its pixels changed.
+///
+/// Colours are averaged in linear space, weighted by alpha so that the
+/// colour of transparent pixels doesn't bleed in. Averaging alpha leaves
+/// fewer pixels over the cutoff of the shader, so the alpha of each level is
+/// scaled back up until as much of the texture is drawn as at full size,
+/// keeping leaves and other cutout textures from fading out at a distance.
+fn update_mips(atlas: &mut Image, rect: URect) {
+    let size = atlas.size();
+    let levels = atlas.texture_descriptor.mip_level_count - 1;
+    let coverage = alpha_coverage(&atlas.data, size, rect, 1.0);
+
+    for level in 1..=levels {
+        let (src_size, dst_size) = (mip_size(size, level - 1), mip_size(size, level));
+        let dst_rect = URect::from_corners(rect.min >> level, ((rect.max - 1) >> level) + 1);
+        let (src, dst) = atlas.data.split_at_mut(mip_offset(size, level));
+        let src = &src[mip_offset(size, level - 1)..];
+
+        for y in dst_rect.min.y..dst_rect.max.y {
+            for x in dst_rect.min.x..dst_rect.max.x {
+                let texels = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(dx, dy)| {
+                    let pos = (UVec2::new(x * 2 + dx, y * 2 + dy)).min(src_size - 1);
+                    let start = (pos.y * src_size.x + pos.x) as usize * 4;
+                    <[u8; 4]>::try_from(&src[start..start + 4]).unwrap()
+                });
+                let start = (y * dst_size.x + x) as usize * 4;
+                dst[start..start + 4].copy_from_slice(&average(texels));
+            }
+        }
+
+        let level_data = &mut dst[..(dst_size.x * dst_size.y) as usize * 4];
+        scale_alpha(level_data, dst_size, dst_rect, coverage);
+    }
+}
+
+/// Mip chain of the padded pixels of a texture, of `size`, the way
+/// `update_mips` computes it in the atlas: the pixels of each level, the full
+/// size one first.
+pub fn mip_chain(pixels: Vec<u8>, size: UVec2) -> Vec<Vec<u8>> {
+    let mut image = image(pixels, size);
+    image.texture_descriptor.mip_level_count = MIP_LEVELS + 1;
+    image.data.resize(mip_offset(size, MIP_LEVELS + 1), 0);
+    update_mips(&mut image, URect::from_corners(UVec2::ZERO, size));
+
+    (0..=MIP_LEVELS)
+        .map(|level| image.data[mip_offset(size, level)..mip_offset(size, level + 1)].to_vec())
+        .collect()
+}
+
+/// Average of four texels, in linear space and weighted by alpha.
+fn average(texels: [[u8; 4]; 4]) -> [u8; 4] {
+    let alpha: u32 = texels.iter().map(|texel| texel[3] as u32).sum();
+    let weight = |texel: &[u8; 4]| {
+        if alpha == 0 {
+            1.0
+        } else {
+            texel[3] as f32
+        }
+    };
+    let total: f32 = texels.iter().map(weight).sum();
+
+    let channel = |i: usize| {
+        let linear: f32 = texels
+            .iter()
+            .map(|texel| Srgba::gamma_function(texel[i] as f32 / 255.0) * weight(texel))
+            .sum();
+        (Srgba::gamma_function_inverse(linear / total) * 255.0).round() as u8
+    };
+
+    [
+        channel(0),
+        channel(1),
+        channel(2),
+        (alpha as f32 / 4.0).round() as u8,
+    ]
+}
+
+/// Share of the pixels in `rect` that pass the alpha cutoff once their alpha
+/// is multiplied by `scale`.
+fn alpha_coverage(data: &[u8], size: UVec2, rect: URect, scale: f32) -> f32 {
+    let mut passed = 0;
+    for y in rect.min.y..rect.max.y {
+        for x in rect.min.x..rect.max.x {
+            let alpha = data[(y * size.x + x) as usize * 4 + 3];
+            if (alpha as f32 * scale).min(255.0) >= ALPHA_CUTOFF as f32 {
+                passed += 1;
+            }
+        }
+    }
+
+    passed as f32 / rect.size().element_product() as f32
+}
+
+/// Scales the alpha of the pixels in `rect` for them to pass the cutoff as
+/// often as `coverage`. Alpha is only ever scaled up.
+fn scale_alpha(data: &mut [u8], size: UVec2, rect: URect, coverage: f32) {
+    if alpha_coverage(data, size, rect, 1.0) >= coverage {
+        return;
+    }
+
+    let (mut low, mut high) = (1.0, 255.0);
+    for _ in 0..16 {
+        let scale = (low + high) / 2.0;
+        if alpha_coverage(data, size, rect, scale) >= coverage {
+            high = scale;
+        } else {
+            low = scale;
+        }
+    }
+
+    for y in rect.min.y..rect.max.y {
+        for x in rect.min.x..rect.max.x {
+            let alpha = &mut data[(y * size.x + x) as usize * 4 + 3];
+            *alpha = (*alpha as f32 * high).min(255.0) as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn id(n: u128) -> AssetId<Image> {
+        AssetId::Uuid {
+            uuid: Uuid::from_u128(n),
+        }
+    }
+
+    fn solid(color: [u8; 4], size: UVec2) -> Image {
+        image(color.repeat((size.x * size.y) as usize), size)
+    }
+
+    #[test]
+    fn extrude_edges() {
+        // 2×2 with a different red in each pixel.
+        let pixels = [
+            [0, 0, 0, 255],
+            [1, 0, 0, 255],
+            [2, 0, 0, 255],
+            [3, 0, 0, 255],
+        ]
+        .concat();
+        let (data, size) = extrude(&pixels, UVec2::splat(2));
+        assert_eq!(size, UVec2::splat(ALIGNMENT * 2));
+
+        let red = |x: u32, y: u32| data[(y * size.x + x) as usize * 4];
+        assert_eq!(red(0, 0), 0);
+        assert_eq!(red(BORDER, BORDER), 0);
+        assert_eq!(red(BORDER + 1, BORDER), 1);
+        assert_eq!(red(BORDER, BORDER + 1), 2);
+        assert_eq!(red(size.x - 1, size.y - 1), 3);
+        assert_eq!(red(size.x - 1, 0), 1);
+
+        let (_, size) = extrude(&[0; 16 * 16 * 4], UVec2::splat(16));
+        assert_eq!(size, UVec2::splat(32));
+        assert_eq!(
+            padded_rect(URect::new(40, 8, 56, 24)),
+            URect::new(32, 0, 64, 32)
+        );
+    }
+
+    #[test]
+    fn atlas_layout() {
+        let red = solid([255, 0, 0, 255], UVec2::splat(16));
+        let blue = solid([0, 0, 255, 255], UVec2::new(16, 32));
+        let ids = [id(1), id(2)];
+        let (layout, atlas) = build_atlas([(ids[0], &red), (ids[1], &blue)]).unwrap();
+
+        assert_eq!(atlas.texture_descriptor.mip_level_count, MIP_LEVELS + 1);
+        assert_eq!(atlas.data.len(), mip_offset(atlas.size(), MIP_LEVELS + 1));
+        for (id, texture) in ids.iter().zip([&red, &blue]) {
+            let rect = layout.textures[layout.get_texture_index(*id).unwrap()];
+            assert_eq!(rect.size(), texture.size());
+            assert_eq!(rect.min % ALIGNMENT, UVec2::splat(BORDER));
+
+            // The texture and its border, down to the last mip level.
+            let padded = padded_rect(rect);
+            for level in 0..=MIP_LEVELS {
+                let size = mip_size(atlas.size(), level);
+                let pos = padded.min >> level;
+                let start = mip_offset(atlas.size(), level) + (pos.y * size.x + pos.x) as usize * 4;
+                assert_eq!(
+                    atlas.data[start..start + 4],
+                    texture.data[..4],
+                    "level {level}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn mip_chain_matches_atlas() {
+        // Half under the alpha cutoff, for the alpha to be scaled on the
+        // smaller levels.
+        let pixels: Vec<_> = (0..16 * 16)
+            .flat_map(|i: u32| [i as u8, 0, 0, (i & 3) as u8 * 85])
+            .collect();
+        let texture = image(pixels.clone(), UVec2::splat(16));
+        let (layout, atlas) = build_atlas([(id(1), &texture)]).unwrap();
+        let padded = padded_rect(layout.textures[0]);
+
+        let (pixels, size) = extrude(&pixels, UVec2::splat(16));
+        for (level, data) in (0..).zip(mip_chain(pixels, size)) {
+            let atlas_size = mip_size(atlas.size(), level);
+            let rect = URect::from_corners(padded.min >> level, padded.max >> level);
+            let row = rect.width() as usize * 4;
+            for (y, data) in (rect.min.y..).zip(data.chunks_exact(row)) {
+                let start =
+                    mip_offset(atlas.size(), level) + (y * atlas_size.x + rect.min.x) as usize * 4;
+                assert_eq!(atlas.data[start..start + row], *data, "level {level}");
+            }
+        }
+    }
+
+    #[test]
+    fn average_by_alpha() {
+        // Transparent black doesn't darken the opaque white.
+        let texels = [
+            [255, 255, 255, 255],
+            [0, 0, 0, 0],
+            [0, 0, 0, 0],
+            [0, 0, 0, 0],
+        ];
+        assert_eq!(average(texels), [255, 255, 255, 64]);
+
+        // Mid grey in linear space, brighter than the sRGB average.
+        let texels = [
+            [255, 255, 255, 255],
+            [0, 0, 0, 255],
+            [255, 255, 255, 255],
+            [0, 0, 0, 255],
+        ];
+        assert_eq!(average(texels), [188, 188, 188, 255]);
+    }
+
+    #[test]
+    fn keep_cutout_coverage() {
+        // A checkerboard of opaque and transparent pixels, like leaves.
+        let size = UVec2::splat(16);
+        let data = (0..size.x * size.y)
+            .flat_map(|i| {
+                let alpha = if (i % 16 + i / 16) % 2 == 0 { 200 } else { 0 };
+                [0, 255, 0, alpha]
+            })
+            .collect();
+        let leaves = image(data, size);
+        let (layout, atlas) = build_atlas([(id(1), &leaves)]).unwrap();
+
+        // Averaging would leave every pixel at 100, under the cutoff.
+        let rect = layout.textures[0];
+        for level in 1..=MIP_LEVELS {
+            let size = mip_size(atlas.size(), level);
+            let level_rect = URect::from_corners(rect.min >> level, rect.max >> level);
+            let level_data = &atlas.data[mip_offset(atlas.size(), level)..];
+            let coverage = alpha_coverage(level_data, size, level_rect, 1.0);
+            assert!(coverage >= 0.5, "level {level}: {coverage}");
+        }
+    }
+}
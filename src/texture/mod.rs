@@ -0,0 +1,239 @@
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::ExtractResourcePlugin,
+        render_asset::RenderAssetUsages,
+        render_resource::TextureFormat,
+        texture::{CompressedImageFormats, ImageSampler, ImageType},
+        Render, RenderApp, RenderSet,
+    },
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    resource_pack::{ResourcePack, ResourcePacks},
+    state::AppState,
+};
+
+use self::{
+    animation::{
+        animate_textures, write_atlas, AtlasWrites, TextureAnimation, TextureAnimations,
+        TextureMetadata,
+    },
+    atlas::build_atlas,
+};
+
+pub mod animation;
+pub mod atlas;
+
+/// Block textures read from the resource packs, waiting to be packed into the
+/// atlas.
+#[derive(Resource, Debug, Default)]
+struct LoadedTextures(Vec<LoadedTexture>);
+
+#[derive(Debug)]
+struct LoadedTexture {
+    name: String,
+    handle: Handle<Image>,
+    animation: Option<TextureAnimation>,
+}
+
+/// Texture of the faces whose texture is missing.
+pub const DEBUG_TEXTURE: &str = "minecraft:block/debug";
+
+#[derive(Resource, Debug)]
+pub struct TextureRegistry {
+    pub block: Handle<Image>,
+    pub block_atlas: TextureAtlasLayout,
+    pub textures: HashMap<String, (Handle<Image>, AssetId<Image>)>,
+    /// Textures without any transparent pixel.
+    pub opaque: HashSet<String>,
+}
+
+pub struct TexturePlugin;
+impl Plugin for TexturePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::LoadingTextures), load_textures);
+        app.add_systems(OnEnter(AppState::ProcessingTextures), create_texture_atlas);
+        app.init_resource::<AtlasWrites>();
+        app.add_plugins(ExtractResourcePlugin::<AtlasWrites>::default());
+        app.add_systems(
+            Update,
+            animate_textures.run_if(resource_exists::<TextureAnimations>),
+        );
+        if let Some(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app.add_systems(Render, write_atlas.in_set(RenderSet::PrepareResources));
+        }
+    }
+}
+
+fn load_textures(
+    mut commands: Commands,
+    mut next_state: ResMut<NextState<AppState>>,
+    packs: Res<ResourcePacks>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let mut textures = vec![];
+    for (name, path) in packs.list("textures", "block", ".png") {
+        let Some(pack) = packs.find(&path) else {
+            continue;
+        };
+        let image = match pack.read(&path).unwrap().and_then(|data| decode_png(&data)) {
+            Ok(image) => image,
+            Err(e) => {
+                warn!("failed to load texture {name}: {e:#}");
+                continue;
+            }
+        };
+        // The animation comes from the same pack as the texture, its frames
+        // wouldn't match another one's.
+        let animation = load_animation(pack, &path, &image);
+        textures.push(LoadedTexture {
+            name,
+            handle: images.add(image),
+            animation,
+        });
+    }
+
+    commands.insert_resource(LoadedTextures(textures));
+    next_state.set(AppState::ProcessingTextures);
+}
+
+fn create_texture_atlas(
+    mut commands: Commands,
+    mut next_state: ResMut<NextState<AppState>>,
+    loaded: Res<LoadedTextures>,
+    mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
+    mut textures: ResMut<Assets<Image>>,
+) {
+    let mut textures_map = HashMap::new();
+    let mut opaque = HashSet::new();
+    let mut animations = vec![];
+    for texture in &loaded.0 {
+        let id = texture.handle.id();
+        let Some(image) = textures.get(id) else {
+            continue;
+        };
+        if is_opaque(image) {
+            opaque.insert(texture.name.clone());
+        }
+        if let Some(animation) = &texture.animation {
+            animations.push((id, animation.clone()));
+        }
+        textures_map.insert(texture.name.clone(), (texture.handle.clone(), id));
+    }
+    commands.remove_resource::<LoadedTextures>();
+
+    // Only the current frame of animated textures is in the atlas.
+    let first_frames: HashMap<_, _> = animations
+        .iter()
+        .map(|(id, animation)| (*id, animation.first_frame()))
+        .collect();
+    let (layout, texture) = fit_atlas(&mut textures_map, &first_frames, &mut textures);
+    opaque.retain(|name| textures_map.contains_key(name));
+    animations.retain(|(id, _)| layout.get_texture_index(*id).is_some());
+    for (id, animation) in &mut animations {
+        let index = layout.get_texture_index(*id).unwrap();
+        animation.rect = layout.textures[index];
+    }
+    commands.insert_resource(TextureAnimations::new(
+        animations
+            .into_iter()
+            .map(|(_, animation)| animation)
+            .collect(),
+    ));
+
+    let texture_handle = textures.add(texture);
+    texture_atlases.add(layout.clone());
+
+    commands.insert_resource(TextureRegistry {
+        block: texture_handle,
+        block_atlas: layout,
+        textures: textures_map,
+        opaque,
+    });
+
+    next_state.set(AppState::LoadingModels);
+}
+
+/// Packs can have more or larger textures than fit in the atlas. The largest
+/// ones are left out of `textures_map` until the rest fits, their faces
+/// showing the debug texture instead.
+fn fit_atlas(
+    textures_map: &mut HashMap<String, (Handle<Image>, AssetId<Image>)>,
+    first_frames: &HashMap<AssetId<Image>, Image>,
+    textures: &mut Assets<Image>,
+) -> (TextureAtlasLayout, Image) {
+    loop {
+        let image = |id: &AssetId<Image>| first_frames.get(id).or_else(|| textures.get(*id));
+        let atlas_textures = textures_map
+            .values()
+            .filter_map(|(_, id)| Some((*id, image(id)?)));
+        let e = match build_atlas(atlas_textures) {
+            Ok(atlas) => return atlas,
+            Err(e) => e,
+        };
+
+        let area =
+            |id: &AssetId<Image>| image(id).map_or(0, |image| image.width() * image.height());
+        let largest = textures_map
+            .iter()
+            .filter(|(name, _)| *name != DEBUG_TEXTURE)
+            .map(|(_, (_, id))| area(id))
+            .max();
+        match largest {
+            Some(largest) => {
+                let before = textures_map.len();
+                textures_map.retain(|name, (_, id)| name == DEBUG_TEXTURE || area(id) < largest);
+                warn!(
+                    "{e:#}, leaving out {} textures of {largest} pixels",
+                    before - textures_map.len()
+                );
+            }
+            // Only a debug texture that doesn't fit on its own is left.
+            None => {
+                warn!("{e:#}, replacing the debug texture");
+                let handle = textures.add(Image::default());
+                textures_map.insert(DEBUG_TEXTURE.to_string(), (handle.clone(), handle.id()));
+            }
+        }
+    }
+}
+
+/// Animation of the texture at `path` in `pack`, from its `.mcmeta` file.
+/// `None` for textures that aren't animated or whose animation is broken.
+fn load_animation(pack: &ResourcePack, path: &str, texture: &Image) -> Option<TextureAnimation> {
+    let data = pack.read(&format!("{path}.mcmeta"))?;
+
+    let animation = data
+        .and_then(|data| Ok(serde_json::from_slice::<TextureMetadata>(&data)?))
+        .and_then(|metadata| {
+            metadata
+                .animation
+                .map(|animation| TextureAnimation::new(texture, &animation))
+                .transpose()
+        });
+    animation.unwrap_or_else(|e| {
+        warn!("failed to load animation of {path}: {e:#}");
+        None
+    })
+}
+
+/// Decodes a PNG file of a resource pack.
+pub fn decode_png(data: &[u8]) -> anyhow::Result<Image> {
+    Ok(Image::from_buffer(
+        data,
+        ImageType::Extension("png"),
+        CompressedImageFormats::NONE,
+        true,
+        ImageSampler::Default,
+        RenderAssetUsages::MAIN_WORLD,
+    )?)
+}
+
+fn is_opaque(image: &Image) -> bool {
+    matches!(
+        image.texture_descriptor.format,
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb
+    ) && image.data.chunks_exact(4).all(|pixel| pixel[3] == u8::MAX)
+}
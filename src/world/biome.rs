@@ -0,0 +1,345 @@
+use std::sync::Arc;
+
+use anyhow::{ensure, Context};
+use bevy::{prelude::*, render::render_resource::TextureFormat};
+use protocol::{nbt::from_nbt, packets::RegistryData, Typed};
+use serde::Deserialize;
+
+use crate::{
+    block::tint::{linear_rgb, Tint},
+    resource_pack::ResourcePacks,
+    texture::decode_png,
+};
+
+const GRASS_COLORMAP: &str = "assets/minecraft/textures/colormap/grass.png";
+const FOLIAGE_COLORMAP: &str = "assets/minecraft/textures/colormap/foliage.png";
+
+/// Colours of the plains, used for biomes the server didn't send and where
+/// the colormaps are missing.
+const DEFAULT_GRASS: u32 = 0x91bd59;
+const DEFAULT_FOLIAGE: u32 = 0x77ab2f;
+const DEFAULT_WATER: u32 = 0x3f76e4;
+
+/// Colours a biome tints blocks with, in linear RGB.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BiomeColors {
+    pub grass: Vec3,
+    pub foliage: Vec3,
+    pub water: Vec3,
+}
+
+impl Default for BiomeColors {
+    fn default() -> Self {
+        Self {
+            grass: linear_rgb(DEFAULT_GRASS),
+            foliage: linear_rgb(DEFAULT_FOLIAGE),
+            water: linear_rgb(DEFAULT_WATER),
+        }
+    }
+}
+
+impl BiomeColors {
+    pub fn tint(&self, tint: Tint) -> Vec3 {
+        match tint {
+            Tint::Grass => self.grass,
+            Tint::Foliage => self.foliage,
+            Tint::Water => self.water,
+            Tint::Fixed(color) => color,
+        }
+    }
+}
+
+/// Image picking the colour of grass or foliage from the temperature and
+/// downfall of a biome, `grass.png` or `foliage.png`.
+#[derive(Debug, Clone, Default)]
+pub struct Colormap {
+    width: usize,
+    /// `0xRRGGBB` in sRGB, row by row.
+    pixels: Vec<u32>,
+}
+
+impl Colormap {
+    /// Colormap at `path` in the highest resource pack that has it.
+    pub fn load(packs: &ResourcePacks, path: &str) -> anyhow::Result<Self> {
+        let data = packs.read(path).context("missing colormap")??;
+
+        Self::from_image(&decode_png(&data)?)
+    }
+
+    pub fn from_image(image: &Image) -> anyhow::Result<Self> {
+        ensure!(
+            matches!(
+                image.texture_descriptor.format,
+                TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb
+            ),
+            "unsupported colormap format {:?}",
+            image.texture_descriptor.format
+        );
+
+        Ok(Self {
+            width: image.width() as usize,
+            pixels: image
+                .data
+                .chunks_exact(4)
+                .map(|pixel| u32::from_be_bytes([0, pixel[0], pixel[1], pixel[2]]))
+                .collect(),
+        })
+    }
+
+    /// Colour at the temperature and downfall of a biome, like vanilla: the
+    /// downfall is scaled by the temperature, so hot and wet is the bottom
+    /// left corner and cold is the right side. `None` when the colormap
+    /// doesn't have that pixel.
+    pub fn sample(&self, temperature: f32, downfall: f32) -> Option<u32> {
+        let temperature = temperature.clamp(0.0, 1.0) as f64;
+        let downfall = downfall.clamp(0.0, 1.0) as f64 * temperature;
+        let x = ((1.0 - temperature) * 255.0) as usize;
+        let y = ((1.0 - downfall) * 255.0) as usize;
+        if x >= self.width {
+            return None;
+        }
+
+        self.pixels.get(y * self.width + x).copied()
+    }
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct Colormaps {
+    pub grass: Colormap,
+    pub foliage: Colormap,
+}
+
+pub fn load_colormaps(mut commands: Commands, packs: Res<ResourcePacks>) {
+    let load = |path| {
+        Colormap::load(&packs, path).unwrap_or_else(|e| {
+            warn!("failed to load colormap {path}: {e:#}");
+            Colormap::default()
+        })
+    };
+
+    commands.insert_resource(Colormaps {
+        grass: load(GRASS_COLORMAP),
+        foliage: load(FOLIAGE_COLORMAP),
+    });
+}
+
+/// Biomes of the registry data the server sent, by the IDs chunk sections
+/// use.
+#[derive(Resource, Debug, Default)]
+pub struct BiomeRegistry {
+    pub names: Vec<String>,
+    /// Shared with the meshing tasks.
+    pub colors: Arc<Vec<BiomeColors>>,
+    /// What the server sent, to recompute the colours with other colormaps.
+    data: Vec<Option<BiomeData>>,
+}
+
+impl BiomeRegistry {
+    fn recolor(&mut self, colormaps: &Colormaps) {
+        let colors = self.data.iter().map(|biome| match biome {
+            Some(biome) => biome.colors(colormaps),
+            None => BiomeColors::default(),
+        });
+        self.colors = Arc::new(colors.collect());
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct BiomeData {
+    temperature: f32,
+    downfall: f32,
+    effects: BiomeEffects,
+}
+
+#[derive(Deserialize, Debug)]
+struct BiomeEffects {
+    water_color: i32,
+    grass_color: Option<i32>,
+    foliage_color: Option<i32>,
+    grass_color_modifier: Option<String>,
+}
+
+impl BiomeData {
+    fn colors(&self, colormaps: &Colormaps) -> BiomeColors {
+        let effects = &self.effects;
+        let grass = effects
+            .grass_color
+            .map(|color| color as u32)
+            .or_else(|| colormaps.grass.sample(self.temperature, self.downfall))
+            .unwrap_or(DEFAULT_GRASS);
+        let grass = match effects.grass_color_modifier.as_deref() {
+            Some("dark_forest") => ((grass & 0xfefefe) + 0x28340a) >> 1,
+            // Vanilla picks between two colours with noise, this is the more
+            // common one.
+            Some("swamp") => 0x4c763c,
+            _ => grass,
+        };
+        let foliage = effects
+            .foliage_color
+            .map(|color| color as u32)
+            .or_else(|| colormaps.foliage.sample(self.temperature, self.downfall))
+            .unwrap_or(DEFAULT_FOLIAGE);
+
+        BiomeColors {
+            grass: linear_rgb(grass),
+            foliage: linear_rgb(foliage),
+            water: linear_rgb(effects.water_color as u32),
+        }
+    }
+}
+
+/// Replaces the `BiomeRegistry` when the server sends the biome registry.
+pub fn handle_registry_data(
+    mut biomes: ResMut<BiomeRegistry>,
+    colormaps: Res<Colormaps>,
+    mut pkts: EventReader<Typed<RegistryData>>,
+) {
+    for pkt in pkts.read() {
+        if pkt.registry_id != "minecraft:worldgen/biome" {
+            continue;
+        }
+
+        let mut names = vec![];
+        let mut data = vec![];
+        for (name, nbt) in pkt.entries.iter() {
+            let biome = nbt
+                .as_ref()
+                .context("no data")
+                .and_then(|nbt| Ok(from_nbt::<BiomeData>(nbt)?));
+            data.push(
+                biome
+                    .inspect_err(|e| warn!("failed to read biome {name}: {e:#}"))
+                    .ok(),
+            );
+            names.push(name.clone());
+        }
+
+        *biomes = BiomeRegistry {
+            names,
+            data,
+            ..default()
+        };
+        biomes.recolor(&colormaps);
+    }
+}
+
+/// Recomputes the biome colours with the colormaps of the resource packs
+/// after they were reloaded.
+pub fn recolor_biomes(mut biomes: ResMut<BiomeRegistry>, colormaps: Res<Colormaps>) {
+    biomes.recolor(&colormaps);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        ecs::system::RunSystemOnce,
+        render::{
+            render_asset::RenderAssetUsages,
+            render_resource::{Extent3d, TextureDimension},
+        },
+        utils::Instant,
+    };
+    use protocol::{LenPrefixed, NBT};
+
+    use super::*;
+
+    /// Colormap with the X coordinate in red and the Y one in green.
+    fn colormap() -> Colormap {
+        let mut image = Image::new_fill(
+            Extent3d {
+                width: 256,
+                height: 256,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &[0, 0, 0, 255],
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::MAIN_WORLD,
+        );
+        for (i, pixel) in image.data.chunks_exact_mut(4).enumerate() {
+            pixel[0] = (i % 256) as u8;
+            pixel[1] = (i / 256) as u8;
+        }
+
+        Colormap::from_image(&image).unwrap()
+    }
+
+    #[test]
+    fn sample() {
+        let colormap = colormap();
+        assert_eq!(colormap.sample(1.0, 1.0), Some(0x000000));
+        assert_eq!(colormap.sample(0.5, 1.0), Some(0x7f7f00));
+        assert_eq!(colormap.sample(2.0, -1.0), Some(0x00ff00));
+        assert_eq!(colormap.sample(0.0, 0.5), Some(0xffff00));
+        assert_eq!(Colormap::default().sample(0.5, 0.5), None);
+    }
+
+    #[test]
+    fn registry_data() {
+        let mut world = World::new();
+        world.init_resource::<BiomeRegistry>();
+        world.insert_resource(Colormaps {
+            grass: colormap(),
+            foliage: colormap(),
+        });
+        world.init_resource::<Events<Typed<RegistryData>>>();
+
+        let biome = |snbt: &str| Some(snbt.parse::<NBT>().unwrap());
+        let entries = [
+            (
+                "minecraft:plains".to_string(),
+                biome("{temperature: 0.5f, downfall: 1.0f, effects: {water_color: 4159204}}"),
+            ),
+            (
+                "minecraft:dark_forest".to_string(),
+                biome(
+                    "{temperature: 1.0f, downfall: 1.0f, effects: {water_color: 0, \
+                     foliage_color: 1056816, grass_color_modifier: \"dark_forest\"}}",
+                ),
+            ),
+            ("minecraft:unknown".to_string(), None),
+        ];
+        world.send_event(Typed::new(
+            Instant::now(),
+            RegistryData {
+                registry_id: "minecraft:worldgen/biome".to_string(),
+                entries: LenPrefixed(entries.to_vec()),
+            },
+        ));
+        world.send_event(Typed::new(
+            Instant::now(),
+            RegistryData {
+                registry_id: "minecraft:dimension_type".to_string(),
+                entries: LenPrefixed(vec![]),
+            },
+        ));
+        world.run_system_once(handle_registry_data);
+
+        let biomes = world.resource::<BiomeRegistry>();
+        assert_eq!(
+            biomes.names,
+            [
+                "minecraft:plains",
+                "minecraft:dark_forest",
+                "minecraft:unknown"
+            ]
+        );
+        assert_eq!(biomes.colors[0].grass, linear_rgb(0x7f7f00));
+        assert_eq!(biomes.colors[0].water, linear_rgb(0x3f76e4));
+        assert_eq!(biomes.colors[1].grass, linear_rgb(0x28340a >> 1));
+        assert_eq!(biomes.colors[1].foliage, linear_rgb(0x102030));
+        assert_eq!(biomes.colors[2], BiomeColors::default());
+
+        // A resource pack without colormaps falls back to the plains colours.
+        world.insert_resource(Colormaps::default());
+        world.run_system_once(recolor_biomes);
+        let biomes = world.resource::<BiomeRegistry>();
+        assert_eq!(biomes.colors[0].grass, linear_rgb(DEFAULT_GRASS));
+        assert_eq!(
+            biomes.colors[1].grass,
+            linear_rgb(((DEFAULT_GRASS & 0xfefefe) + 0x28340a) >> 1)
+        );
+        assert_eq!(biomes.colors[1].foliage, linear_rgb(0x102030));
+        assert_eq!(biomes.colors[2], BiomeColors::default());
+    }
+}
@@ -0,0 +1,622 @@
+use anyhow::{bail, ensure, Context};
+use protocol::{Decode, VarInt};
+
+use crate::block::BlockStateRegistry;
+
+use super::light::ChunkLight;
+
+/// Number of blocks along each edge of a chunk section.
+pub const SECTION_WIDTH: usize = 16;
+pub const SECTION_VOLUME: usize = SECTION_WIDTH * SECTION_WIDTH * SECTION_WIDTH;
+/// Biomes are stored once per cell of 4x4x4 blocks.
+pub const BIOME_WIDTH: usize = 4;
+pub const BIOME_VOLUME: usize = BIOME_WIDTH * BIOME_WIDTH * BIOME_WIDTH;
+
+/// Column of chunk sections, from the bottom of the world up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub x: i32,
+    pub z: i32,
+    pub sections: Vec<ChunkSection>,
+    pub light: ChunkLight,
+}
+
+impl Chunk {
+    /// Decodes the `data` of a `ChunkDataAndUpdateLight` packet. Every block
+    /// state has to be in `blocks`. The chunk has no light data until it is
+    /// updated from the rest of the packet.
+    pub fn decode(
+        x: i32,
+        z: i32,
+        data: &[u8],
+        blocks: &BlockStateRegistry,
+    ) -> anyhow::Result<Self> {
+        let mut rdr = data;
+        let mut sections = vec![];
+
+        while !rdr.is_empty() {
+            let section = ChunkSection::decode(&mut rdr, blocks)
+                .with_context(|| format!("failed to decode section {}", sections.len()))?;
+            sections.push(section);
+        }
+
+        Ok(Self {
+            x,
+            z,
+            light: ChunkLight::new(sections.len()),
+            sections,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkSection {
+    /// Number of blocks that aren't air.
+    pub block_count: u16,
+    pub block_states: PalettedContainer,
+    pub biomes: PalettedContainer,
+}
+
+impl ChunkSection {
+    pub fn decode(rdr: &mut &[u8], blocks: &BlockStateRegistry) -> anyhow::Result<Self> {
+        // Counted from the block states instead, a wrong count from the
+        // server would make the section look empty or overflow on updates.
+        i16::decode(rdr)?;
+
+        let block_states = PalettedContainer::decode(rdr, &BLOCK_STATES)
+            .context("failed to decode block states")?;
+        if let Some(id) = block_states.find(|id| !blocks.contains(id)) {
+            bail!("unknown block state {id}");
+        }
+
+        let biomes = PalettedContainer::decode(rdr, &BIOMES).context("failed to decode biomes")?;
+
+        let block_count = block_states.count(&BLOCK_STATES, |id| !blocks.is_air(id));
+
+        Ok(Self {
+            block_count: block_count as u16,
+            block_states,
+            biomes,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.block_count == 0
+    }
+
+    /// Block state ID at section relative coordinates, which can be looked up
+    /// in the `BlockStateRegistry`.
+    pub fn block_state(&self, x: usize, y: usize, z: usize) -> i32 {
+        self.block_states
+            .get((y * SECTION_WIDTH + z) * SECTION_WIDTH + x)
+    }
+
+    /// Replaces the block state at section relative coordinates and returns
+    /// the previous one.
+    pub fn set_block_state(
+        &mut self,
+        x: usize,
+        y: usize,
+        z: usize,
+        id: i32,
+        blocks: &BlockStateRegistry,
+    ) -> i32 {
+        let index = (y * SECTION_WIDTH + z) * SECTION_WIDTH + x;
+        let old = self.block_states.set(index, id, &BLOCK_STATES);
+
+        match (blocks.is_air(old), blocks.is_air(id)) {
+            (true, false) => self.block_count += 1,
+            (false, true) => self.block_count -= 1,
+            _ => {}
+        }
+
+        old
+    }
+
+    /// Biome ID of the cell containing the block at section relative
+    /// coordinates.
+    pub fn biome(&self, x: usize, y: usize, z: usize) -> i32 {
+        let (x, y, z) = (x / BIOME_WIDTH, y / BIOME_WIDTH, z / BIOME_WIDTH);
+        self.biomes.get((y * BIOME_WIDTH + z) * BIOME_WIDTH + x)
+    }
+}
+
+/// How a paletted container is encoded, which differs between block states
+/// and biomes.
+struct PaletteFormat {
+    entries: usize,
+    /// Indirect palettes use at least this many bits per entry, even if the
+    /// server sent less.
+    min_indirect_bits: u8,
+    /// Anything above uses registry IDs directly.
+    max_indirect_bits: u8,
+}
+
+const BLOCK_STATES: PaletteFormat = PaletteFormat {
+    entries: SECTION_VOLUME,
+    min_indirect_bits: 4,
+    max_indirect_bits: 8,
+};
+
+const BIOMES: PaletteFormat = PaletteFormat {
+    entries: BIOME_VOLUME,
+    min_indirect_bits: 1,
+    max_indirect_bits: 3,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PalettedContainer {
+    /// Every entry has the same ID.
+    Single(i32),
+    /// Entries are indices into the palette.
+    Indirect {
+        palette: Vec<i32>,
+        data: PackedArray,
+    },
+    /// Entries are registry IDs.
+    Direct(PackedArray),
+}
+
+impl PalettedContainer {
+    fn decode(rdr: &mut &[u8], format: &PaletteFormat) -> anyhow::Result<Self> {
+        let bits = u8::decode(rdr)?;
+
+        if bits == 0 {
+            let id = VarInt::decode(rdr)?.0;
+            // The data array is empty, but it is still sent.
+            Vec::<u64>::decode(rdr)?;
+
+            return Ok(Self::Single(id));
+        }
+
+        if bits <= format.max_indirect_bits {
+            let palette: Vec<i32> = Vec::<VarInt>::decode(rdr)?
+                .into_iter()
+                .map(|id| id.0)
+                .collect();
+            ensure!(!palette.is_empty(), "palette is empty");
+
+            let bits = bits.max(format.min_indirect_bits);
+            let data = PackedArray::decode(rdr, bits, format.entries)?;
+            if let Some(index) = data.iter().find(|index| *index as usize >= palette.len()) {
+                bail!(
+                    "palette index {index} is out of bounds for {} entries",
+                    palette.len()
+                );
+            }
+
+            return Ok(Self::Indirect { palette, data });
+        }
+
+        ensure!(bits <= 32, "{bits} bits per entry is too many");
+        Ok(Self::Direct(PackedArray::decode(
+            rdr,
+            bits,
+            format.entries,
+        )?))
+    }
+
+    pub fn get(&self, index: usize) -> i32 {
+        match self {
+            Self::Single(id) => *id,
+            Self::Indirect { palette, data } => palette[data.get(index) as usize],
+            Self::Direct(data) => data.get(index) as i32,
+        }
+    }
+
+    /// Replaces the ID at `index` and returns the previous one. The palette
+    /// grows as needed and switches to registry IDs once it gets too big.
+    fn set(&mut self, index: usize, id: i32, format: &PaletteFormat) -> i32 {
+        let old = self.get(index);
+        if old == id {
+            return old;
+        }
+
+        if let Self::Single(single) = *self {
+            *self = Self::Indirect {
+                palette: vec![single],
+                data: PackedArray::new(format.min_indirect_bits, format.entries),
+            };
+        }
+
+        if let Self::Indirect { palette, data } = self {
+            let palette_index = palette.iter().position(|p| *p == id).unwrap_or_else(|| {
+                palette.push(id);
+                palette.len() - 1
+            });
+
+            if palette_index < 1 << data.bits {
+                data.set(index, palette_index as u32);
+                return old;
+            }
+
+            if data.bits < format.max_indirect_bits {
+                *data = data.repack(data.bits + 1);
+                data.set(index, palette_index as u32);
+                return old;
+            }
+
+            let ids = data.iter().map(|i| palette[i as usize] as u32);
+            let bits = bits_for(palette.iter().max().copied().unwrap_or(0) as u32)
+                .max(format.max_indirect_bits + 1);
+            *self = Self::Direct(PackedArray::from_iter(bits, format.entries, ids));
+        }
+
+        if let Self::Direct(data) = self {
+            let bits = bits_for(id as u32);
+            if bits > data.bits {
+                *data = data.repack(bits);
+            }
+            data.set(index, id as u32);
+        }
+
+        old
+    }
+
+    /// Number of entries whose ID matches `predicate`. Indirect palettes
+    /// check each ID of the palette once.
+    fn count(&self, format: &PaletteFormat, mut predicate: impl FnMut(i32) -> bool) -> usize {
+        match self {
+            Self::Single(id) if predicate(*id) => format.entries,
+            Self::Single(_) => 0,
+            Self::Indirect { palette, data } => {
+                let matches: Vec<_> = palette.iter().map(|id| predicate(*id)).collect();
+                data.iter().filter(|index| matches[*index as usize]).count()
+            }
+            Self::Direct(data) => data.iter().filter(|id| predicate(*id as i32)).count(),
+        }
+    }
+
+    /// Returns the first ID in the container that matches `predicate`.
+    /// Indirect palettes are checked without looking at the entries.
+    fn find(&self, mut predicate: impl FnMut(i32) -> bool) -> Option<i32> {
+        match self {
+            Self::Single(id) => Some(*id).filter(|id| predicate(*id)),
+            Self::Indirect { palette, .. } => palette.iter().copied().find(|id| predicate(*id)),
+            Self::Direct(data) => data.iter().map(|id| id as i32).find(|id| predicate(*id)),
+        }
+    }
+}
+
+/// Fixed size entries packed into longs, starting from the least significant
+/// bits. Entries never span two longs, leftover bits are padding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackedArray {
+    bits: u8,
+    len: usize,
+    longs: Vec<u64>,
+}
+
+impl PackedArray {
+    fn decode(rdr: &mut &[u8], bits: u8, len: usize) -> anyhow::Result<Self> {
+        let longs = Vec::<u64>::decode(rdr)?;
+
+        let expected = len.div_ceil(64 / bits as usize);
+        ensure!(
+            longs.len() == expected,
+            "expected {expected} longs for {len} entries of {bits} bits, got {}",
+            longs.len()
+        );
+
+        Ok(Self { bits, len, longs })
+    }
+
+    fn new(bits: u8, len: usize) -> Self {
+        Self {
+            bits,
+            len,
+            longs: vec![0; len.div_ceil(64 / bits as usize)],
+        }
+    }
+
+    fn from_iter(bits: u8, len: usize, vals: impl Iterator<Item = u32>) -> Self {
+        let mut array = Self::new(bits, len);
+        for (index, val) in vals.enumerate() {
+            array.set(index, val);
+        }
+        array
+    }
+
+    /// Copies the entries to an array with a different number of bits per
+    /// entry.
+    fn repack(&self, bits: u8) -> Self {
+        Self::from_iter(bits, self.len, self.iter())
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.bits
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn get(&self, index: usize) -> u32 {
+        assert!(
+            index < self.len,
+            "index {index} out of bounds for {}",
+            self.len
+        );
+
+        let per_long = 64 / self.bits as usize;
+        let shift = (index % per_long) * self.bits as usize;
+        let mask = (1u64 << self.bits) - 1;
+
+        ((self.longs[index / per_long] >> shift) & mask) as u32
+    }
+
+    pub fn set(&mut self, index: usize, val: u32) {
+        assert!(
+            index < self.len,
+            "index {index} out of bounds for {}",
+            self.len
+        );
+        assert!(
+            bits_for(val) <= self.bits,
+            "{val} doesn't fit in {} bits",
+            self.bits
+        );
+
+        let per_long = 64 / self.bits as usize;
+        let shift = (index % per_long) * self.bits as usize;
+        let mask = (1u64 << self.bits) - 1;
+
+        let long = &mut self.longs[index / per_long];
+        *long = (*long & !(mask << shift)) | (val as u64) << shift;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.len).map(|index| self.get(index))
+    }
+}
+
+/// Number of bits needed to store `val`.
+fn bits_for(val: u32) -> u8 {
+    (u32::BITS - val.leading_zeros()) as u8
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use bevy::utils::HashMap;
+    use bytes::BytesMut;
+    use protocol::Encode;
+
+    use super::*;
+
+    pub(in crate::world) const AIR: i32 = 0;
+    pub(in crate::world) const STONE: i32 = 1;
+    pub(in crate::world) const GRASS_BLOCK: i32 = 9;
+    pub(in crate::world) const DIRT: i32 = 10;
+    pub(in crate::world) const BEDROCK: i32 = 79;
+    pub(in crate::world) const TORCH: i32 = 2355;
+    pub(in crate::world) const PLAINS: i32 = 39;
+
+    pub(in crate::world) fn registry() -> BlockStateRegistry {
+        let definitions: HashMap<_, _> = serde_json::from_str(
+            r#"{
+                "minecraft:air": { "definition": {}, "states": [{ "id": 0, "default": true }] },
+                "minecraft:stone": { "definition": {}, "states": [{ "id": 1, "default": true }] },
+                "minecraft:grass_block": {
+                    "definition": {},
+                    "properties": { "snowy": ["true", "false"] },
+                    "states": [
+                        { "id": 8, "properties": { "snowy": "true" } },
+                        { "id": 9, "default": true, "properties": { "snowy": "false" } }
+                    ]
+                },
+                "minecraft:dirt": { "definition": {}, "states": [{ "id": 10, "default": true }] },
+                "minecraft:bedrock": { "definition": {}, "states": [{ "id": 79, "default": true }] },
+                "minecraft:torch": { "definition": {}, "states": [{ "id": 2355, "default": true }] }
+            }"#,
+        )
+        .unwrap();
+
+        BlockStateRegistry::new(definitions)
+    }
+
+    /// The superflat chunk of `testdata/flat_chunk.bin` at `x`, `z`.
+    pub(in crate::world) fn flat_chunk(x: i32, z: i32) -> Chunk {
+        let data = include_bytes!("testdata/flat_chunk.bin");
+        Chunk::decode(x, z, data, &registry()).unwrap()
+    }
+
+    fn pack(bits: u8, values: &[u32]) -> Vec<u64> {
+        let per_long = 64 / bits as usize;
+        values
+            .chunks(per_long)
+            .map(|chunk| {
+                chunk.iter().enumerate().fold(0, |long, (i, val)| {
+                    long | (*val as u64) << (i * bits as usize)
+                })
+            })
+            .collect()
+    }
+
+    fn encode_container(buf: &mut BytesMut, bits: u8, palette: &[i32], longs: &[u64]) {
+        bits.encode(buf).unwrap();
+        if bits == 0 {
+            VarInt(palette[0]).encode(buf).unwrap();
+        } else if !palette.is_empty() {
+            let palette: Vec<_> = palette.iter().map(|id| VarInt(*id)).collect();
+            palette.encode(buf).unwrap();
+        }
+        longs.encode(buf).unwrap();
+    }
+
+    /// Built by hand in the layout a vanilla 1.21 server sends a chunk of the
+    /// default superflat world in: bedrock, two layers of dirt and grass in
+    /// the bottom section, 23 empty sections above and plains everywhere.
+    #[test]
+    fn decode_flat_chunk() {
+        let chunk = flat_chunk(3, -2);
+
+        assert_eq!((chunk.x, chunk.z), (3, -2));
+        assert_eq!(chunk.sections.len(), 24);
+
+        let bottom = &chunk.sections[0];
+        assert_eq!(bottom.block_count, 1024);
+        assert!(matches!(
+            &bottom.block_states,
+            PalettedContainer::Indirect { palette, data } if palette.len() == 4 && data.bits() == 4
+        ));
+        for (x, z) in [(0, 0), (15, 15), (7, 3)] {
+            assert_eq!(bottom.block_state(x, 0, z), BEDROCK);
+            assert_eq!(bottom.block_state(x, 1, z), DIRT);
+            assert_eq!(bottom.block_state(x, 2, z), DIRT);
+            assert_eq!(bottom.block_state(x, 3, z), GRASS_BLOCK);
+            assert_eq!(bottom.block_state(x, 4, z), AIR);
+            assert_eq!(bottom.block_state(x, 15, z), AIR);
+        }
+        assert_eq!(bottom.biome(5, 5, 5), PLAINS);
+
+        for section in &chunk.sections[1..] {
+            assert!(section.is_empty());
+            assert_eq!(section.block_states, PalettedContainer::Single(AIR));
+            assert_eq!(section.block_state(8, 8, 8), AIR);
+            assert_eq!(section.biome(15, 15, 15), PLAINS);
+        }
+    }
+
+    #[test]
+    fn direct_and_indirect_biomes() {
+        // 15 bits per entry, as the server sends for the global palette, so
+        // four entries fit in a long with four bits of padding.
+        let states: Vec<u32> = (0..SECTION_VOLUME as u32)
+            .map(|i| [STONE, DIRT, GRASS_BLOCK][i as usize % 3] as u32)
+            .collect();
+        // Three bits per entry, where 21 fit in a long.
+        let biomes: Vec<u32> = (0..BIOME_VOLUME as u32).map(|i| i % 2).collect();
+
+        let mut buf = BytesMut::new();
+        4096i16.encode(&mut buf).unwrap();
+        encode_container(&mut buf, 15, &[], &pack(15, &states));
+        encode_container(&mut buf, 3, &[PLAINS, 7], &pack(3, &biomes));
+
+        let section = ChunkSection::decode(&mut &buf[..], &registry()).unwrap();
+        assert_eq!(section.block_state(0, 0, 0), STONE);
+        assert_eq!(section.block_state(1, 0, 0), DIRT);
+        assert_eq!(section.block_state(2, 0, 0), GRASS_BLOCK);
+        assert_eq!(
+            section.block_state(15, 15, 15),
+            [STONE, DIRT, GRASS_BLOCK][4095 % 3]
+        );
+
+        assert_eq!(section.biome(0, 0, 0), PLAINS);
+        assert_eq!(section.biome(4, 0, 0), 7);
+        assert_eq!(section.biome(15, 15, 15), 7);
+    }
+
+    #[test]
+    fn indirect_palette_widths() {
+        // Every width a server uses for palettes, where entries don't fill the
+        // longs for most of them and the leftover high bits are padding.
+        let palette = [AIR, STONE, 8, GRASS_BLOCK, DIRT, BEDROCK, TORCH];
+        let states: Vec<u32> = (0..SECTION_VOLUME as u32).map(|i| i * 5 % 7).collect();
+        let biomes: Vec<u32> = (0..BIOME_VOLUME as u32).map(|i| i % 2).collect();
+        for (block_bits, biome_bits) in [(4, 1), (5, 2), (6, 3), (7, 3), (8, 3)] {
+            let mut buf = BytesMut::new();
+            4096i16.encode(&mut buf).unwrap();
+            encode_container(&mut buf, block_bits, &palette, &pack(block_bits, &states));
+            encode_container(
+                &mut buf,
+                biome_bits,
+                &[7, PLAINS],
+                &pack(biome_bits, &biomes),
+            );
+
+            let mut rdr = &buf[..];
+            let section = ChunkSection::decode(&mut rdr, &registry()).unwrap();
+            assert!(rdr.is_empty());
+            for (i, index) in states.iter().enumerate() {
+                let (x, y, z) = (i % 16, i / 256, i / 16 % 16);
+                assert_eq!(
+                    section.block_state(x, y, z),
+                    palette[*index as usize],
+                    "{block_bits} bits"
+                );
+            }
+            for (i, index) in biomes.iter().enumerate() {
+                let (x, y, z) = (i % 4 * 4, i / 16 * 4, i / 4 % 4 * 4);
+                assert_eq!(
+                    section.biome(x, y, z),
+                    [7, PLAINS][*index as usize],
+                    "{biome_bits} bits"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn recount_blocks() {
+        let registry = registry();
+        let section = |count: i16, bits: u8, palette: &[i32], longs: &[u64]| {
+            let mut buf = BytesMut::new();
+            count.encode(&mut buf).unwrap();
+            encode_container(&mut buf, bits, palette, longs);
+            encode_container(&mut buf, 0, &[PLAINS], &[]);
+            ChunkSection::decode(&mut &buf[..], &registry).unwrap()
+        };
+
+        // Counts the server got wrong are ignored.
+        let mut stone = section(0, 0, &[STONE], &[]);
+        assert_eq!(stone.block_count, 4096);
+        stone.set_block_state(0, 0, 0, AIR, &registry);
+        assert_eq!(stone.block_count, 4095);
+        assert!(section(4096, 0, &[AIR], &[]).is_empty());
+
+        let states: Vec<u32> = (0..SECTION_VOLUME as u32)
+            .map(|i| (i % 4 == 0) as u32)
+            .collect();
+        let section = section(-1, 4, &[AIR, DIRT], &pack(4, &states));
+        assert_eq!(section.block_count, 1024);
+    }
+
+    #[test]
+    fn small_indirect_bits() {
+        // Block palettes always use at least four bits, whatever the server
+        // says.
+        let states: Vec<u32> = (0..SECTION_VOLUME as u32).map(|i| i % 2).collect();
+
+        let mut buf = BytesMut::new();
+        2048i16.encode(&mut buf).unwrap();
+        encode_container(&mut buf, 1, &[AIR, STONE], &pack(4, &states));
+        encode_container(&mut buf, 0, &[PLAINS], &[]);
+
+        let section = ChunkSection::decode(&mut &buf[..], &registry()).unwrap();
+        assert_eq!(section.block_state(0, 0, 0), AIR);
+        assert_eq!(section.block_state(1, 0, 0), STONE);
+    }
+
+    #[test]
+    fn invalid() {
+        let registry = registry();
+        let decode = |buf: BytesMut| ChunkSection::decode(&mut &buf[..], &registry);
+
+        // Unknown block state.
+        let mut buf = BytesMut::new();
+        4096i16.encode(&mut buf).unwrap();
+        encode_container(&mut buf, 0, &[12345], &[]);
+        encode_container(&mut buf, 0, &[PLAINS], &[]);
+        assert!(decode(buf).is_err());
+
+        // Palette index out of bounds.
+        let mut buf = BytesMut::new();
+        4096i16.encode(&mut buf).unwrap();
+        encode_container(&mut buf, 4, &[STONE], &pack(4, &[1; SECTION_VOLUME]));
+        encode_container(&mut buf, 0, &[PLAINS], &[]);
+        assert!(decode(buf).is_err());
+
+        // Data array of the wrong length.
+        let mut buf = BytesMut::new();
+        4096i16.encode(&mut buf).unwrap();
+        encode_container(&mut buf, 4, &[STONE], &[0; 16]);
+        encode_container(&mut buf, 0, &[PLAINS], &[]);
+        assert!(decode(buf).is_err());
+
+        // Truncated anywhere.
+        let data = include_bytes!("testdata/flat_chunk.bin");
+        for len in [1, 2, 3, 100, 2100] {
+            assert!(Chunk::decode(0, 0, &data[..len], &registry).is_err());
+        }
+    }
+}
@@ -0,0 +1,57 @@
+#import bevy_pbr::mesh_functions::{get_world_from_local, mesh_position_local_to_clip}
+
+@group(2) @binding(0) var atlas_texture: texture_2d<f32>;
+@group(2) @binding(1) var atlas_sampler: sampler;
+
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) position: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) atlas_rect: vec4<f32>,
+    @location(3) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) atlas_rect: vec4<f32>,
+    @location(2) color: vec4<f32>,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = mesh_position_local_to_clip(
+        get_world_from_local(vertex.instance_index),
+        vec4<f32>(vertex.position, 1.0),
+    );
+    out.uv = vertex.uv;
+    out.atlas_rect = vertex.atlas_rect;
+    out.color = vertex.color;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let rect_min = in.atlas_rect.xy;
+    let rect_size = in.atlas_rect.zw;
+
+    // The edges of the textures are extruded in the atlas, so the
+    // neighbouring textures don't bleed in. Take the gradients from the
+    // unwrapped UVs so that the wrap isn't seen as a jump across the whole
+    // texture, which would pick the smallest mip level.
+    let uv = rect_min + fract(in.uv) * rect_size;
+    let color = textureSampleGrad(
+        atlas_texture,
+        atlas_sampler,
+        uv,
+        dpdx(in.uv) * rect_size,
+        dpdy(in.uv) * rect_size,
+    );
+
+    if color.a < 0.5 {
+        discard;
+    }
+    // Smooth lighting and ambient occlusion, baked into the vertex colours.
+    return vec4<f32>(color.rgb * in.color.rgb, color.a);
+}
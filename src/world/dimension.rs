@@ -0,0 +1,231 @@
+use anyhow::Context;
+use bevy::prelude::*;
+use protocol::{
+    nbt::from_nbt,
+    packets::{LoginPlay, RegistryData, Respawn},
+    Typed,
+};
+use serde::Deserialize;
+
+use super::map::{ChunkMap, DEFAULT_MIN_Y};
+
+/// What the client needs of an entry of the `minecraft:dimension_type`
+/// registry.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct DimensionType {
+    /// Lowest block of the dimension.
+    pub min_y: i32,
+}
+
+/// Dimension types of the registry data the server sent, by the IDs the
+/// `LoginPlay` and `Respawn` packets use.
+#[derive(Resource, Debug, Default)]
+pub struct DimensionTypeRegistry {
+    pub names: Vec<String>,
+    pub types: Vec<Option<DimensionType>>,
+}
+
+impl DimensionTypeRegistry {
+    pub fn get(&self, id: i32) -> Option<DimensionType> {
+        let id = usize::try_from(id).ok()?;
+        self.types.get(id).copied().flatten()
+    }
+}
+
+/// Dimension the player is in, named like `minecraft:the_nether`. Empty
+/// until the server sent `LoginPlay`.
+#[derive(Resource, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Dimension {
+    pub name: String,
+}
+
+/// Replaces the `DimensionTypeRegistry` when the server sends the dimension
+/// type registry.
+pub fn handle_registry_data(
+    mut dimension_types: ResMut<DimensionTypeRegistry>,
+    mut pkts: EventReader<Typed<RegistryData>>,
+) {
+    for pkt in pkts.read() {
+        if pkt.registry_id != "minecraft:dimension_type" {
+            continue;
+        }
+
+        let mut names = vec![];
+        let mut types = vec![];
+        for (name, nbt) in pkt.entries.iter() {
+            let dimension_type = nbt
+                .as_ref()
+                .context("no data")
+                .and_then(|nbt| Ok(from_nbt::<DimensionType>(nbt)?));
+            types.push(
+                dimension_type
+                    .inspect_err(|e| warn!("failed to read dimension type {name}: {e:#}"))
+                    .ok(),
+            );
+            names.push(name.clone());
+        }
+
+        *dimension_types = DimensionTypeRegistry { names, types };
+    }
+}
+
+/// Starts over with an empty `ChunkMap` at the height of the new dimension
+/// when the player logs in, or respawns in another dimension.
+pub fn handle_login_and_respawn(
+    dimension_types: Res<DimensionTypeRegistry>,
+    mut dimension: ResMut<Dimension>,
+    mut chunks: ResMut<ChunkMap>,
+    mut logins: EventReader<Typed<LoginPlay>>,
+    mut respawns: EventReader<Typed<Respawn>>,
+) {
+    let logins = logins
+        .read()
+        .map(|pkt| (true, pkt.dimension_type.0, &pkt.dimension_name));
+    let respawns = respawns
+        .read()
+        .map(|pkt| (false, pkt.dimension_type.0, &pkt.dimension_name));
+    for (login, dimension_type, name) in logins.chain(respawns) {
+        if !login && dimension.name == *name {
+            continue;
+        }
+
+        let min_y = match dimension_types.get(dimension_type) {
+            Some(dimension_type) => dimension_type.min_y,
+            None => {
+                warn!("unknown dimension type {dimension_type} of {name}");
+                DEFAULT_MIN_Y
+            }
+        };
+        chunks.reset(min_y);
+        dimension.name.clone_from(name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{ecs::system::RunSystemOnce, utils::Instant};
+    use protocol::{LenPrefixed, VarInt, NBT};
+
+    use super::*;
+    use crate::world::chunk::tests::flat_chunk;
+
+    fn login(dimension_type: i32, name: &str) -> LoginPlay {
+        LoginPlay {
+            id: 1,
+            is_hardcore: false,
+            dimensions: LenPrefixed(vec![]),
+            max_players: VarInt(20),
+            view_distance: VarInt(10),
+            simulation_distance: VarInt(10),
+            reduced_debug_info: false,
+            enable_respawn_screen: true,
+            do_limited_crafting: false,
+            dimension_type: VarInt(dimension_type),
+            dimension_name: name.to_owned(),
+            hashed_seed: 0,
+            gamemode: 0,
+            previous_gamemode: -1,
+            is_debug: false,
+            is_flat: false,
+            death_location: None,
+            portal_cooldown: VarInt(0),
+            enforces_secure_chat: false,
+        }
+    }
+
+    fn respawn(dimension_type: i32, name: &str) -> Respawn {
+        Respawn {
+            dimension_type: VarInt(dimension_type),
+            dimension_name: name.to_owned(),
+            hashed_seed: 0,
+            gamemode: 0,
+            previous_gamemode: -1,
+            is_debug: false,
+            is_flat: false,
+            death_location: None,
+            portal_cooldown: VarInt(0),
+            data_kept: 0,
+        }
+    }
+
+    /// Runs `handle_login_and_respawn` on the packets and returns the lowest
+    /// block of the `ChunkMap` and whether it kept its chunk.
+    fn spawn(world: &mut World, logins: Vec<LoginPlay>, respawns: Vec<Respawn>) -> (i32, bool) {
+        world.insert_resource(Events::<Typed<LoginPlay>>::default());
+        world.insert_resource(Events::<Typed<Respawn>>::default());
+        for pkt in logins {
+            world.send_event(Typed::new(Instant::now(), pkt));
+        }
+        for pkt in respawns {
+            world.send_event(Typed::new(Instant::now(), pkt));
+        }
+        world.resource_mut::<ChunkMap>().insert(flat_chunk(0, 0));
+        world.run_system_once(handle_login_and_respawn);
+
+        let chunks = world.resource::<ChunkMap>();
+        (chunks.min_y(), chunks.get(IVec2::ZERO).is_some())
+    }
+
+    #[test]
+    fn dimension_heights() {
+        let mut world = World::new();
+        world.init_resource::<DimensionTypeRegistry>();
+        world.init_resource::<Dimension>();
+        world.init_resource::<ChunkMap>();
+        world.init_resource::<Events<Typed<RegistryData>>>();
+
+        let dimension_type = |snbt: &str| Some(snbt.parse::<NBT>().unwrap());
+        let entries = [
+            (
+                "minecraft:overworld".to_string(),
+                dimension_type("{min_y: -64, height: 384, has_skylight: 1b}"),
+            ),
+            (
+                "minecraft:the_nether".to_string(),
+                dimension_type("{min_y: 0, height: 256, has_skylight: 0b}"),
+            ),
+            ("minecraft:broken".to_string(), None),
+        ];
+        world.send_event(Typed::new(
+            Instant::now(),
+            RegistryData {
+                registry_id: "minecraft:dimension_type".to_string(),
+                entries: LenPrefixed(entries.to_vec()),
+            },
+        ));
+        world.run_system_once(handle_registry_data);
+        let dimension_types = world.resource::<DimensionTypeRegistry>();
+        assert_eq!(dimension_types.names.len(), 3);
+        assert_eq!(dimension_types.get(1), Some(DimensionType { min_y: 0 }));
+        assert_eq!(dimension_types.get(2), None);
+        assert_eq!(dimension_types.get(-1), None);
+
+        // Logging in always starts over.
+        assert_eq!(
+            spawn(&mut world, vec![login(1, "minecraft:the_nether")], vec![]),
+            (0, false)
+        );
+        assert_eq!(world.resource::<Dimension>().name, "minecraft:the_nether");
+
+        // Respawning in the same dimension keeps the chunks.
+        assert_eq!(
+            spawn(&mut world, vec![], vec![respawn(1, "minecraft:the_nether")]),
+            (0, true)
+        );
+        assert_eq!(
+            spawn(&mut world, vec![], vec![respawn(0, "minecraft:overworld")]),
+            (-64, false)
+        );
+
+        // Custom dimensions can share a type, and unknown types fall back to
+        // the overworld height.
+        assert_eq!(
+            spawn(&mut world, vec![], vec![respawn(1, "custom:nether_copy")]),
+            (0, false)
+        );
+        assert_eq!(
+            spawn(&mut world, vec![], vec![respawn(7, "custom:unknown")]),
+            (DEFAULT_MIN_Y, false)
+        );
+    }
+}
@@ -0,0 +1,486 @@
+use std::collections::VecDeque;
+
+use anyhow::{ensure, Context};
+use bevy::{prelude::*, utils::HashMap};
+use protocol::LenPrefixed;
+
+use crate::{block::BlockStateRegistry, direction::Direction};
+
+use super::{
+    chunk::{SECTION_VOLUME, SECTION_WIDTH},
+    map::ChunkMap,
+};
+
+/// Light of a block from the sky and from light emitting blocks, each from 0
+/// to `LightLevel::MAX`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LightLevel {
+    pub sky: u8,
+    pub block: u8,
+}
+
+impl LightLevel {
+    pub const MAX: u8 = 15;
+
+    /// Open sky.
+    pub const FULL_SKY: Self = Self {
+        sky: Self::MAX,
+        block: 0,
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LightKind {
+    Sky,
+    Block,
+}
+
+impl LightKind {
+    pub const ALL: [LightKind; 2] = [LightKind::Sky, LightKind::Block];
+}
+
+const NIBBLES_LEN: usize = SECTION_VOLUME / 2;
+
+/// Light of one kind in a section, 4 bits per block in the same order as the
+/// block states, low bits first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LightNibbles(Box<[u8]>);
+
+impl LightNibbles {
+    pub fn filled(level: u8) -> Self {
+        Self(vec![level << 4 | level; NIBBLES_LEN].into())
+    }
+
+    pub fn decode(data: &[u8]) -> anyhow::Result<Self> {
+        ensure!(
+            data.len() == NIBBLES_LEN,
+            "light array of {} bytes instead of {NIBBLES_LEN}",
+            data.len()
+        );
+
+        Ok(Self(data.into()))
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> u8 {
+        let index = nibble_index(x, y, z);
+        self.0[index / 2] >> (index % 2 * 4) & 0xf
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, z: usize, level: u8) {
+        let index = nibble_index(x, y, z);
+        let shift = index % 2 * 4;
+        let byte = &mut self.0[index / 2];
+        *byte = *byte & !(0xf << shift) | (level & 0xf) << shift;
+    }
+}
+
+fn nibble_index(x: usize, y: usize, z: usize) -> usize {
+    (y * SECTION_WIDTH + z) * SECTION_WIDTH + x
+}
+
+/// Sky and block light of one section, with the missing data filled in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionLight {
+    pub sky: LightNibbles,
+    pub block: LightNibbles,
+}
+
+impl SectionLight {
+    pub fn get(&self, x: usize, y: usize, z: usize) -> LightLevel {
+        LightLevel {
+            sky: self.sky.get(x, y, z),
+            block: self.block.get(x, y, z),
+        }
+    }
+}
+
+/// Light the server sent for a chunk, in `ChunkDataAndUpdateLight` or
+/// `UpdateLight`. Bit `i` of the masks is for light section `i`, and there is
+/// an array for every bit set in the non-empty masks.
+#[derive(Debug, Clone, Copy)]
+pub struct LightData<'a> {
+    pub sky_mask: &'a [i64],
+    pub block_mask: &'a [i64],
+    pub empty_sky_mask: &'a [i64],
+    pub empty_block_mask: &'a [i64],
+    pub sky: &'a [LenPrefixed<u8>],
+    pub block: &'a [LenPrefixed<u8>],
+}
+
+/// Sky and block light of a chunk. There is a light section for each chunk
+/// section and one more below and above them, from the bottom up. Like in
+/// vanilla, a section without data takes its sky light from the bottom of the
+/// next section above that has some, or from the open sky, and has no block
+/// light.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ChunkLight {
+    sky: Vec<Option<LightNibbles>>,
+    block: Vec<Option<LightNibbles>>,
+}
+
+impl ChunkLight {
+    pub fn new(sections: usize) -> Self {
+        Self {
+            sky: vec![None; sections + 2],
+            block: vec![None; sections + 2],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.sky.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sky.is_empty()
+    }
+
+    /// Replaces the sections in the masks of `data` and returns their indices.
+    /// Sections in neither mask are left as they are. Nothing changes when
+    /// `data` is invalid.
+    pub fn update(&mut self, data: &LightData) -> anyhow::Result<Vec<usize>> {
+        let sky = decode_sections(self.len(), data.sky_mask, data.empty_sky_mask, data.sky)
+            .context("failed to decode sky light")?;
+        let block = decode_sections(
+            self.len(),
+            data.block_mask,
+            data.empty_block_mask,
+            data.block,
+        )
+        .context("failed to decode block light")?;
+
+        let mut changed: Vec<usize> = sky.iter().chain(&block).map(|(i, _)| *i).collect();
+        changed.sort_unstable();
+        changed.dedup();
+
+        for (index, nibbles) in sky {
+            self.sky[index] = Some(nibbles);
+        }
+        for (index, nibbles) in block {
+            self.block[index] = Some(nibbles);
+        }
+
+        Ok(changed)
+    }
+
+    /// Light at coordinates relative to light section `index`. Sections past
+    /// the top are open sky.
+    pub fn get(&self, kind: LightKind, index: usize, x: usize, y: usize, z: usize) -> u8 {
+        match kind {
+            LightKind::Sky => {
+                for (i, section) in self.sky.iter().enumerate().skip(index) {
+                    if let Some(section) = section {
+                        return section.get(x, if i == index { y } else { 0 }, z);
+                    }
+                }
+                LightLevel::MAX
+            }
+            LightKind::Block => self
+                .block
+                .get(index)
+                .and_then(Option::as_ref)
+                .map_or(0, |section| section.get(x, y, z)),
+        }
+    }
+
+    /// Sets the light at coordinates relative to light section `index`,
+    /// filling in the data of the section first if it had none. The sky light
+    /// of the sections without data under it is filled in too, as it comes
+    /// from this section.
+    pub fn set(&mut self, kind: LightKind, index: usize, x: usize, y: usize, z: usize, level: u8) {
+        if index >= self.len() {
+            return;
+        }
+
+        let section = match kind {
+            LightKind::Sky => {
+                let missing: Vec<_> = (0..=index)
+                    .rev()
+                    .take_while(|i| *i == index || self.sky[*i].is_none())
+                    .filter(|i| self.sky[*i].is_none())
+                    .collect();
+                for i in missing {
+                    self.sky[i] = Some(self.inherited_sky(i));
+                }
+                self.sky[index].as_mut().unwrap()
+            }
+            LightKind::Block => self.block[index].get_or_insert_with(|| LightNibbles::filled(0)),
+        };
+
+        section.set(x, y, z, level);
+    }
+
+    /// Copy of light section `index` with the missing data filled in, for
+    /// meshing off the main thread.
+    pub fn section(&self, index: usize) -> Option<SectionLight> {
+        if index >= self.len() {
+            return None;
+        }
+
+        let sky = match &self.sky[index] {
+            Some(sky) => sky.clone(),
+            None => self.inherited_sky(index),
+        };
+        let block = self.block[index]
+            .clone()
+            .unwrap_or_else(|| LightNibbles::filled(0));
+
+        Some(SectionLight { sky, block })
+    }
+
+    /// Sky light of section `index` when it has no data, the light at the
+    /// bottom of the sections above it.
+    fn inherited_sky(&self, index: usize) -> LightNibbles {
+        let mut sky = LightNibbles::filled(0);
+        for z in 0..SECTION_WIDTH {
+            for x in 0..SECTION_WIDTH {
+                let level = self.get(LightKind::Sky, index + 1, x, 0, z);
+                for y in 0..SECTION_WIDTH {
+                    sky.set(x, y, z, level);
+                }
+            }
+        }
+
+        sky
+    }
+}
+
+fn decode_sections(
+    len: usize,
+    mask: &[i64],
+    empty_mask: &[i64],
+    arrays: &[LenPrefixed<u8>],
+) -> anyhow::Result<Vec<(usize, LightNibbles)>> {
+    let bit = |mask: &[i64], i: usize| {
+        mask.get(i / 64)
+            .is_some_and(|bits| bits >> (i % 64) & 1 == 1)
+    };
+    let mut arrays = arrays.iter();
+    let mut sections = vec![];
+
+    for index in 0..len {
+        if bit(mask, index) {
+            let data = arrays
+                .next()
+                .with_context(|| format!("missing light array for section {index}"))?;
+            sections.push((index, LightNibbles::decode(data)?));
+        } else if bit(empty_mask, index) {
+            sections.push((index, LightNibbles::filled(0)));
+        }
+    }
+    ensure!(arrays.next().is_none(), "more light arrays than sections");
+
+    Ok(sections)
+}
+
+/// Recomputes the light around `pos` after its block changed, like the server
+/// does, so that placed torches and broken blocks light up right away. The
+/// next light update from the server replaces it.
+///
+/// Light that came from `pos` is removed first, then the light around the
+/// removed area spreads back in, one level less per block. Sky light at the
+/// full level goes down without getting darker. Blocks that are opaque cubes
+/// stop light.
+pub fn relight(chunks: &mut ChunkMap, pos: IVec3, blocks: &BlockStateRegistry) {
+    let mut light = LightChanges {
+        chunks,
+        old: HashMap::new(),
+    };
+    for kind in LightKind::ALL {
+        relight_kind(&mut light, pos, kind, blocks);
+    }
+
+    // Only the sections where the light ended up different are meshed
+    // again.
+    for ((pos, kind), old) in light.old {
+        if light.chunks.light(pos, kind) != Some(old) {
+            light.chunks.mark_block(pos);
+        }
+    }
+}
+
+/// Light set during `relight`, with the level each block had before.
+struct LightChanges<'a> {
+    chunks: &'a mut ChunkMap,
+    old: HashMap<(IVec3, LightKind), u8>,
+}
+
+impl LightChanges<'_> {
+    fn get(&self, pos: IVec3, kind: LightKind) -> Option<u8> {
+        self.chunks.light(pos, kind)
+    }
+
+    fn set(&mut self, pos: IVec3, kind: LightKind, level: u8) {
+        if let Some(old) = self.get(pos, kind) {
+            self.old.entry((pos, kind)).or_insert(old);
+            self.chunks.set_light(pos, kind, level);
+        }
+    }
+
+    fn blocks_light(&self, pos: IVec3, blocks: &BlockStateRegistry) -> bool {
+        self.chunks
+            .get_block(pos)
+            .is_some_and(|id| blocks.blocks_light(id))
+    }
+}
+
+fn relight_kind(
+    light: &mut LightChanges,
+    pos: IVec3,
+    kind: LightKind,
+    blocks: &BlockStateRegistry,
+) {
+    let Some(old) = light.get(pos, kind) else {
+        return;
+    };
+
+    let mut removal = VecDeque::from([(pos, old)]);
+    let mut increase = VecDeque::new();
+    light.set(pos, kind, 0);
+
+    while let Some((pos, level)) = removal.pop_front() {
+        for direction in Direction::ALL {
+            let next = pos + direction.normal();
+            let Some(next_level) = light.get(next, kind) else {
+                continue;
+            };
+            if next_level == 0 {
+                continue;
+            }
+
+            let from_above =
+                kind == LightKind::Sky && direction == Direction::Down && level == LightLevel::MAX;
+            if next_level < level || from_above {
+                light.set(next, kind, 0);
+                removal.push_back((next, next_level));
+            } else {
+                // Lit from somewhere else, it lights the removed area again.
+                increase.push_back(next);
+            }
+        }
+    }
+
+    if kind == LightKind::Block {
+        let emission = light
+            .chunks
+            .get_block(pos)
+            .map_or(0, |id| blocks.light_emission(id));
+        if emission > 0 {
+            light.set(pos, kind, emission);
+            increase.push_back(pos);
+        }
+    }
+    increase.extend(Direction::ALL.map(|direction| pos + direction.normal()));
+
+    while let Some(pos) = increase.pop_front() {
+        let level = light.get(pos, kind).unwrap_or(0);
+        if level <= 1 {
+            continue;
+        }
+
+        for direction in Direction::ALL {
+            let next = pos + direction.normal();
+            let Some(next_level) = light.get(next, kind) else {
+                continue;
+            };
+            if light.blocks_light(next, blocks) {
+                continue;
+            }
+
+            let level = if kind == LightKind::Sky
+                && direction == Direction::Down
+                && level == LightLevel::MAX
+            {
+                level
+            } else {
+                level - 1
+            };
+            if next_level < level {
+                light.set(next, kind, level);
+                increase.push_back(next);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arrays(levels: &[u8]) -> Vec<LenPrefixed<u8>> {
+        levels
+            .iter()
+            .map(|level| LenPrefixed(LightNibbles::filled(*level).0.into_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn nibbles() {
+        let mut nibbles = LightNibbles::filled(3);
+        nibbles.set(1, 0, 0, 12);
+        nibbles.set(2, 0, 0, 15);
+        assert_eq!(nibbles.0[0], 0xc3);
+        assert_eq!(nibbles.0[1], 0x3f);
+        assert_eq!(nibbles.get(1, 0, 0), 12);
+        assert_eq!(nibbles.get(0, 0, 0), 3);
+
+        nibbles.set(15, 15, 15, 7);
+        assert_eq!(nibbles.0[NIBBLES_LEN - 1], 0x73);
+        assert!(LightNibbles::decode(&[0; 16]).is_err());
+    }
+
+    #[test]
+    fn update() {
+        let mut light = ChunkLight::new(4);
+        // Sky light in sections 1 and 4, section 3 dark, 5 unknown.
+        let sky = arrays(&[6, 15]);
+        let block = arrays(&[9]);
+        let changed = light
+            .update(&LightData {
+                sky_mask: &[0b10010],
+                block_mask: &[0b1],
+                empty_sky_mask: &[0b1000],
+                empty_block_mask: &[],
+                sky: &sky,
+                block: &block,
+            })
+            .unwrap();
+        assert_eq!(changed, [0, 1, 3, 4]);
+
+        assert_eq!(light.get(LightKind::Sky, 5, 0, 0, 0), 15);
+        assert_eq!(light.get(LightKind::Sky, 3, 0, 0, 0), 0);
+        // Section 2 takes the light at the bottom of section 3.
+        assert_eq!(light.get(LightKind::Sky, 2, 4, 8, 4), 0);
+        assert_eq!(light.get(LightKind::Sky, 1, 4, 8, 4), 6);
+        assert_eq!(light.get(LightKind::Block, 0, 0, 0, 0), 9);
+        assert_eq!(light.get(LightKind::Block, 1, 0, 0, 0), 0);
+
+        // Missing arrays leave the light as it was.
+        let err = light.update(&LightData {
+            sky_mask: &[0b11],
+            block_mask: &[],
+            empty_sky_mask: &[],
+            empty_block_mask: &[],
+            sky: &arrays(&[1]),
+            block: &[],
+        });
+        assert!(err.is_err());
+        assert_eq!(light.get(LightKind::Sky, 0, 0, 0, 0), 6);
+    }
+
+    #[test]
+    fn set_missing_section() {
+        let mut light = ChunkLight::new(2);
+        let mut nibbles = LightNibbles::filled(0);
+        nibbles.set(3, 0, 3, 10);
+        light.sky[3] = Some(nibbles);
+
+        // The filled in section keeps the sky light it had, and so do the
+        // ones under it.
+        light.set(LightKind::Sky, 1, 3, 0, 3, 4);
+        assert_eq!(light.get(LightKind::Sky, 1, 3, 0, 3), 4);
+        assert_eq!(light.get(LightKind::Sky, 1, 3, 9, 3), 10);
+        assert_eq!(light.get(LightKind::Sky, 1, 5, 9, 5), 0);
+        assert_eq!(light.get(LightKind::Sky, 0, 3, 9, 3), 10);
+        assert_eq!(light.section(2).unwrap().get(3, 15, 3).sky, 10);
+    }
+}
@@ -0,0 +1,576 @@
+use std::collections::BTreeSet;
+
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::block::BlockStateRegistry;
+
+use super::{
+    chunk::{Chunk, SECTION_WIDTH},
+    light::{self, LightData, LightKind, LightLevel},
+};
+
+pub type BlockStateId = i32;
+
+/// Lowest block of the overworld, used until the server says which dimension
+/// the player is in.
+pub const DEFAULT_MIN_Y: i32 = -64;
+
+/// Position of a loaded chunk, in chunks.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Deref)]
+pub struct ChunkPos(pub IVec2);
+
+/// Indices of the sections of a chunk that changed, all of them when the chunk
+/// was just loaded. It is replaced every time the chunk changes, so systems
+/// that keep something per section should look for `Changed<ChangedSections>`.
+#[derive(Component, Debug, Clone, Default, PartialEq, Eq, Deref)]
+pub struct ChangedSections(pub Vec<usize>);
+
+struct LoadedChunk {
+    chunk: Chunk,
+    /// Spawned by `sync_chunk_entities`.
+    entity: Option<Entity>,
+}
+
+/// Every chunk the server sent, keyed by chunk position. Each chunk gets an
+/// entity with its `ChunkPos` and `ChangedSections` once
+/// `sync_chunk_entities` ran.
+#[derive(Resource)]
+pub struct ChunkMap {
+    min_y: i32,
+    chunks: HashMap<IVec2, LoadedChunk>,
+    changed: HashMap<IVec2, BTreeSet<usize>>,
+    despawned: Vec<Entity>,
+}
+
+impl ChunkMap {
+    pub fn new(min_y: i32) -> Self {
+        Self {
+            min_y,
+            chunks: HashMap::new(),
+            changed: HashMap::new(),
+            despawned: vec![],
+        }
+    }
+
+    pub fn min_y(&self) -> i32 {
+        self.min_y
+    }
+
+    /// Adds a chunk, replacing the one at the same position. A replaced chunk
+    /// keeps its entity. The sections of the chunks next to it change too, as
+    /// the new chunk can hide their faces.
+    pub fn insert(&mut self, chunk: Chunk) {
+        let pos = IVec2::new(chunk.x, chunk.z);
+        self.changed
+            .insert(pos, (0..chunk.sections.len()).collect());
+        self.mark_neighbours(pos);
+
+        let entity = self.chunks.remove(&pos).and_then(|loaded| loaded.entity);
+        self.chunks.insert(pos, LoadedChunk { chunk, entity });
+    }
+
+    pub fn remove(&mut self, pos: IVec2) -> Option<Chunk> {
+        let loaded = self.chunks.remove(&pos)?;
+        self.changed.remove(&pos);
+        self.despawned.extend(loaded.entity);
+        self.mark_neighbours(pos);
+
+        Some(loaded.chunk)
+    }
+
+    /// Removes every chunk, like when switching dimensions.
+    pub fn clear(&mut self) {
+        let positions: Vec<_> = self.chunks.keys().copied().collect();
+        for pos in positions {
+            self.remove(pos);
+        }
+    }
+
+    /// Removes every chunk and moves the bottom of the world to `min_y`, when
+    /// entering a dimension.
+    pub fn reset(&mut self, min_y: i32) {
+        self.clear();
+        self.min_y = min_y;
+    }
+
+    pub fn get(&self, pos: IVec2) -> Option<&Chunk> {
+        self.chunks.get(&pos).map(|loaded| &loaded.chunk)
+    }
+
+    pub fn entity(&self, pos: IVec2) -> Option<Entity> {
+        self.chunks.get(&pos)?.entity
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (IVec2, &Chunk)> {
+        self.chunks
+            .iter()
+            .map(|(pos, loaded)| (*pos, &loaded.chunk))
+    }
+
+    /// Block state at `pos`, `None` if its chunk isn't loaded or it is outside
+    /// the world.
+    pub fn get_block(&self, pos: IVec3) -> Option<BlockStateId> {
+        let (chunk_pos, section, [x, y, z]) = self.locate(pos)?;
+        let section = self.get(chunk_pos)?.sections.get(section)?;
+
+        Some(section.block_state(x, y, z))
+    }
+
+    /// Replaces the block state at `pos` and returns the previous one, `None`
+    /// if its chunk isn't loaded or it is outside the world.
+    pub fn set_block(
+        &mut self,
+        pos: IVec3,
+        id: BlockStateId,
+        blocks: &BlockStateRegistry,
+    ) -> Option<BlockStateId> {
+        let (chunk_pos, index, [x, y, z]) = self.locate(pos)?;
+        let section = self
+            .chunks
+            .get_mut(&chunk_pos)?
+            .chunk
+            .sections
+            .get_mut(index)?;
+
+        let old = section.set_block_state(x, y, z, id, blocks);
+        if old != id {
+            self.mark_block(pos);
+            light::relight(self, pos, blocks);
+        }
+
+        Some(old)
+    }
+
+    /// Light at `pos`, `None` if its chunk isn't loaded. Blocks above the
+    /// light sections of the world are lit by the open sky and blocks below
+    /// them are dark.
+    pub fn get_light(&self, pos: IVec3) -> Option<LightLevel> {
+        let chunk =
+            self.get(IVec2::new(pos.x, pos.z).div_euclid(IVec2::splat(SECTION_WIDTH as i32)))?;
+        let Some((_, index, [x, y, z])) = self.locate_light(pos) else {
+            return Some(LightLevel::default());
+        };
+
+        Some(LightLevel {
+            sky: chunk.light.get(LightKind::Sky, index, x, y, z),
+            block: chunk.light.get(LightKind::Block, index, x, y, z),
+        })
+    }
+
+    /// Replaces the light of a chunk with the light sent by the server.
+    /// Light for chunks that aren't loaded is ignored.
+    pub fn update_light(&mut self, pos: IVec2, data: &LightData) -> anyhow::Result<()> {
+        let Some(loaded) = self.chunks.get_mut(&pos) else {
+            return Ok(());
+        };
+        let changed = loaded.chunk.light.update(data)?;
+
+        // Faces are lit by the light of the blocks around them, which can be
+        // in the sections next to the one whose light changed.
+        for offset in neighbour_offsets().chain([IVec3::ZERO]) {
+            if offset.y != 0 {
+                continue;
+            }
+            let pos = pos + IVec2::new(offset.x, offset.z);
+            let Some(loaded) = self.chunks.get(&pos) else {
+                continue;
+            };
+            let sections = loaded.chunk.sections.len();
+            self.changed.entry(pos).or_default().extend(
+                changed
+                    .iter()
+                    .flat_map(|index| index.saturating_sub(2)..=*index)
+                    .filter(|index| *index < sections),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Light of one kind at `pos`, `None` if its chunk isn't loaded or it is
+    /// below the light sections of the world.
+    pub(super) fn light(&self, pos: IVec3, kind: LightKind) -> Option<u8> {
+        let (chunk_pos, index, [x, y, z]) = self.locate_light(pos)?;
+        Some(self.get(chunk_pos)?.light.get(kind, index, x, y, z))
+    }
+
+    /// Sets the light of one kind at `pos`, if its chunk is loaded. The
+    /// sections aren't marked as changed, light often ends up where it was.
+    pub(super) fn set_light(&mut self, pos: IVec3, kind: LightKind, level: u8) {
+        let Some((chunk_pos, index, [x, y, z])) = self.locate_light(pos) else {
+            return;
+        };
+        if let Some(loaded) = self.chunks.get_mut(&chunk_pos) {
+            loaded.chunk.light.set(kind, index, x, y, z, level);
+        }
+    }
+
+    /// Marks the section of the block at `pos` and the sections around it as
+    /// changed. Blocks on the border of a section can hide faces in the next
+    /// one and change the lighting of the faces in the diagonal ones.
+    pub(super) fn mark_block(&mut self, pos: IVec3) {
+        for offset in neighbour_offsets().chain([IVec3::ZERO]) {
+            let Some((chunk_pos, index, _)) = self.locate(pos + offset) else {
+                continue;
+            };
+            if self
+                .get(chunk_pos)
+                .is_some_and(|chunk| index < chunk.sections.len())
+            {
+                self.changed.entry(chunk_pos).or_default().insert(index);
+            }
+        }
+    }
+
+    /// Marks every section of the loaded chunks around `pos`, diagonals included,
+    /// as changed.
+    fn mark_neighbours(&mut self, pos: IVec2) {
+        for offset in neighbour_offsets().filter(|offset| offset.y == 0) {
+            let neighbour = pos + IVec2::new(offset.x, offset.z);
+            if let Some(loaded) = self.chunks.get(&neighbour) {
+                self.changed
+                    .entry(neighbour)
+                    .or_default()
+                    .extend(0..loaded.chunk.sections.len());
+            }
+        }
+    }
+
+    /// Splits a block position into its chunk position, section index and
+    /// position in that section.
+    fn locate(&self, pos: IVec3) -> Option<(IVec2, usize, [usize; 3])> {
+        let width = SECTION_WIDTH as i32;
+        let y = pos.y - self.min_y;
+        if y < 0 {
+            return None;
+        }
+
+        let chunk_pos = IVec2::new(pos.x.div_euclid(width), pos.z.div_euclid(width));
+        let local = IVec3::new(pos.x, y, pos.z).rem_euclid(IVec3::splat(width));
+
+        Some((
+            chunk_pos,
+            (y / width) as usize,
+            local.to_array().map(|v| v as usize),
+        ))
+    }
+
+    /// Like `locate`, but with the index of the light section, which starts
+    /// one section below the world.
+    fn locate_light(&self, pos: IVec3) -> Option<(IVec2, usize, [usize; 3])> {
+        self.locate(pos + IVec3::new(0, SECTION_WIDTH as i32, 0))
+    }
+}
+
+impl Default for ChunkMap {
+    fn default() -> Self {
+        Self::new(DEFAULT_MIN_Y)
+    }
+}
+
+/// Spawns and despawns chunk entities and replaces the `ChangedSections` of
+/// chunks that changed since the last run.
+pub fn sync_chunk_entities(
+    mut commands: Commands,
+    mut chunks: ResMut<ChunkMap>,
+    mut changed_sections: Query<&mut ChangedSections>,
+) {
+    if chunks.changed.is_empty() && chunks.despawned.is_empty() {
+        return;
+    }
+
+    for entity in std::mem::take(&mut chunks.despawned) {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let min_y = chunks.min_y;
+    for (pos, sections) in std::mem::take(&mut chunks.changed) {
+        let Some(loaded) = chunks.chunks.get_mut(&pos) else {
+            continue;
+        };
+        let sections = ChangedSections(sections.into_iter().collect());
+
+        match loaded.entity {
+            Some(entity) => {
+                if let Ok(mut changed) = changed_sections.get_mut(entity) {
+                    *changed = sections;
+                }
+            }
+            None => {
+                let translation =
+                    IVec3::new(pos.x, 0, pos.y) * SECTION_WIDTH as i32 + IVec3::new(0, min_y, 0);
+                let entity = commands
+                    .spawn((
+                        Name::new(format!("Chunk {}, {}", pos.x, pos.y)),
+                        ChunkPos(pos),
+                        sections,
+                        SpatialBundle::from_transform(Transform::from_translation(
+                            translation.as_vec3(),
+                        )),
+                    ))
+                    .id();
+                loaded.entity = Some(entity);
+            }
+        }
+    }
+}
+
+/// Offsets of the 26 blocks around a block.
+fn neighbour_offsets() -> impl Iterator<Item = IVec3> {
+    (-1..=1)
+        .flat_map(|x| (-1..=1).flat_map(move |y| (-1..=1).map(move |z| IVec3::new(x, y, z))))
+        .filter(|offset| *offset != IVec3::ZERO)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bevy::ecs::system::RunSystemOnce;
+    use protocol::LenPrefixed;
+
+    use super::*;
+    use crate::{
+        block::geometry::tests::cube,
+        world::chunk::tests::{
+            flat_chunk, registry, AIR, BEDROCK, DIRT, GRASS_BLOCK, STONE, TORCH,
+        },
+    };
+
+    #[test]
+    fn get_block() {
+        let mut chunks = ChunkMap::default();
+        chunks.insert(flat_chunk(0, 0));
+        chunks.insert(flat_chunk(-1, 2));
+
+        assert_eq!(chunks.get_block(IVec3::new(0, -64, 0)), Some(BEDROCK));
+        assert_eq!(chunks.get_block(IVec3::new(15, -62, 15)), Some(DIRT));
+        assert_eq!(chunks.get_block(IVec3::new(3, -61, 7)), Some(GRASS_BLOCK));
+        assert_eq!(chunks.get_block(IVec3::new(3, -60, 7)), Some(AIR));
+        assert_eq!(chunks.get_block(IVec3::new(3, 319, 7)), Some(AIR));
+        assert_eq!(chunks.get_block(IVec3::new(-1, -64, 32)), Some(BEDROCK));
+        assert_eq!(
+            chunks.get_block(IVec3::new(-16, -61, 47)),
+            Some(GRASS_BLOCK)
+        );
+
+        assert_eq!(chunks.get_block(IVec3::new(3, -65, 7)), None);
+        assert_eq!(chunks.get_block(IVec3::new(3, 320, 7)), None);
+        assert_eq!(chunks.get_block(IVec3::new(-1, 0, 0)), None);
+        assert_eq!(chunks.get_block(IVec3::new(16, 0, 0)), None);
+
+        let mut positions: Vec<_> = chunks.iter().map(|(pos, _)| pos).collect();
+        positions.sort_by_key(|pos| (pos.x, pos.y));
+        assert_eq!(positions, [IVec2::new(-1, 2), IVec2::new(0, 0)]);
+    }
+
+    #[test]
+    fn set_block() {
+        let blocks = registry();
+        let mut chunks = ChunkMap::default();
+        chunks.insert(flat_chunk(0, 0));
+
+        let pos = IVec3::new(5, 100, 9);
+        assert_eq!(chunks.set_block(pos, STONE, &blocks), Some(AIR));
+        assert_eq!(chunks.get_block(pos), Some(STONE));
+        assert_eq!(chunks.get(IVec2::ZERO).unwrap().sections[10].block_count, 1);
+
+        assert_eq!(chunks.set_block(pos, AIR, &blocks), Some(STONE));
+        assert!(chunks.get(IVec2::ZERO).unwrap().sections[10].is_empty());
+
+        assert_eq!(chunks.set_block(IVec3::new(0, 0, 16), STONE, &blocks), None);
+    }
+
+    #[test]
+    fn neighbour_changes() {
+        let blocks = registry();
+        let mut chunks = ChunkMap::default();
+        chunks.insert(flat_chunk(0, 0));
+        chunks.changed.clear();
+
+        chunks.insert(flat_chunk(1, 0));
+        assert_eq!(chunks.changed[&IVec2::ZERO].len(), 24);
+        chunks.changed.clear();
+
+        // Top corner of the first section, next to the chunk at 1, 0.
+        chunks.set_block(IVec3::new(15, -49, 3), STONE, &blocks);
+        assert_eq!(chunks.changed[&IVec2::ZERO], BTreeSet::from([0, 1]));
+        assert_eq!(chunks.changed[&IVec2::new(1, 0)], BTreeSet::from([0, 1]));
+        chunks.changed.clear();
+
+        chunks.remove(IVec2::new(1, 0));
+        assert_eq!(chunks.changed.keys().collect::<Vec<_>>(), [&IVec2::ZERO]);
+    }
+
+    /// Sky light of the first section of a flat chunk in the open, dark in
+    /// the ground and full above the grass.
+    fn flat_sky() -> Vec<LenPrefixed<u8>> {
+        let mut data = vec![0xff; 2048];
+        data[..4 * 16 * 16 / 2].fill(0);
+        vec![LenPrefixed(data)]
+    }
+
+    /// Light of a flat chunk, with the section below the world dark.
+    fn flat_light(sky: &[LenPrefixed<u8>]) -> LightData<'_> {
+        LightData {
+            sky_mask: &[0b10],
+            block_mask: &[],
+            empty_sky_mask: &[0b1],
+            empty_block_mask: &[],
+            sky,
+            block: &[],
+        }
+    }
+
+    #[test]
+    fn update_light() {
+        let sky_light = flat_sky();
+        let mut chunks = ChunkMap::default();
+        chunks.insert(flat_chunk(0, 0));
+        chunks.insert(flat_chunk(1, 0));
+        chunks.changed.clear();
+
+        chunks
+            .update_light(IVec2::ZERO, &flat_light(&sky_light))
+            .unwrap();
+        assert_eq!(chunks.changed[&IVec2::ZERO], BTreeSet::from([0, 1]));
+        assert_eq!(chunks.changed[&IVec2::new(1, 0)], BTreeSet::from([0, 1]));
+
+        let light = |pos| chunks.get_light(pos).unwrap();
+        assert_eq!(light(IVec3::new(3, -61, 7)), LightLevel::default());
+        assert_eq!(light(IVec3::new(3, -60, 7)), LightLevel::FULL_SKY);
+        assert_eq!(light(IVec3::new(3, -70, 7)), LightLevel::default());
+        assert_eq!(light(IVec3::new(3, -100, 7)), LightLevel::default());
+        // Without data, sections above take the sky light from the top.
+        assert_eq!(light(IVec3::new(3, 100, 7)), LightLevel::FULL_SKY);
+        assert_eq!(light(IVec3::new(3, 400, 7)), LightLevel::FULL_SKY);
+        assert_eq!(chunks.get_light(IVec3::new(-1, 0, 0)), None);
+
+        // Light of chunks that aren't loaded is dropped.
+        chunks
+            .update_light(IVec2::ONE, &flat_light(&sky_light))
+            .unwrap();
+        assert!(chunks.get(IVec2::ONE).is_none());
+    }
+
+    #[test]
+    fn relight() {
+        let mut blocks = registry();
+        let geometry = Arc::make_mut(&mut blocks.blockstates_geometry);
+        for id in [STONE, DIRT, GRASS_BLOCK, BEDROCK] {
+            geometry.insert(id, cube(Vec4::ONE).into());
+        }
+        assert_eq!(blocks.light_emission(TORCH), 14);
+
+        let sky_light = flat_sky();
+        let mut chunks = ChunkMap::default();
+        for x in -1..=1 {
+            for z in -1..=1 {
+                chunks.insert(flat_chunk(x, z));
+                chunks
+                    .update_light(IVec2::new(x, z), &flat_light(&sky_light))
+                    .unwrap();
+            }
+        }
+        let light = |chunks: &ChunkMap, x, y, z| chunks.get_light(IVec3::new(x, y, z)).unwrap();
+        let block = |chunks: &ChunkMap, x, y, z| light(chunks, x, y, z).block;
+        let sky = |chunks: &ChunkMap, x, y, z| light(chunks, x, y, z).sky;
+
+        chunks.set_block(IVec3::new(8, -60, 8), TORCH, &blocks);
+        assert_eq!(block(&chunks, 8, -60, 8), 14);
+        assert_eq!(block(&chunks, 8, -60, 12), 10);
+        assert_eq!(block(&chunks, 8, -55, 8), 9);
+        assert_eq!(block(&chunks, -2, -60, 8), 4);
+        // Into the chunk next to it, and not into the ground.
+        assert_eq!(block(&chunks, 8, -60, 20), 2);
+        assert_eq!(block(&chunks, 8, -61, 8), 0);
+        assert_eq!(sky(&chunks, 8, -60, 8), 15);
+
+        chunks.set_block(IVec3::new(8, -60, 8), AIR, &blocks);
+        assert_eq!(block(&chunks, 8, -60, 8), 0);
+        assert_eq!(block(&chunks, 8, -60, 12), 0);
+        assert_eq!(block(&chunks, -2, -60, 8), 0);
+
+        // A block in the sky shades the column under it, which is lit from
+        // the sides.
+        chunks.set_block(IVec3::new(8, 100, 8), STONE, &blocks);
+        assert_eq!(sky(&chunks, 8, 100, 8), 0);
+        assert_eq!(sky(&chunks, 8, 101, 8), 15);
+        assert_eq!(sky(&chunks, 8, 99, 8), 14);
+        assert_eq!(sky(&chunks, 8, -60, 8), 14);
+        assert_eq!(sky(&chunks, 9, -60, 8), 15);
+
+        chunks.set_block(IVec3::new(8, 100, 8), AIR, &blocks);
+        assert_eq!(sky(&chunks, 8, 100, 8), 15);
+        assert_eq!(sky(&chunks, 8, -60, 8), 15);
+
+        // Breaking the grass lets the sky into the hole.
+        chunks.set_block(IVec3::new(8, -61, 8), AIR, &blocks);
+        assert_eq!(sky(&chunks, 8, -61, 8), 15);
+        assert_eq!(sky(&chunks, 8, -62, 8), 0);
+    }
+
+    #[test]
+    fn palette_growth() {
+        let blocks = registry();
+        let mut section = flat_chunk(0, 0).sections.remove(0);
+
+        // More distinct IDs than an indirect palette can hold.
+        let ids: Vec<i32> = (0..300).map(|i| 1000 + i).collect();
+        for (i, id) in ids.iter().enumerate() {
+            section.set_block_state(i % 16, 4 + i / 256, i / 16 % 16, *id, &blocks);
+        }
+
+        for (i, id) in ids.iter().enumerate() {
+            assert_eq!(section.block_state(i % 16, 4 + i / 256, i / 16 % 16), *id);
+        }
+        assert_eq!(section.block_state(0, 0, 0), BEDROCK);
+        assert_eq!(section.block_state(0, 15, 0), AIR);
+    }
+
+    #[test]
+    fn entities() {
+        let blocks = registry();
+        let mut world = World::new();
+        world.insert_resource(ChunkMap::default());
+
+        world.resource_mut::<ChunkMap>().insert(flat_chunk(1, 2));
+        world.run_system_once(sync_chunk_entities);
+
+        let entity = world
+            .resource::<ChunkMap>()
+            .entity(IVec2::new(1, 2))
+            .unwrap();
+        assert_eq!(
+            world.get::<ChunkPos>(entity),
+            Some(&ChunkPos(IVec2::new(1, 2)))
+        );
+        assert_eq!(world.get::<ChangedSections>(entity).unwrap().len(), 24);
+        assert_eq!(
+            world.get::<Transform>(entity).unwrap().translation,
+            Vec3::new(16.0, -64.0, 32.0)
+        );
+
+        world
+            .resource_mut::<ChunkMap>()
+            .set_block(IVec3::new(17, 0, 33), STONE, &blocks);
+        world.run_system_once(sync_chunk_entities);
+        assert_eq!(
+            *world.get::<ChangedSections>(entity).unwrap(),
+            ChangedSections(vec![3, 4])
+        );
+
+        world.resource_mut::<ChunkMap>().remove(IVec2::new(1, 2));
+        world.run_system_once(sync_chunk_entities);
+        assert!(world.get_entity(entity).is_none());
+        assert!(world.resource::<ChunkMap>().is_empty());
+    }
+}
@@ -0,0 +1,63 @@
+use bevy::{
+    pbr::{MaterialPipeline, MaterialPipelineKey},
+    prelude::*,
+    render::{
+        mesh::{MeshVertexAttribute, MeshVertexBufferLayoutRef},
+        render_resource::{
+            AsBindGroup, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError,
+            VertexFormat,
+        },
+    },
+};
+
+pub const CHUNK_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(0x4b1d_9c3e_2f70_4a8e_b61d_03c5_7e2a_91f4);
+
+/// Rectangle of the atlas the UVs of a vertex wrap around, as min and size in
+/// atlas UVs. Greedy quads span several blocks and have UVs past 1, which the
+/// shader brings back into the texture of the block.
+pub const ATTRIBUTE_ATLAS_RECT: MeshVertexAttribute =
+    MeshVertexAttribute::new("AtlasRect", 0x7263_6674, VertexFormat::Float32x4);
+
+/// Atlas rectangle of faces whose UVs already point into the atlas.
+pub const WHOLE_ATLAS: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+/// Unlit material of chunk sections, sampling the block atlas with the UVs
+/// wrapped in `ATTRIBUTE_ATLAS_RECT` and darkened by the vertex colours.
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct ChunkAtlasMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    pub atlas: Handle<Image>,
+}
+
+impl Material for ChunkAtlasMaterial {
+    fn vertex_shader() -> ShaderRef {
+        CHUNK_SHADER_HANDLE.into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        CHUNK_SHADER_HANDLE.into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Mask(0.5)
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayoutRef,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let vertex_layout = layout.0.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_UV_0.at_shader_location(1),
+            ATTRIBUTE_ATLAS_RECT.at_shader_location(2),
+            Mesh::ATTRIBUTE_COLOR.at_shader_location(3),
+        ])?;
+        descriptor.vertex.buffers = vec![vertex_layout];
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1362 @@
+use bevy::{
+    prelude::*,
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    block::{
+        geometry::{face_corners, BlockGeometry, MeshData},
+        tint::Tint,
+        variant::BlockVariants,
+        BlockStateRegistry,
+    },
+    core::LocalPlayer,
+    direction::Direction,
+    fly_camera::FlyCamera,
+    texture::TextureRegistry,
+};
+
+use super::{
+    biome::{BiomeColors, BiomeRegistry},
+    chunk::{ChunkSection, SECTION_VOLUME, SECTION_WIDTH},
+    light::{LightLevel, SectionLight},
+    map::{BlockStateId, ChangedSections, ChunkMap, ChunkPos},
+    material::{ChunkAtlasMaterial, ATTRIBUTE_ATLAS_RECT, WHOLE_ATLAS},
+};
+
+/// Material every chunk section is rendered with, the block atlas.
+#[derive(Resource, Debug, Clone, Deref)]
+pub struct ChunkMaterial(pub Handle<ChunkAtlasMaterial>);
+
+/// How sections are meshed. Flipping `greedy` remeshes every loaded section,
+/// so that `ChunkMeshStats` can be compared between the two.
+#[derive(Resource, Reflect, Debug, Clone, Default)]
+#[reflect(Resource)]
+pub struct MeshingSettings {
+    /// Merge the faces of plain cubes with the same texture into larger
+    /// quads, instead of meshing each block state on its own.
+    pub greedy: bool,
+}
+
+/// Triangles in the meshes of the loaded sections.
+#[derive(Resource, Reflect, Debug, Default)]
+#[reflect(Resource)]
+pub struct ChunkMeshStats {
+    pub triangles: usize,
+    #[reflect(ignore)]
+    sections: HashMap<SectionKey, usize>,
+}
+
+impl ChunkMeshStats {
+    fn set(&mut self, key: SectionKey, triangles: usize) {
+        let old = self.sections.insert(key, triangles).unwrap_or(0);
+        self.triangles = self.triangles + triangles - old;
+    }
+
+    fn retain_loaded(&mut self, chunks: &ChunkMap) {
+        self.sections
+            .retain(|(pos, _), _| chunks.get(*pos).is_some());
+        self.triangles = self.sections.values().sum();
+    }
+}
+
+/// Child of a chunk entity holding the mesh of one of its sections.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SectionMesh {
+    pub section: usize,
+}
+
+pub fn init_chunk_material(
+    mut commands: Commands,
+    texture_registry: Res<TextureRegistry>,
+    mut materials: ResMut<Assets<ChunkAtlasMaterial>>,
+) {
+    commands.insert_resource(ChunkMaterial(materials.add(ChunkAtlasMaterial {
+        atlas: texture_registry.block.clone(),
+    })));
+}
+
+/// Points the chunk material at the new atlas after the textures were
+/// reloaded.
+pub fn update_chunk_material(
+    texture_registry: Res<TextureRegistry>,
+    material: Res<ChunkMaterial>,
+    mut materials: ResMut<Assets<ChunkAtlasMaterial>>,
+) {
+    if let Some(material) = materials.get_mut(&material.0) {
+        material.atlas = texture_registry.block.clone();
+    }
+}
+
+/// Sections waiting to be meshed and the meshing tasks in flight, keyed by
+/// chunk position and section index.
+#[derive(Resource, Default)]
+pub struct SectionMeshTasks {
+    pending: HashSet<SectionKey>,
+    running: HashMap<SectionKey, Task<Option<Mesh>>>,
+}
+
+type SectionKey = (IVec2, usize);
+
+/// Meshing tasks running at the same time. Keeping it low leaves the pending
+/// sections to be picked by distance once a task finishes.
+const MAX_MESH_TASKS: usize = 32;
+/// Finished meshes added to the world per frame.
+const MAX_APPLIED_MESHES: usize = 16;
+
+/// Copy of a section and the sections around it, so that it can be meshed
+/// off the main thread. The diagonal neighbours are needed for the ambient
+/// occlusion of the faces on the edges of the section.
+pub struct SectionSnapshot {
+    /// The 3×3×3 sections centred on the meshed one, indexed by
+    /// `neighbour_index`, `None` when not loaded.
+    sections: [Option<ChunkSection>; 27],
+    /// Light of the same sections, which exists above and below the world too.
+    light: [Option<SectionLight>; 27],
+    /// World position of the first block of the meshed section.
+    origin: IVec3,
+}
+
+fn neighbour_index(offset: IVec3) -> usize {
+    ((offset.x + 1) + (offset.y + 1) * 3 + (offset.z + 1) * 9) as usize
+}
+
+impl SectionSnapshot {
+    pub fn new(chunks: &ChunkMap, (pos, index): SectionKey) -> Option<Self> {
+        let mut sections = [(); 27].map(|_| None);
+        let mut light = [(); 27].map(|_| None);
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    let offset = IVec3::new(x, y, z);
+                    let Some(chunk) = chunks.get(pos + IVec2::new(x, z)) else {
+                        continue;
+                    };
+                    // Light sections start one section below the world.
+                    let light_index = (index + 1).checked_add_signed(y as isize);
+
+                    sections[neighbour_index(offset)] = index
+                        .checked_add_signed(y as isize)
+                        .and_then(|index| chunk.sections.get(index).cloned());
+                    light[neighbour_index(offset)] =
+                        light_index.and_then(|index| chunk.light.section(index));
+                }
+            }
+        }
+        sections[neighbour_index(IVec3::ZERO)].as_ref()?;
+        let width = SECTION_WIDTH as i32;
+        let origin = IVec3::new(pos.x, 0, pos.y) * width
+            + IVec3::new(0, chunks.min_y() + index as i32 * width, 0);
+
+        Some(Self {
+            sections,
+            light,
+            origin,
+        })
+    }
+
+    fn section(&self) -> &ChunkSection {
+        self.sections[neighbour_index(IVec3::ZERO)]
+            .as_ref()
+            .expect("snapshot without its section")
+    }
+
+    /// Block state at `pos` relative to the section, for positions in the
+    /// section or in one of the sections around it.
+    fn neighbour(&self, pos: IVec3) -> Option<BlockStateId> {
+        let width = SECTION_WIDTH as i32;
+        let offset = pos.div_euclid(IVec3::splat(width));
+        if offset.abs().max_element() > 1 {
+            return None;
+        }
+        let [x, y, z] = pos
+            .rem_euclid(IVec3::splat(width))
+            .to_array()
+            .map(|v| v as usize);
+
+        Some(
+            self.sections[neighbour_index(offset)]
+                .as_ref()?
+                .block_state(x, y, z),
+        )
+    }
+
+    /// Light at `pos` relative to the section, for positions in the section
+    /// or in one of the sections around it. Unloaded chunks are lit by the
+    /// open sky.
+    fn light(&self, pos: IVec3) -> LightLevel {
+        let width = SECTION_WIDTH as i32;
+        let offset = pos.div_euclid(IVec3::splat(width));
+        if offset.abs().max_element() > 1 {
+            return LightLevel::FULL_SKY;
+        }
+        let [x, y, z] = pos
+            .rem_euclid(IVec3::splat(width))
+            .to_array()
+            .map(|v| v as usize);
+
+        self.light[neighbour_index(offset)]
+            .as_ref()
+            .map_or(LightLevel::FULL_SKY, |light| light.get(x, y, z))
+    }
+
+    /// Colour the tinted faces of the block at `pos` in the section are
+    /// multiplied by, from the biome it is in unless the tint is fixed.
+    fn tint(
+        &self,
+        pos: IVec3,
+        tints: &HashMap<BlockStateId, Tint>,
+        biomes: &[BiomeColors],
+    ) -> Vec3 {
+        let Some(&tint) = self.neighbour(pos).and_then(|id| tints.get(&id)) else {
+            return Vec3::ONE;
+        };
+        let [x, y, z] = pos.to_array().map(|v| v as usize);
+        let biome = self.section().biome(x, y, z);
+
+        usize::try_from(biome)
+            .ok()
+            .and_then(|biome| biomes.get(biome))
+            .copied()
+            .unwrap_or_default()
+            .tint(tint)
+    }
+
+    pub fn build_mesh(
+        &self,
+        geometry: &HashMap<BlockStateId, BlockVariants>,
+        tints: &HashMap<BlockStateId, Tint>,
+        biomes: &[BiomeColors],
+        greedy: bool,
+    ) -> Option<Mesh> {
+        build_section_mesh(
+            self.section(),
+            self.origin,
+            |pos| self.neighbour(pos),
+            |pos| self.light(pos),
+            |pos| self.tint(pos, tints, biomes),
+            geometry,
+            greedy,
+        )
+    }
+}
+
+/// Queues the sections in `ChangedSections`, cancelling the task of a section
+/// that was already being meshed.
+pub fn queue_changed_sections(
+    mut tasks: ResMut<SectionMeshTasks>,
+    changed_chunks: Query<(&ChunkPos, &ChangedSections), Changed<ChangedSections>>,
+) {
+    for (pos, changed) in &changed_chunks {
+        for &index in changed.iter() {
+            // Dropping a task cancels it.
+            tasks.running.remove(&(**pos, index));
+            tasks.pending.insert((**pos, index));
+        }
+    }
+}
+
+/// Queues every loaded section again when the meshing settings change.
+pub fn remesh_on_settings_change(
+    settings: Res<MeshingSettings>,
+    chunks: Res<ChunkMap>,
+    stats: Res<ChunkMeshStats>,
+    mut tasks: ResMut<SectionMeshTasks>,
+) {
+    if !settings.is_changed() || settings.is_added() {
+        return;
+    }
+
+    info!(
+        "remeshing with greedy meshing {}, {} triangles before",
+        if settings.greedy { "on" } else { "off" },
+        stats.triangles
+    );
+    remesh_all(&chunks, &mut tasks);
+}
+
+/// Remeshes everything after the block models were reloaded, their faces
+/// and atlas UVs may have changed, or the biome colours were.
+pub fn remesh_on_blocks_change(
+    blocks: Res<BlockStateRegistry>,
+    biomes: Res<BiomeRegistry>,
+    chunks: Res<ChunkMap>,
+    mut tasks: ResMut<SectionMeshTasks>,
+) {
+    if blocks.is_changed() || biomes.is_changed() {
+        remesh_all(&chunks, &mut tasks);
+    }
+}
+
+fn remesh_all(chunks: &ChunkMap, tasks: &mut SectionMeshTasks) {
+    for (pos, chunk) in chunks.iter() {
+        for index in 0..chunk.sections.len() {
+            tasks.running.remove(&(pos, index));
+            tasks.pending.insert((pos, index));
+        }
+    }
+}
+
+/// Starts meshing the pending sections closest to the player, or to the fly
+/// camera when there is no player.
+pub fn spawn_mesh_tasks(
+    chunks: Res<ChunkMap>,
+    blocks: Res<BlockStateRegistry>,
+    biomes: Res<BiomeRegistry>,
+    settings: Res<MeshingSettings>,
+    mut tasks: ResMut<SectionMeshTasks>,
+    players: Query<&Transform, With<LocalPlayer>>,
+    cameras: Query<&Transform, With<FlyCamera>>,
+) {
+    let count = MAX_MESH_TASKS.saturating_sub(tasks.running.len());
+    if count == 0 || tasks.pending.is_empty() {
+        return;
+    }
+
+    let viewer = players
+        .iter()
+        .chain(&cameras)
+        .next()
+        .map_or(Vec3::ZERO, |transform| transform.translation);
+
+    let pool = AsyncComputeTaskPool::get();
+    for key in nearest_sections(&tasks.pending, viewer, chunks.min_y(), count) {
+        tasks.pending.remove(&key);
+        let Some(snapshot) = SectionSnapshot::new(&chunks, key) else {
+            continue;
+        };
+
+        let geometry = blocks.blockstates_geometry.clone();
+        let tints = blocks.tints.clone();
+        let colors = biomes.colors.clone();
+        let greedy = settings.greedy;
+        let task =
+            pool.spawn(async move { snapshot.build_mesh(&geometry, &tints, &colors, greedy) });
+        tasks.running.insert(key, task);
+    }
+}
+
+/// Up to `count` sections of `pending`, closest to `viewer` first.
+fn nearest_sections(
+    pending: &HashSet<SectionKey>,
+    viewer: Vec3,
+    min_y: i32,
+    count: usize,
+) -> Vec<SectionKey> {
+    let distance = |(pos, index): &SectionKey| {
+        let width = SECTION_WIDTH as i32;
+        let corner = IVec3::new(pos.x * width, min_y + *index as i32 * width, pos.y * width);
+        (corner.as_vec3() + Vec3::splat(width as f32 / 2.0)).distance_squared(viewer)
+    };
+    let compare = |a: &SectionKey, b: &SectionKey| distance(a).total_cmp(&distance(b));
+
+    let mut keys: Vec<_> = pending.iter().copied().collect();
+    if keys.len() > count {
+        keys.select_nth_unstable_by(count, compare);
+        keys.truncate(count);
+    }
+    keys.sort_by(compare);
+    keys
+}
+
+/// Adds the meshes of finished tasks to their chunk entity, a few per frame.
+/// Sections that end up without geometry lose their mesh entity.
+#[allow(clippy::too_many_arguments)]
+pub fn apply_section_meshes(
+    mut commands: Commands,
+    chunks: Res<ChunkMap>,
+    material: Res<ChunkMaterial>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut tasks: ResMut<SectionMeshTasks>,
+    mut stats: ResMut<ChunkMeshStats>,
+    children: Query<&Children>,
+    section_meshes: Query<(&SectionMesh, &Handle<Mesh>)>,
+) {
+    let finished: Vec<_> = tasks
+        .running
+        .iter_mut()
+        .filter_map(|(key, task)| Some((*key, block_on(future::poll_once(task))?)))
+        .take(MAX_APPLIED_MESHES)
+        .collect();
+
+    if chunks.is_changed() {
+        stats.retain_loaded(&chunks);
+    }
+
+    for ((pos, index), mesh) in finished {
+        tasks.running.remove(&(pos, index));
+        let Some(entity) = chunks.entity(pos) else {
+            continue;
+        };
+
+        let triangles = mesh
+            .as_ref()
+            .and_then(Mesh::indices)
+            .map_or(0, |indices| indices.len() / 3);
+        stats.set((pos, index), triangles);
+
+        let current = children
+            .get(entity)
+            .into_iter()
+            .flat_map(|children| children.iter())
+            .find_map(|child| {
+                let (section, mesh) = section_meshes.get(*child).ok()?;
+                (section.section == index).then_some((*child, mesh))
+            });
+
+        match (mesh, current) {
+            (Some(mesh), Some((_, handle))) => {
+                meshes.insert(handle, mesh);
+            }
+            (Some(mesh), None) => {
+                let child = commands
+                    .spawn((
+                        Name::new(format!("Section {index}")),
+                        SectionMesh { section: index },
+                        MaterialMeshBundle {
+                            mesh: meshes.add(mesh),
+                            material: material.0.clone(),
+                            transform: Transform::from_xyz(
+                                0.0,
+                                (index * SECTION_WIDTH) as f32,
+                                0.0,
+                            ),
+                            ..default()
+                        },
+                    ))
+                    .id();
+                commands.entity(entity).add_child(child);
+            }
+            (None, Some((child, _))) => {
+                commands.entity(child).despawn_recursive();
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// Brightness of a face per number of blocks around its corner that occlude
+/// it, like vanilla's smooth lighting where each of the four blocks around a
+/// corner darkens it by a fifth.
+const AMBIENT_OCCLUSION: [f32; 4] = [1.0, 0.8, 0.6, 0.4];
+
+/// Blocks, light and tints around the section being meshed, by position
+/// relative to it.
+struct SectionView<'a, N, L, T> {
+    section: &'a ChunkSection,
+    neighbour: N,
+    light: L,
+    tint: T,
+    /// World position of the section, which picks the variants of blocks.
+    origin: IVec3,
+    geometry: &'a HashMap<BlockStateId, BlockVariants>,
+}
+
+impl<'a, N, L, T> SectionView<'a, N, L, T>
+where
+    N: Fn(IVec3) -> Option<BlockStateId>,
+    L: Fn(IVec3) -> LightLevel,
+    T: Fn(IVec3) -> Vec3,
+{
+    fn block(&self, pos: IVec3) -> Option<&'a BlockGeometry> {
+        let width = SECTION_WIDTH as i32;
+        let id = if pos.cmpge(IVec3::ZERO).all() && pos.cmplt(IVec3::splat(width)).all() {
+            let [x, y, z] = pos.to_array().map(|v| v as usize);
+            self.section.block_state(x, y, z)
+        } else {
+            (self.neighbour)(pos)?
+        };
+
+        let variants = self.geometry.get(&id)?;
+        Some(variants.get(self.origin + pos))
+    }
+
+    fn occludes(&self, pos: IVec3) -> bool {
+        self.block(pos).is_some_and(BlockGeometry::is_opaque_cube)
+    }
+
+    /// Brightness of the vertex at `local` in the block at `pos`, on a face
+    /// towards `direction`. The light is the average of the blocks in front
+    /// of the face around the vertex, skipping the ones that occlude it.
+    fn vertex_brightness(
+        &self,
+        pos: IVec3,
+        local: Vec3,
+        direction: Direction,
+        ambient_occlusion: bool,
+        shade: bool,
+    ) -> f32 {
+        let normal = direction.normal();
+        // Faces inside the block, like the top of a slab, take the light of
+        // the block itself.
+        let on_side = (local - Vec3::splat(0.5)).dot(normal.as_vec3()) > 0.5 - 1e-4;
+        let front = if on_side { pos + normal } else { pos };
+
+        let [side1, side2] = tangents(direction).map(|axis| {
+            let towards = if local.dot(axis.as_vec3()) >= 0.5 {
+                1
+            } else {
+                -1
+            };
+            front + axis * towards
+        });
+        let corner = side1 + side2 - front;
+
+        let (occluded1, occluded2) = (self.occludes(side1), self.occludes(side2));
+        // Light can't get around both sides to the corner.
+        let occluded_corner = (occluded1 && occluded2) || self.occludes(corner);
+
+        let (mut sky, mut block, mut samples) = (0.0, 0.0, 0);
+        for (pos, occluded) in [
+            (front, false),
+            (side1, occluded1),
+            (side2, occluded2),
+            (corner, occluded_corner),
+        ] {
+            if !occluded {
+                let light = (self.light)(pos);
+                sky += light.sky as f32;
+                block += light.block as f32;
+                samples += 1;
+            }
+        }
+        let level = f32::max(sky, block) / samples as f32;
+
+        let occluders = 4 - samples;
+        let ambient_occlusion = if ambient_occlusion {
+            AMBIENT_OCCLUSION[occluders]
+        } else {
+            1.0
+        };
+        let shade = if shade {
+            directional_shade(direction)
+        } else {
+            1.0
+        };
+
+        light_brightness(level) * ambient_occlusion * shade
+    }
+
+    /// Colour of each vertex of `faces`, added to the block at `pos`: its
+    /// brightness, times the tint of the block on tinted faces.
+    fn face_colors(&self, faces: &MeshData, pos: IVec3, ambient_occlusion: bool) -> Vec<[f32; 4]> {
+        let tint = faces.tinted.contains(&true).then(|| (self.tint)(pos));
+
+        faces
+            .positions
+            .iter()
+            .zip(&faces.normals)
+            .zip(&faces.shade)
+            .zip(&faces.tinted)
+            .map(|(((local, normal), shade), tinted)| {
+                let direction = Direction::from_normal(Vec3::from(*normal));
+                let brightness = self.vertex_brightness(
+                    pos,
+                    Vec3::from(*local),
+                    direction,
+                    ambient_occlusion,
+                    *shade,
+                );
+                let color = match tint {
+                    Some(tint) if *tinted => tint * brightness,
+                    _ => Vec3::splat(brightness),
+                };
+                color.extend(1.0).to_array()
+            })
+            .collect()
+    }
+}
+
+/// The two axes a face towards `direction` lies along.
+fn tangents(direction: Direction) -> [IVec3; 2] {
+    match direction {
+        Direction::Down | Direction::Up => [IVec3::X, IVec3::Z],
+        Direction::North | Direction::South => [IVec3::X, IVec3::Y],
+        Direction::West | Direction::East => [IVec3::Y, IVec3::Z],
+    }
+}
+
+/// Vanilla's fixed shading of faces, brightest on top.
+fn directional_shade(direction: Direction) -> f32 {
+    match direction {
+        Direction::Up => 1.0,
+        Direction::Down => 0.5,
+        Direction::North | Direction::South => 0.8,
+        Direction::West | Direction::East => 0.6,
+    }
+}
+
+/// Vanilla's curve from a light level to a brightness, without the ambient
+/// light of the dimension.
+fn light_brightness(level: f32) -> f32 {
+    let level = level / LightLevel::MAX as f32;
+    level / (4.0 - 3.0 * level)
+}
+
+/// Combines the geometry of every block state in the section, each moved to
+/// its position in the section. Cullable faces are skipped when the neighbour
+/// on that side covers them. `neighbour` looks up blocks outside the section
+/// and `light` the light of any block, by position relative to the section;
+/// unloaded neighbours hide nothing. Vertices are coloured by smooth lighting,
+/// and the tinted faces of the block at a position also by `tint`.
+/// With `greedy`, the visible faces of plain cubes are merged into larger
+/// quads instead. Returns `None` when there is nothing to draw.
+pub fn build_section_mesh(
+    section: &ChunkSection,
+    origin: IVec3,
+    neighbour: impl Fn(IVec3) -> Option<BlockStateId>,
+    light: impl Fn(IVec3) -> LightLevel,
+    tint: impl Fn(IVec3) -> Vec3,
+    geometry: &HashMap<BlockStateId, BlockVariants>,
+    greedy: bool,
+) -> Option<Mesh> {
+    if section.is_empty() {
+        return None;
+    }
+
+    let view = SectionView {
+        section,
+        neighbour,
+        light,
+        tint,
+        origin,
+        geometry,
+    };
+    let width = SECTION_WIDTH as i32;
+
+    let mut data = MeshData::default();
+    let mut atlas_rects = vec![];
+    let mut colors = vec![];
+    // Visible cube faces left for greedy meshing, per direction and block,
+    // with the brightness of their corners.
+    let mut cube_faces = [(); 6].map(|_| vec![None; SECTION_VOLUME]);
+
+    for y in 0..width {
+        for z in 0..width {
+            for x in 0..width {
+                let pos = IVec3::new(x, y, z);
+                let Some(block) = view.block(pos) else {
+                    continue;
+                };
+                let cube = block.cube_faces.filter(|_| greedy);
+                let ambient_occlusion = block.ambient_occlusion;
+
+                let translation = pos.as_vec3();
+                data.append(&block.always, translation);
+                colors.extend(view.face_colors(&block.always, pos, ambient_occlusion));
+
+                for direction in Direction::ALL {
+                    let faces = block.cullable(direction);
+                    if faces.is_empty() {
+                        continue;
+                    }
+
+                    let hidden = view
+                        .block(pos + direction.normal())
+                        .is_some_and(|other| other.is_opaque(direction.opposite()));
+                    if hidden {
+                        continue;
+                    }
+
+                    let face_colors = view.face_colors(faces, pos, ambient_occlusion);
+                    match cube {
+                        Some(rects) => {
+                            let corners: [[f32; 4]; 4] = face_colors.try_into().unwrap();
+                            cube_faces[direction.index()][block_index(pos)] =
+                                Some((rects[direction.index()], corners));
+                        }
+                        None => {
+                            data.append(faces, translation);
+                            colors.extend(face_colors);
+                        }
+                    }
+                }
+                atlas_rects.resize(data.positions.len(), WHOLE_ATLAS);
+            }
+        }
+    }
+
+    for direction in Direction::ALL {
+        merge_faces(
+            direction,
+            &mut cube_faces[direction.index()],
+            &mut data,
+            &mut atlas_rects,
+            &mut colors,
+        );
+    }
+
+    if data.is_empty() {
+        return None;
+    }
+
+    Some(
+        data.to_mesh()
+            .with_inserted_attribute(ATTRIBUTE_ATLAS_RECT, atlas_rects)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors),
+    )
+}
+
+fn block_index(pos: IVec3) -> usize {
+    let width = SECTION_WIDTH as i32;
+    ((pos.y * width + pos.z) * width + pos.x) as usize
+}
+
+/// Atlas rectangle of a cube face and the brightness of its corners.
+type CubeFace = (Vec4, [[f32; 4]; 4]);
+
+/// Greedy meshing of the `direction` faces of a section: each face grows
+/// along the texture's U axis while the next faces have the same texture and
+/// lighting, then along its V axis while whole rows do. The UVs of a merged
+/// quad go up to its size in blocks, for the shader to repeat the texture.
+fn merge_faces(
+    direction: Direction,
+    faces: &mut [Option<CubeFace>],
+    data: &mut MeshData,
+    atlas_rects: &mut Vec<[f32; 4]>,
+    colors: &mut Vec<[f32; 4]>,
+) {
+    let width = SECTION_WIDTH as i32;
+    let corners = face_corners(direction);
+    let u = (corners[3] - corners[0]).abs();
+    let v = (corners[1] - corners[0]).abs();
+    let n = direction.normal().abs().as_vec3();
+    let (u_step, v_step, n_step) = (u.as_ivec3(), v.as_ivec3(), n.as_ivec3());
+    let at = |layer: i32, i: i32, j: i32| block_index(n_step * layer + u_step * i + v_step * j);
+
+    for layer in 0..width {
+        for j in 0..width {
+            for i in 0..width {
+                let Some(face) = faces[at(layer, i, j)] else {
+                    continue;
+                };
+                let (rect, corner_colors) = face;
+
+                let w = (i..width)
+                    .take_while(|&i| faces[at(layer, i, j)] == Some(face))
+                    .count() as i32;
+                let h = (j..width)
+                    .take_while(|&j| (i..i + w).all(|i| faces[at(layer, i, j)] == Some(face)))
+                    .count() as i32;
+
+                for j in j..j + h {
+                    for i in i..i + w {
+                        faces[at(layer, i, j)] = None;
+                    }
+                }
+
+                let size = Vec2::new(w as f32, h as f32);
+                let origin = n * layer as f32 + u * i as f32 + v * j as f32;
+                let quad = MeshData {
+                    positions: corners
+                        .map(|corner| {
+                            let corner = n * corner.dot(n)
+                                + u * corner.dot(u) * size.x
+                                + v * corner.dot(v) * size.y;
+                            (origin + corner).to_array()
+                        })
+                        .to_vec(),
+                    normals: vec![direction.normal().as_vec3().to_array(); 4],
+                    uvs: [Vec2::ZERO, Vec2::Y, Vec2::ONE, Vec2::X]
+                        .map(|uv| (uv * size).to_array())
+                        .to_vec(),
+                    shade: vec![true; 4],
+                    tinted: vec![false; 4],
+                    indices: vec![0, 1, 2, 0, 2, 3],
+                };
+
+                data.append(&quad, Vec3::ZERO);
+                atlas_rects.resize(data.positions.len(), rect.to_array());
+                colors.extend(corner_colors);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bevy::{ecs::system::RunSystemOnce, render::mesh::VertexAttributeValues, tasks::TaskPool};
+
+    use super::*;
+    use crate::{
+        block::geometry::tests::cube,
+        world::{
+            chunk::tests::{flat_chunk, registry, AIR, BEDROCK, DIRT, GRASS_BLOCK, PLAINS, STONE},
+            map::sync_chunk_entities,
+        },
+    };
+
+    /// One quad on top of the block.
+    fn quad() -> Mesh {
+        MeshData {
+            positions: vec![
+                [0.0, 1.0, 0.0],
+                [0.0, 1.0, 1.0],
+                [1.0, 1.0, 1.0],
+                [1.0, 1.0, 0.0],
+            ],
+            normals: vec![[0.0, 1.0, 0.0]; 4],
+            uvs: vec![[0.0, 0.0]; 4],
+            shade: vec![true; 4],
+            tinted: vec![false; 4],
+            indices: vec![0, 1, 2, 0, 2, 3],
+        }
+        .to_mesh()
+    }
+
+    fn no_tint(_: IVec3) -> Vec3 {
+        Vec3::ONE
+    }
+
+    fn sky(_: IVec3) -> LightLevel {
+        LightLevel::FULL_SKY
+    }
+
+    fn world() -> World {
+        AsyncComputeTaskPool::get_or_init(TaskPool::default);
+
+        let mut blocks = registry();
+        let geometry = Arc::make_mut(&mut blocks.blockstates_geometry);
+        for id in [STONE, DIRT, GRASS_BLOCK, BEDROCK] {
+            geometry.insert(id, cube(texture(id)).into());
+        }
+
+        let mut chunks = ChunkMap::default();
+        chunks.insert(flat_chunk(0, 0));
+
+        let mut world = World::new();
+        world.insert_resource(Assets::<Mesh>::default());
+        world.insert_resource(blocks);
+        world.insert_resource(chunks);
+        world.insert_resource(ChunkMaterial(Handle::default()));
+        world.init_resource::<SectionMeshTasks>();
+        world.init_resource::<MeshingSettings>();
+        world.init_resource::<ChunkMeshStats>();
+        world.init_resource::<BiomeRegistry>();
+        world
+    }
+
+    /// A different atlas rectangle for each block.
+    fn texture(id: i32) -> Vec4 {
+        Vec4::new(id as f32 / 128.0, 0.0, 1.0 / 64.0, 1.0 / 64.0)
+    }
+
+    fn schedule() -> Schedule {
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                sync_chunk_entities,
+                queue_changed_sections,
+                remesh_on_settings_change,
+                spawn_mesh_tasks,
+                apply_section_meshes,
+            )
+                .chain(),
+        );
+        schedule
+    }
+
+    /// Runs the schedule until every queued section is meshed.
+    fn run(world: &mut World, schedule: &mut Schedule) {
+        for _ in 0..1000 {
+            schedule.run(world);
+            let tasks = world.resource::<SectionMeshTasks>();
+            if tasks.pending.is_empty() && tasks.running.is_empty() {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        panic!("sections are still being meshed");
+    }
+
+    fn section_meshes(world: &mut World) -> Vec<(usize, Handle<Mesh>)> {
+        let mut sections: Vec<_> = world
+            .query::<(&SectionMesh, &Handle<Mesh>)>()
+            .iter(world)
+            .map(|(section, mesh)| (section.section, mesh.clone()))
+            .collect();
+        sections.sort_by_key(|(section, _)| *section);
+        sections
+    }
+
+    fn vertex_count(world: &World, handle: &Handle<Mesh>) -> usize {
+        world
+            .resource::<Assets<Mesh>>()
+            .get(handle)
+            .unwrap()
+            .count_vertices()
+    }
+
+    #[test]
+    fn section_mesh() {
+        let world = world();
+        let chunks = world.resource::<ChunkMap>();
+        let chunk = chunks.get(IVec2::ZERO).unwrap();
+        let blocks = &world.resource::<BlockStateRegistry>().blockstates_geometry;
+
+        // Four full layers without neighbours: the top, the bottom and the
+        // outer sides are visible.
+        let mesh = build_section_mesh(
+            &chunk.sections[0],
+            IVec3::ZERO,
+            |_| None,
+            sky,
+            no_tint,
+            blocks,
+            false,
+        )
+        .unwrap();
+        assert_eq!(mesh.count_vertices(), (256 + 256 + 4 * 16 * 4) * 4);
+        assert_eq!(mesh.indices().unwrap().len(), (256 + 256 + 4 * 16 * 4) * 6);
+
+        // Surrounded by stone, only the top of the grass is left.
+        let mesh = build_section_mesh(
+            &chunk.sections[0],
+            IVec3::ZERO,
+            |_| Some(STONE),
+            sky,
+            no_tint,
+            blocks,
+            false,
+        )
+        .unwrap();
+        assert_eq!(mesh.count_vertices(), 256 * 4);
+
+        assert!(build_section_mesh(
+            &chunk.sections[1],
+            IVec3::ZERO,
+            |_| None,
+            sky,
+            no_tint,
+            blocks,
+            false
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn greedy_mesh() {
+        let world = world();
+        let chunks = world.resource::<ChunkMap>();
+        let chunk = chunks.get(IVec2::ZERO).unwrap();
+        let blocks = &world.resource::<BlockStateRegistry>().blockstates_geometry;
+
+        // One quad for the top and the bottom, and on each side one for the
+        // bedrock, the two layers of dirt and the grass.
+        let mesh = build_section_mesh(
+            &chunk.sections[0],
+            IVec3::ZERO,
+            |_| None,
+            sky,
+            no_tint,
+            blocks,
+            true,
+        )
+        .unwrap();
+        assert_eq!(mesh.count_vertices(), (2 + 4 * 3) * 4);
+
+        // Stone around the layers, but not above them where it would darken
+        // the edges of the top.
+        let stone = |pos: IVec3| (pos.y < 4).then_some(STONE);
+        let mesh = build_section_mesh(
+            &chunk.sections[0],
+            IVec3::ZERO,
+            stone,
+            sky,
+            no_tint,
+            blocks,
+            true,
+        )
+        .unwrap();
+        let (
+            Some(VertexAttributeValues::Float32x3(positions)),
+            Some(VertexAttributeValues::Float32x2(uvs)),
+            Some(VertexAttributeValues::Float32x4(rects)),
+        ) = (
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+            mesh.attribute(Mesh::ATTRIBUTE_UV_0),
+            mesh.attribute(ATTRIBUTE_ATLAS_RECT),
+        )
+        else {
+            panic!("mesh is missing attributes");
+        };
+        assert_eq!(
+            positions,
+            &[
+                [0.0, 4.0, 0.0],
+                [0.0, 4.0, 16.0],
+                [16.0, 4.0, 16.0],
+                [16.0, 4.0, 0.0]
+            ]
+        );
+        assert_eq!(uvs, &[[0.0, 0.0], [0.0, 16.0], [16.0, 16.0], [16.0, 0.0]]);
+        assert_eq!(rects, &[texture(GRASS_BLOCK).to_array(); 4]);
+
+        // A different block in the middle of the top splits it.
+        let mut section = chunk.sections[0].clone();
+        let registry = world.resource::<BlockStateRegistry>();
+        section.set_block_state(5, 3, 5, STONE, registry);
+        let mesh =
+            build_section_mesh(&section, IVec3::ZERO, stone, sky, no_tint, blocks, true).unwrap();
+        assert_eq!(mesh.count_vertices(), 5 * 4);
+    }
+
+    /// Brightness of the vertices of `mesh` at `pos` facing `normal`.
+    fn brightness_at(mesh: &Mesh, pos: Vec3, normal: Vec3) -> Vec<f32> {
+        let (
+            Some(VertexAttributeValues::Float32x3(positions)),
+            Some(VertexAttributeValues::Float32x3(normals)),
+            Some(VertexAttributeValues::Float32x4(colors)),
+        ) = (
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL),
+            mesh.attribute(Mesh::ATTRIBUTE_COLOR),
+        )
+        else {
+            panic!("mesh is missing attributes");
+        };
+
+        positions
+            .iter()
+            .zip(normals)
+            .zip(colors)
+            .filter(|((p, n), _)| Vec3::from(**p) == pos && Vec3::from(**n) == normal)
+            .map(|(_, color)| color[0])
+            .collect()
+    }
+
+    #[test]
+    fn ambient_occlusion() {
+        let mut world = world();
+        let chunks = world.resource::<ChunkMap>();
+        let mut section = chunks.get(IVec2::ZERO).unwrap().sections[0].clone();
+        let registry = world.resource::<BlockStateRegistry>();
+        section.set_block_state(5, 4, 5, STONE, registry);
+        let blocks = &registry.blockstates_geometry;
+
+        // The stone darkens the corner it shares with the three grass tops
+        // that are still visible.
+        let mesh = build_section_mesh(&section, IVec3::ZERO, |_| None, sky, no_tint, blocks, false)
+            .unwrap();
+        assert_eq!(
+            brightness_at(&mesh, Vec3::new(5.0, 4.0, 5.0), Vec3::Y),
+            [0.8; 3]
+        );
+        assert_eq!(
+            brightness_at(&mesh, Vec3::new(3.0, 4.0, 3.0), Vec3::Y),
+            [1.0; 4]
+        );
+
+        // Faces are shaded by the direction they face.
+        let corner = Vec3::ZERO;
+        assert_eq!(brightness_at(&mesh, corner, Vec3::NEG_Y), [0.5]);
+        assert_eq!(brightness_at(&mesh, corner, Vec3::NEG_Z), [0.8]);
+        assert_eq!(brightness_at(&mesh, corner, Vec3::NEG_X), [0.6]);
+
+        // Greedy meshing keeps the darker corners apart.
+        let greedy =
+            build_section_mesh(&section, IVec3::ZERO, |_| None, sky, no_tint, blocks, true)
+                .unwrap();
+        assert_eq!(
+            brightness_at(&greedy, Vec3::new(5.0, 4.0, 5.0), Vec3::Y),
+            [0.8; 3]
+        );
+
+        let mut blocks = world.resource_mut::<BlockStateRegistry>();
+        let geometry = Arc::make_mut(&mut blocks.blockstates_geometry);
+        geometry.get_mut(&GRASS_BLOCK).unwrap().geometry[0].ambient_occlusion = false;
+        let blocks = &world.resource::<BlockStateRegistry>().blockstates_geometry;
+        let mesh = build_section_mesh(&section, IVec3::ZERO, |_| None, sky, no_tint, blocks, false)
+            .unwrap();
+        assert_eq!(
+            brightness_at(&mesh, Vec3::new(5.0, 4.0, 5.0), Vec3::Y),
+            [1.0; 3]
+        );
+    }
+
+    #[test]
+    fn smooth_lighting() {
+        let world = world();
+        let chunks = world.resource::<ChunkMap>();
+        let chunk = chunks.get(IVec2::ZERO).unwrap();
+        let blocks = &world.resource::<BlockStateRegistry>().blockstates_geometry;
+
+        // Sky light on the west half, darkness on the east one.
+        let light = |pos: IVec3| LightLevel {
+            sky: if pos.x < 8 { LightLevel::MAX } else { 0 },
+            block: 0,
+        };
+        let mesh = build_section_mesh(
+            &chunk.sections[0],
+            IVec3::ZERO,
+            |_| None,
+            light,
+            no_tint,
+            blocks,
+            false,
+        )
+        .unwrap();
+
+        let top = |x: f32| brightness_at(&mesh, Vec3::new(x, 4.0, 8.0), Vec3::Y);
+        assert_eq!(top(4.0), [1.0; 4]);
+        // Half of the light around the vertices on the border, level 7.5.
+        assert_eq!(top(8.0), [0.2; 4]);
+        assert_eq!(top(12.0), [0.0; 4]);
+    }
+
+    #[test]
+    fn biome_tint() {
+        let mut world = world();
+        let mut blocks = world.resource_mut::<BlockStateRegistry>();
+        let geometry = Arc::make_mut(&mut blocks.blockstates_geometry);
+        geometry.get_mut(&GRASS_BLOCK).unwrap().geometry[0].cullable[Direction::Up.index()]
+            .tinted = vec![true; 4];
+
+        let chunks = world.resource::<ChunkMap>();
+        let blocks = world.resource::<BlockStateRegistry>();
+        let snapshot = SectionSnapshot::new(chunks, (IVec2::ZERO, 0)).unwrap();
+        let mut biomes = vec![BiomeColors::default(); PLAINS as usize + 1];
+        biomes[PLAINS as usize].grass = Vec3::new(0.5, 1.0, 0.25);
+        let mesh = snapshot
+            .build_mesh(&blocks.blockstates_geometry, &blocks.tints, &biomes, false)
+            .unwrap();
+
+        let color_at = |pos: Vec3, normal: Vec3| {
+            let (
+                Some(VertexAttributeValues::Float32x3(positions)),
+                Some(VertexAttributeValues::Float32x3(normals)),
+                Some(VertexAttributeValues::Float32x4(colors)),
+            ) = (
+                mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+                mesh.attribute(Mesh::ATTRIBUTE_NORMAL),
+                mesh.attribute(Mesh::ATTRIBUTE_COLOR),
+            )
+            else {
+                panic!("mesh is missing attributes");
+            };
+            let i = (0..positions.len())
+                .find(|&i| Vec3::from(positions[i]) == pos && Vec3::from(normals[i]) == normal)
+                .unwrap();
+            colors[i]
+        };
+        // Only the tinted top of the grass gets the colour of the plains.
+        assert_eq!(
+            color_at(Vec3::new(8.0, 4.0, 8.0), Vec3::Y),
+            [0.5, 1.0, 0.25, 1.0]
+        );
+        assert_eq!(
+            color_at(Vec3::new(0.0, 4.0, 8.0), Vec3::NEG_X)[..3],
+            [directional_shade(Direction::West); 3]
+        );
+
+        // Biomes the server didn't send look like the plains.
+        assert_eq!(
+            snapshot.tint(IVec3::new(8, 3, 8), &blocks.tints, &[]),
+            BiomeColors::default().grass
+        );
+        assert_eq!(
+            snapshot.tint(IVec3::new(8, 0, 8), &blocks.tints, &[]),
+            Vec3::ONE
+        );
+    }
+
+    #[test]
+    fn switch_greedy() {
+        let mut world = world();
+        let mut schedule = schedule();
+
+        run(&mut world, &mut schedule);
+        assert_eq!(
+            world.resource::<ChunkMeshStats>().triangles,
+            (256 + 256 + 4 * 16 * 4) * 2
+        );
+
+        world.resource_mut::<MeshingSettings>().greedy = true;
+        run(&mut world, &mut schedule);
+        assert_eq!(
+            world.resource::<ChunkMeshStats>().triangles,
+            (2 + 4 * 3) * 2
+        );
+
+        world.resource_mut::<ChunkMap>().remove(IVec2::ZERO);
+        run(&mut world, &mut schedule);
+        assert_eq!(world.resource::<ChunkMeshStats>().triangles, 0);
+    }
+
+    #[test]
+    fn random_variants() {
+        let mut world = world();
+        let mut blocks = world.resource_mut::<BlockStateRegistry>();
+        let variants = BlockVariants::new(
+            false,
+            vec![vec![(1, cube(texture(STONE))), (1, cube(texture(DIRT)))]],
+        );
+        Arc::make_mut(&mut blocks.blockstates_geometry).insert(STONE, variants.clone());
+
+        let chunks = world.resource::<ChunkMap>();
+        let mut section = chunks.get(IVec2::ZERO).unwrap().sections[0].clone();
+        let registry = world.resource::<BlockStateRegistry>();
+        section.set_block_state(5, 4, 5, STONE, registry);
+        let blocks = &registry.blockstates_geometry;
+
+        // The same section picks its variants by where it is in the world.
+        // Greedy meshing puts the texture of cube faces in their atlas rect.
+        let mut picked = HashSet::new();
+        for x in 0..16 {
+            let origin = IVec3::new(x * 16, -64, 0);
+            let mesh =
+                build_section_mesh(&section, origin, |_| None, sky, no_tint, blocks, true).unwrap();
+            let (
+                Some(VertexAttributeValues::Float32x3(positions)),
+                Some(VertexAttributeValues::Float32x3(normals)),
+                Some(VertexAttributeValues::Float32x4(rects)),
+            ) = (
+                mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+                mesh.attribute(Mesh::ATTRIBUTE_NORMAL),
+                mesh.attribute(ATTRIBUTE_ATLAS_RECT),
+            )
+            else {
+                panic!("mesh is missing attributes");
+            };
+            let rect = positions
+                .iter()
+                .zip(normals)
+                .zip(rects)
+                .find(|((p, n), _)| **p == [5.0, 5.0, 5.0] && Vec3::from(**n) == Vec3::Y)
+                .map(|(_, rect)| Vec4::from(*rect))
+                .unwrap();
+
+            let expected = variants
+                .get(origin + IVec3::new(5, 4, 5))
+                .cube_faces
+                .unwrap()[0];
+            assert_eq!(rect, expected);
+            picked.insert(rect.to_array().map(f32::to_bits));
+        }
+        assert_eq!(picked.len(), 2);
+    }
+
+    #[test]
+    fn snapshot() {
+        let mut world = world();
+        let mut chunks = world.resource_mut::<ChunkMap>();
+        chunks.insert(flat_chunk(1, 0));
+        chunks.insert(flat_chunk(0, -1));
+
+        let chunks = world.resource::<ChunkMap>();
+        let blocks = &world.resource::<BlockStateRegistry>().blockstates_geometry;
+        let snapshot = SectionSnapshot::new(chunks, (IVec2::ZERO, 0)).unwrap();
+        assert_eq!(snapshot.neighbour(IVec3::new(16, 3, 0)), Some(GRASS_BLOCK));
+        assert_eq!(snapshot.neighbour(IVec3::new(0, 16, 0)), Some(AIR));
+        assert_eq!(snapshot.neighbour(IVec3::new(0, 0, -1)), Some(BEDROCK));
+        assert_eq!(snapshot.neighbour(IVec3::new(0, -1, 0)), None);
+        assert_eq!(snapshot.neighbour(IVec3::new(-1, 0, 0)), None);
+
+        // The east and north sides are covered by the loaded chunks.
+        let mesh = snapshot
+            .build_mesh(blocks, &HashMap::new(), &[], false)
+            .unwrap();
+        assert_eq!(mesh.count_vertices(), (256 + 256 + 2 * 16 * 4) * 4);
+
+        assert!(SectionSnapshot::new(chunks, (IVec2::ONE, 0)).is_none());
+        assert!(SectionSnapshot::new(chunks, (IVec2::ZERO, 24)).is_none());
+    }
+
+    #[test]
+    fn nearest_first() {
+        let pending = HashSet::from_iter([
+            (IVec2::new(0, 0), 0),
+            (IVec2::new(0, 0), 4),
+            (IVec2::new(3, 0), 4),
+            (IVec2::new(-1, 0), 4),
+            (IVec2::new(0, 0), 10),
+        ]);
+
+        let viewer = Vec3::new(8.0, 0.0, 8.0);
+        assert_eq!(
+            nearest_sections(&pending, viewer, -64, 3),
+            [
+                (IVec2::new(0, 0), 4),
+                (IVec2::new(-1, 0), 4),
+                (IVec2::new(3, 0), 4)
+            ]
+        );
+        assert_eq!(nearest_sections(&pending, viewer, -64, 10).len(), 5);
+    }
+
+    #[test]
+    fn cancel_stale_tasks() {
+        let mut world = world();
+        let mut queue = Schedule::default();
+        queue.add_systems((sync_chunk_entities, queue_changed_sections).chain());
+
+        queue.run(&mut world);
+        world.run_system_once(spawn_mesh_tasks);
+        let tasks = world.resource::<SectionMeshTasks>();
+        assert!(tasks.pending.is_empty());
+        assert_eq!(tasks.running.len(), 24);
+
+        set_block(&mut world, IVec3::new(0, -60, 0), STONE);
+        queue.run(&mut world);
+        let tasks = world.resource::<SectionMeshTasks>();
+        assert_eq!(tasks.pending, HashSet::from_iter([(IVec2::ZERO, 0)]));
+        assert_eq!(tasks.running.len(), 23);
+        assert!(!tasks.running.contains_key(&(IVec2::ZERO, 0)));
+    }
+
+    fn set_block(world: &mut World, pos: IVec3, id: i32) {
+        world.resource_scope(|world, mut chunks: Mut<ChunkMap>| {
+            chunks.set_block(pos, id, world.resource::<BlockStateRegistry>());
+        });
+    }
+
+    #[test]
+    fn rebuild_changed_sections() {
+        let full = (256 + 256 + 4 * 16 * 4) * 4;
+        let mut world = world();
+        let mut schedule = schedule();
+
+        run(&mut world, &mut schedule);
+        let sections = section_meshes(&mut world);
+        assert_eq!(sections.len(), 1);
+        let (index, bottom) = sections[0].clone();
+        assert_eq!(index, 0);
+        assert_eq!(vertex_count(&world, &bottom), full);
+
+        // Swap the bottom mesh for a marker, which stays as long as the
+        // section isn't rebuilt.
+        world.resource_mut::<Assets<Mesh>>().insert(&bottom, quad());
+
+        run(&mut world, &mut schedule);
+        assert_eq!(vertex_count(&world, &bottom), 4);
+
+        // The stone shades the column under it down to the grass, so the
+        // bottom section is rebuilt too.
+        set_block(&mut world, IVec3::new(0, 0, 0), STONE);
+        run(&mut world, &mut schedule);
+
+        let sections = section_meshes(&mut world);
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0], (0, bottom.clone()));
+        assert_eq!(vertex_count(&world, &bottom), full);
+        assert_eq!(sections[1].0, 4);
+        assert_eq!(vertex_count(&world, &sections[1].1), 6 * 4);
+
+        // Removing a corner hides three faces and uncovers three others.
+        set_block(&mut world, IVec3::new(0, -64, 0), AIR);
+        run(&mut world, &mut schedule);
+        assert_eq!(vertex_count(&world, &bottom), full);
+
+        // A block on top of the grass hides the face below it.
+        set_block(&mut world, IVec3::new(5, -60, 5), STONE);
+        run(&mut world, &mut schedule);
+        assert_eq!(vertex_count(&world, &bottom), full + 5 * 4 - 4);
+
+        set_block(&mut world, IVec3::new(0, 0, 0), AIR);
+        run(&mut world, &mut schedule);
+        assert_eq!(section_meshes(&mut world), [(0, bottom)]);
+    }
+}
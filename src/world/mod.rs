@@ -1,19 +1,177 @@
-use bevy::prelude::*;
-use protocol::{packets::ChunkDataAndUpdateLight, PacketEvent};
+#![allow(dead_code)]
+use bevy::{asset::load_internal_asset, prelude::*};
+use protocol::{
+    packets::{BlockUpdate, ChunkDataAndUpdateLight, UnloadChunk, UpdateLight},
+    Typed,
+};
 
+use crate::{block::BlockStateRegistry, state::AppState, texture::TextureRegistry};
+
+use self::{
+    biome::{handle_registry_data, load_colormaps, recolor_biomes, BiomeRegistry, Colormaps},
+    chunk::Chunk,
+    dimension::{handle_login_and_respawn, Dimension, DimensionTypeRegistry},
+    light::LightData,
+    map::ChunkMap,
+    material::{ChunkAtlasMaterial, CHUNK_SHADER_HANDLE},
+    mesher::{
+        apply_section_meshes, init_chunk_material, queue_changed_sections, remesh_on_blocks_change,
+        remesh_on_settings_change, spawn_mesh_tasks, update_chunk_material, ChunkMaterial,
+        ChunkMeshStats, MeshingSettings, SectionMeshTasks,
+    },
+};
+
+mod biome;
 mod chunk;
+mod dimension;
+mod light;
+mod map;
+mod material;
+mod mesher;
 
 pub struct WorldPlugin;
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, handle_chunk_data_and_update_light);
+        load_internal_asset!(app, CHUNK_SHADER_HANDLE, "chunk.wgsl", Shader::from_wgsl);
+        app.add_plugins(MaterialPlugin::<ChunkAtlasMaterial> {
+            prepass_enabled: false,
+            shadows_enabled: false,
+            ..default()
+        });
+
+        app.register_type::<MeshingSettings>();
+        app.register_type::<ChunkMeshStats>();
+        app.init_resource::<ChunkMap>();
+        app.init_resource::<SectionMeshTasks>();
+        app.init_resource::<MeshingSettings>();
+        app.init_resource::<ChunkMeshStats>();
+        app.init_resource::<BiomeRegistry>();
+        app.init_resource::<DimensionTypeRegistry>();
+        app.init_resource::<Dimension>();
+        // Server resource packs can replace the colormaps.
+        app.add_systems(OnEnter(AppState::LoadingTextures), load_colormaps);
+        app.add_systems(
+            Update,
+            (
+                recolor_biomes.run_if(resource_changed::<Colormaps>),
+                handle_registry_data,
+            )
+                .chain()
+                .run_if(resource_exists::<Colormaps>),
+        );
+        app.add_systems(
+            Update,
+            (
+                dimension::handle_registry_data,
+                handle_login_and_respawn,
+                (handle_chunk_data_and_update_light, handle_block_update)
+                    .run_if(resource_exists::<BlockStateRegistry>),
+                handle_update_light,
+                handle_unload_chunk,
+                map::sync_chunk_entities,
+                queue_changed_sections,
+                remesh_on_settings_change,
+                (
+                    remesh_on_blocks_change,
+                    spawn_mesh_tasks,
+                    apply_section_meshes,
+                )
+                    .run_if(
+                        resource_exists::<BlockStateRegistry>
+                            .and_then(resource_exists::<ChunkMaterial>),
+                    ),
+            )
+                .chain(),
+        );
+        app.add_systems(
+            Update,
+            init_chunk_material.run_if(
+                resource_exists::<TextureRegistry>.and_then(not(resource_exists::<ChunkMaterial>)),
+            ),
+        );
+        app.add_systems(
+            Update,
+            update_chunk_material.run_if(
+                resource_exists::<ChunkMaterial>
+                    .and_then(resource_exists_and_changed::<TextureRegistry>),
+            ),
+        );
+    }
+}
+
+fn handle_chunk_data_and_update_light(
+    mut chunks: ResMut<ChunkMap>,
+    blocks: Res<BlockStateRegistry>,
+    mut pkts: EventReader<Typed<ChunkDataAndUpdateLight>>,
+) {
+    for pkt in pkts.read() {
+        let mut chunk = match Chunk::decode(pkt.chunk_x, pkt.chunk_z, &pkt.data, &blocks) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                warn!(
+                    "failed to decode chunk {}, {}: {e:#}",
+                    pkt.chunk_x, pkt.chunk_z
+                );
+                continue;
+            }
+        };
+
+        let light = LightData {
+            sky_mask: &pkt.sky_light_mask,
+            block_mask: &pkt.block_light_mask,
+            empty_sky_mask: &pkt.empty_sky_light_mask,
+            empty_block_mask: &pkt.empty_block_light_mask,
+            sky: &pkt.sky_lights,
+            block: &pkt.block_lights,
+        };
+        if let Err(e) = chunk.light.update(&light) {
+            warn!(
+                "failed to decode light of chunk {}, {}: {e:#}",
+                pkt.chunk_x, pkt.chunk_z
+            );
+        }
+        chunks.insert(chunk);
     }
 }
 
-fn handle_chunk_data_and_update_light(mut pkts: EventReader<PacketEvent>) {
+fn handle_update_light(mut chunks: ResMut<ChunkMap>, mut pkts: EventReader<Typed<UpdateLight>>) {
     for pkt in pkts.read() {
-        if let Some(_pkt) = pkt.decode::<ChunkDataAndUpdateLight>() {
-            //println!("{:?}", pkt.data);
+        let pos = IVec2::new(pkt.chunk_x.0, pkt.chunk_z.0);
+        let light = LightData {
+            sky_mask: &pkt.sky_light_mask,
+            block_mask: &pkt.block_light_mask,
+            empty_sky_mask: &pkt.empty_sky_light_mask,
+            empty_block_mask: &pkt.empty_block_light_mask,
+            sky: &pkt.sky_lights,
+            block: &pkt.block_lights,
+        };
+        if let Err(e) = chunks.update_light(pos, &light) {
+            warn!(
+                "failed to update light of chunk {}, {}: {e:#}",
+                pos.x, pos.y
+            );
         }
     }
 }
+
+fn handle_unload_chunk(mut chunks: ResMut<ChunkMap>, mut pkts: EventReader<Typed<UnloadChunk>>) {
+    for pkt in pkts.read() {
+        chunks.remove(IVec2::new(pkt.chunk_x, pkt.chunk_z));
+    }
+}
+
+fn handle_block_update(
+    mut chunks: ResMut<ChunkMap>,
+    blocks: Res<BlockStateRegistry>,
+    mut pkts: EventReader<Typed<BlockUpdate>>,
+) {
+    for pkt in pkts.read() {
+        let pos = IVec3::new(pkt.location.x, pkt.location.y, pkt.location.z);
+        if !blocks.contains(pkt.block_id.0) {
+            warn!("unknown block state {} at {pos}", pkt.block_id.0);
+            continue;
+        }
+
+        chunks.set_block(pos, pkt.block_id.0, &blocks);
+    }
+}